@@ -0,0 +1,180 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Invitations to join a tenant.
+//!
+//! An invitation is sent to an email address with the role the invitee will
+//! get. Only a hash of its token is stored, the token itself goes in the link
+//! that is sent and is spent the first time it is accepted or declined.
+
+use std::fmt;
+
+use argon2::password_hash::rand_core::{OsRng, RngCore};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use super::role_core::Role;
+
+/// An invitation to join a tenant with a preset role.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+pub struct Invitation {
+    pub invitation_id: Uuid,
+    pub tenant_id: Uuid,
+    pub email: String,
+    pub role: Role,
+    #[serde(skip)]
+    pub token_hash: String,
+    pub invited_by: Option<Uuid>,
+    pub status: InvitationStatus,
+    pub created_at: DateTime<Utc>,
+    pub expires_at: DateTime<Utc>,
+    pub responded_at: Option<DateTime<Utc>>,
+    /// The user who accepted.
+    pub accepted_by: Option<Uuid>,
+}
+
+impl Invitation {
+    /// Still waiting for an answer and not yet expired.
+    pub fn is_open(&self, now: DateTime<Utc>) -> bool {
+        self.status == InvitationStatus::Pending && now < self.expires_at
+    }
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum InvitationStatus {
+    #[default]
+    Pending,
+    Accepted,
+    Declined,
+    Revoked,
+}
+
+impl InvitationStatus {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            InvitationStatus::Pending => "pending",
+            InvitationStatus::Accepted => "accepted",
+            InvitationStatus::Declined => "declined",
+            InvitationStatus::Revoked => "revoked",
+        }
+    }
+}
+
+impl From<&str> for InvitationStatus {
+    /// Unknown names are read as revoked so they can never be used.
+    fn from(name: &str) -> InvitationStatus {
+        match name {
+            "pending" => InvitationStatus::Pending,
+            "accepted" => InvitationStatus::Accepted,
+            "declined" => InvitationStatus::Declined,
+            _ => InvitationStatus::Revoked,
+        }
+    }
+}
+
+impl From<String> for InvitationStatus {
+    fn from(name: String) -> InvitationStatus {
+        InvitationStatus::from(name.as_str())
+    }
+}
+
+impl fmt::Display for InvitationStatus {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// A new random token, 32 bytes as hex.
+pub fn new_token() -> String {
+    let mut bytes = [0u8; 32];
+    OsRng.fill_bytes(&mut bytes);
+    to_hex(&bytes)
+}
+
+/// What is stored in place of the token.
+pub fn hash_token(token: &str) -> String {
+    to_hex(&Sha256::digest(token.as_bytes()))
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Duration;
+
+    use super::*;
+
+    #[test]
+    fn tokens_are_random_and_hashed() {
+        let token = new_token();
+        assert_eq!(token.len(), 64);
+        assert_ne!(token, new_token());
+        assert_eq!(hash_token(&token), hash_token(&token));
+        assert_ne!(hash_token(&token), token);
+        assert_eq!(
+            hash_token("abc"),
+            "ba7816bf8f01cfea414140de5dae2223b00361a396177a9cb410ff61f20015ad"
+        );
+    }
+
+    #[test]
+    fn open_until_answered_or_expired() {
+        let now = Utc::now();
+        let mut i = Invitation {
+            invitation_id: Uuid::new_v4(),
+            tenant_id: Uuid::new_v4(),
+            email: "dave@example.com".to_string(),
+            role: Role::Member,
+            token_hash: hash_token("t"),
+            invited_by: None,
+            status: InvitationStatus::Pending,
+            created_at: now,
+            expires_at: now + Duration::hours(1),
+            responded_at: None,
+            accepted_by: None,
+        };
+        assert!(i.is_open(now));
+        assert!(!i.is_open(now + Duration::hours(2)));
+        i.status = InvitationStatus::Declined;
+        assert!(!i.is_open(now));
+    }
+
+    #[test]
+    fn status_names() {
+        for s in [
+            InvitationStatus::Pending,
+            InvitationStatus::Accepted,
+            InvitationStatus::Declined,
+            InvitationStatus::Revoked,
+        ] {
+            assert_eq!(InvitationStatus::from(s.as_str()), s);
+        }
+        assert_eq!(InvitationStatus::from("junk"), InvitationStatus::Revoked);
+    }
+}
@@ -22,4 +22,11 @@
 # SOFTWARE.
 */
 
+pub mod invitation_core;
+pub mod page_core;
+pub mod password_core;
+pub mod role_core;
+pub mod tenant_core;
 pub mod user_core;
+pub mod user_tenant_core;
+pub mod validation_core;
@@ -0,0 +1,120 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use serde::Serialize;
+
+/// One page of a listing.
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Rows matching the filters across all pages.
+    pub total: i64,
+    /// Pass back as `after` to get the next page, `None` on the last page.
+    pub next_cursor: Option<String>,
+}
+
+impl<T> Page<T> {
+    /// `items` is fetched with `limit + 1` so an extra row shows there is a
+    /// next page, it is dropped and the cursor made from the last item kept.
+    pub fn new(
+        mut items: Vec<T>,
+        limit: u32,
+        total: i64,
+        cursor: impl Fn(&T) -> String,
+    ) -> Page<T> {
+        let limit = limit as usize;
+        let next_cursor = if items.len() > limit {
+            items.truncate(limit);
+            items.last().map(cursor)
+        } else {
+            None
+        };
+        Page {
+            items,
+            total,
+            next_cursor,
+        }
+    }
+}
+
+/// Turns a search string into a `LIKE` pattern matching it anywhere, `%`, `_`
+/// and `\` are escaped so the query needs `ESCAPE '\'`.
+pub fn like_pattern(search: &str) -> String {
+    let mut pattern = String::with_capacity(search.len() + 2);
+    pattern.push('%');
+    for c in search.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            pattern.push('\\');
+        }
+        pattern.push(c);
+    }
+    pattern.push('%');
+    pattern
+}
+
+/// Cursors are hex so they can go in a URL without escaping.
+pub fn hex_encode(s: &str) -> String {
+    s.bytes().map(|b| format!("{:02x}", b)).collect()
+}
+
+pub fn hex_decode(s: &str) -> Option<String> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    let bytes = (0..s.len())
+        .step_by(2)
+        .map(|i| s.get(i..i + 2).and_then(|h| u8::from_str_radix(h, 16).ok()))
+        .collect::<Option<Vec<u8>>>()?;
+    String::from_utf8(bytes).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn page_has_cursor_only_with_more_rows() {
+        let page = Page::new(vec![1, 2, 3], 2, 3, |i| i.to_string());
+        assert_eq!(page.items, vec![1, 2]);
+        assert_eq!(page.next_cursor.as_deref(), Some("2"));
+        let page = Page::new(vec![1, 2], 2, 2, |i| i.to_string());
+        assert_eq!(page.items, vec![1, 2]);
+        assert!(page.next_cursor.is_none());
+    }
+
+    #[test]
+    fn like_pattern_escapes_wildcards() {
+        assert_eq!(like_pattern("dave"), "%dave%");
+        assert_eq!(like_pattern("50%_a\\b"), "%50\\%\\_a\\\\b%");
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        assert_eq!(hex_encode("Dave W"), "446176652057");
+        assert_eq!(hex_decode("446176652057").as_deref(), Some("Dave W"));
+        assert_eq!(hex_decode(&hex_encode("Zoë")).as_deref(), Some("Zoë"));
+        assert_eq!(hex_decode("4"), None);
+        assert_eq!(hex_decode("zz"), None);
+    }
+}
@@ -0,0 +1,142 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use argon2::{
+    password_hash::{
+        self, rand_core::OsRng, PasswordHash, PasswordHasher, PasswordVerifier, SaltString,
+    },
+    Algorithm, Argon2, Params, Version,
+};
+use serde::{Deserialize, Serialize};
+use uuid::Uuid;
+
+/// Argon2id cost parameters used when hashing passwords.
+///
+/// The defaults follow the OWASP recommendation for Argon2id: 19 MiB of
+/// memory, 2 iterations and a parallelism of 1. Existing hashes carry their
+/// own parameters so they can still be verified after these are changed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordHashParams {
+    pub memory_kib: u32,
+    pub iterations: u32,
+    pub parallelism: u32,
+}
+
+impl Default for PasswordHashParams {
+    fn default() -> PasswordHashParams {
+        PasswordHashParams {
+            memory_kib: 19 * 1024,
+            iterations: 2,
+            parallelism: 1,
+        }
+    }
+}
+
+impl PasswordHashParams {
+    /// Fails when argon2 won't accept the parameters.
+    pub fn check(&self) -> Result<(), password_hash::Error> {
+        self.argon2().map(|_| ())
+    }
+
+    fn argon2(&self) -> Result<Argon2<'static>, password_hash::Error> {
+        let params = Params::new(self.memory_kib, self.iterations, self.parallelism, None)?;
+        Ok(Argon2::new(Algorithm::Argon2id, Version::V0x13, params))
+    }
+}
+
+/// What is required of new passwords and how they are hashed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct PasswordPolicy {
+    /// Minimum length in characters.
+    pub min_length: usize,
+    pub hash: PasswordHashParams,
+}
+
+impl Default for PasswordPolicy {
+    fn default() -> PasswordPolicy {
+        PasswordPolicy {
+            min_length: 8,
+            hash: PasswordHashParams::default(),
+        }
+    }
+}
+
+impl PasswordPolicy {
+    pub fn is_acceptable(&self, password: &str) -> bool {
+        password.chars().count() >= self.min_length
+    }
+
+    /// The message for the password field when it isn't acceptable.
+    pub fn check(&self, password: &str) -> Result<(), String> {
+        if self.is_acceptable(password) {
+            Ok(())
+        } else {
+            Err(format!("must be at least {} characters", self.min_length))
+        }
+    }
+}
+
+/// Outcome of checking a user name and password.
+///
+/// An unknown user name and a wrong password both give `Rejected` so callers
+/// cannot tell them apart.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PasswordVerification {
+    Verified(Uuid),
+    Rejected,
+}
+
+/// Hash a password into a PHC string (`$argon2id$...`) with a random salt.
+pub fn hash_password(
+    password: &str,
+    params: &PasswordHashParams,
+) -> Result<String, password_hash::Error> {
+    let salt = SaltString::generate(&mut OsRng);
+    let hash = params.argon2()?.hash_password(password.as_bytes(), &salt)?;
+    Ok(hash.to_string())
+}
+
+/// Check a password against a stored PHC hash.
+///
+/// When there is no usable stored hash (unknown user, or a user whose
+/// password was never set) a throwaway hash is computed with `params` so the
+/// call costs about the same either way. The final comparison inside argon2
+/// is constant time.
+pub fn verify_password_hash(
+    password: &str,
+    stored_hash: Option<&str>,
+    params: &PasswordHashParams,
+) -> bool {
+    match stored_hash.and_then(|h| PasswordHash::new(h).ok()) {
+        Some(parsed) => Argon2::default()
+            .verify_password(password.as_bytes(), &parsed)
+            .is_ok(),
+        None => {
+            let _ = hash_password(password, params);
+            false
+        }
+    }
+}
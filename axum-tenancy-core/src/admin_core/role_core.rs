@@ -0,0 +1,285 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Per tenant roles and the permissions they grant.
+//!
+//! A [`UserTenant`](super::user_tenant_core::UserTenant) carries the user's
+//! [`Role`] in that tenant. [`RolePermissions`] maps role names to grants,
+//! permissions are `resource:action` strings chosen by the application, e.g.
+//! `invoices:write`. A grant is a permission or a pattern:
+//!
+//! - `*` grants everything
+//! - `invoices:*` grants every action on invoices
+//! - `*:read` grants reading everything
+
+use std::{collections::BTreeMap, fmt};
+
+use serde::{Deserialize, Serialize};
+
+/// A user's role in one tenant, stored as its name.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum Role {
+    Owner,
+    Admin,
+    #[default]
+    Member,
+    Viewer,
+    /// Any other name, given permissions in [`RolePermissions`].
+    Custom(String),
+}
+
+impl Role {
+    pub fn as_str(&self) -> &str {
+        match self {
+            Role::Owner => "owner",
+            Role::Admin => "admin",
+            Role::Member => "member",
+            Role::Viewer => "viewer",
+            Role::Custom(name) => name,
+        }
+    }
+}
+
+impl From<&str> for Role {
+    fn from(name: &str) -> Role {
+        match name {
+            "owner" => Role::Owner,
+            "admin" => Role::Admin,
+            "member" => Role::Member,
+            "viewer" => Role::Viewer,
+            name => Role::Custom(name.to_string()),
+        }
+    }
+}
+
+impl From<String> for Role {
+    fn from(name: String) -> Role {
+        Role::from(name.as_str())
+    }
+}
+
+impl From<Role> for String {
+    fn from(role: Role) -> String {
+        role.as_str().to_string()
+    }
+}
+
+impl fmt::Display for Role {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// The grants for each role by name. Starts with the built-in roles:
+///
+/// | role | grants |
+/// |---|---|
+/// | owner | `*` |
+/// | admin | `*:read`, `*:write`, `members:*` |
+/// | member | `*:read`, `*:write` |
+/// | viewer | `*:read` |
+///
+/// Roles given when deserializing or with [`RolePermissions::role`] are
+/// added to these or replace them. Roles with no entry have no permissions.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(from = "BTreeMap<String, Vec<String>>")]
+pub struct RolePermissions {
+    roles: BTreeMap<String, Vec<String>>,
+}
+
+impl Default for RolePermissions {
+    fn default() -> RolePermissions {
+        let grants = |g: &[&str]| g.iter().map(|s| s.to_string()).collect();
+        RolePermissions {
+            roles: BTreeMap::from([
+                ("owner".to_string(), grants(&["*"])),
+                (
+                    "admin".to_string(),
+                    grants(&["*:read", "*:write", "members:*"]),
+                ),
+                ("member".to_string(), grants(&["*:read", "*:write"])),
+                ("viewer".to_string(), grants(&["*:read"])),
+            ]),
+        }
+    }
+}
+
+impl From<BTreeMap<String, Vec<String>>> for RolePermissions {
+    fn from(roles: BTreeMap<String, Vec<String>>) -> RolePermissions {
+        let mut permissions = RolePermissions::default();
+        permissions.roles.extend(roles);
+        permissions
+    }
+}
+
+impl RolePermissions {
+    pub fn new() -> RolePermissions {
+        RolePermissions::default()
+    }
+
+    /// Sets the grants for `role`, replacing any it had.
+    pub fn role(mut self, role: impl Into<Role>, grants: &[&str]) -> RolePermissions {
+        self.roles.insert(
+            role.into().as_str().to_string(),
+            grants.iter().map(|s| s.to_string()).collect(),
+        );
+        self
+    }
+
+    pub fn grants(&self, role: &Role) -> &[String] {
+        self.roles
+            .get(role.as_str())
+            .map(Vec::as_slice)
+            .unwrap_or_default()
+    }
+
+    pub fn allows(&self, role: &Role, permission: &str) -> bool {
+        self.grants(role)
+            .iter()
+            .any(|grant| grant_matches(grant, permission))
+    }
+
+    /// Whether `granter` has every grant of `role`, so giving someone `role`
+    /// doesn't hand out more than the granter has.
+    pub fn can_grant(&self, granter: &Role, role: &Role) -> bool {
+        self.grants(role)
+            .iter()
+            .all(|grant| self.allows(granter, grant))
+    }
+
+    /// The names of the roles with grants.
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.roles.keys().map(String::as_str)
+    }
+
+    /// Role names and grants that can't be used, as `(role, message)`.
+    pub fn check(&self) -> Result<(), (String, String)> {
+        for (role, grants) in &self.roles {
+            if role.trim().is_empty() {
+                return Err((role.clone(), "role names must not be empty".to_string()));
+            }
+            if let Some(grant) = grants.iter().find(|g| !is_grant(g)) {
+                return Err((
+                    role.clone(),
+                    format!("{} is not *, resource:action or a pattern of one", grant),
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+fn is_grant(grant: &str) -> bool {
+    grant == "*"
+        || matches!(grant.split_once(':'), Some((resource, action))
+            if !resource.is_empty() && !action.is_empty() && !action.contains(':'))
+}
+
+fn grant_matches(grant: &str, permission: &str) -> bool {
+    if grant == "*" || grant == permission {
+        return true;
+    }
+    match (grant.split_once(':'), permission.split_once(':')) {
+        (Some((g_resource, g_action)), Some((resource, action))) => {
+            (g_resource == "*" || g_resource == resource) && (g_action == "*" || g_action == action)
+        }
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn role_names() {
+        for role in [Role::Owner, Role::Admin, Role::Member, Role::Viewer] {
+            assert_eq!(Role::from(role.as_str()), role);
+        }
+        assert_eq!(
+            Role::from("accountant"),
+            Role::Custom("accountant".to_string())
+        );
+        assert_eq!(Role::default(), Role::Member);
+        assert_eq!(String::from(Role::Viewer), "viewer");
+    }
+
+    #[test]
+    fn patterns() {
+        assert!(grant_matches("*", "invoices:write"));
+        assert!(grant_matches("invoices:write", "invoices:write"));
+        assert!(grant_matches("invoices:*", "invoices:write"));
+        assert!(grant_matches("*:write", "invoices:write"));
+        assert!(!grant_matches("invoices:read", "invoices:write"));
+        assert!(!grant_matches("*:read", "invoices:write"));
+        assert!(!grant_matches("orders:*", "invoices:write"));
+        assert!(!grant_matches("invoices", "invoices:write"));
+    }
+
+    #[test]
+    fn built_in_roles() {
+        let p = RolePermissions::default();
+        assert!(p.allows(&Role::Owner, "tenant:delete"));
+        assert!(!p.allows(&Role::Admin, "tenant:delete"));
+        assert!(p.allows(&Role::Admin, "members:invite"));
+        assert!(!p.allows(&Role::Member, "members:invite"));
+        assert!(p.allows(&Role::Member, "invoices:write"));
+        assert!(p.allows(&Role::Viewer, "invoices:read"));
+        assert!(!p.allows(&Role::Viewer, "invoices:write"));
+        assert!(!p.allows(&Role::from("accountant"), "invoices:read"));
+    }
+
+    #[test]
+    fn custom_roles() {
+        let p = RolePermissions::new()
+            .role("accountant", &["invoices:*", "*:read"])
+            .role(Role::Viewer, &["reports:read"]);
+        assert!(p.allows(&Role::from("accountant"), "invoices:write"));
+        assert!(!p.allows(&Role::from("accountant"), "orders:write"));
+        assert!(!p.allows(&Role::Viewer, "invoices:read"));
+        assert!(p.allows(&Role::Owner, "invoices:read"));
+        assert!(p.check().is_ok());
+        assert!(RolePermissions::new()
+            .role("x", &["invoices"])
+            .check()
+            .is_err());
+        assert!(RolePermissions::new().role(" ", &["*"]).check().is_err());
+    }
+
+    #[test]
+    fn granting_roles() {
+        let p = RolePermissions::new().role("accountant", &["invoices:*", "*:read"]);
+        assert!(p.can_grant(&Role::Owner, &Role::Owner));
+        assert!(p.can_grant(&Role::Admin, &Role::Admin));
+        assert!(p.can_grant(&Role::Admin, &Role::Viewer));
+        assert!(!p.can_grant(&Role::Admin, &Role::Owner));
+        assert!(!p.can_grant(&Role::Admin, &Role::from("accountant")));
+        assert!(!p.can_grant(&Role::Viewer, &Role::Member));
+        assert_eq!(
+            p.names().collect::<Vec<_>>(),
+            ["accountant", "admin", "member", "owner", "viewer"]
+        );
+    }
+}
@@ -22,33 +22,95 @@
 # SOFTWARE.
 */
 
+use std::fmt;
+
+use axum_login::AuthUser;
+use chrono::{DateTime, Utc};
 use serde::{Deserialize, Serialize};
+use sqlx::{Database, FromRow, QueryBuilder};
 use uuid::Uuid;
-use sqlx::FromRow;
 
-#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+use super::page_core::{hex_decode, hex_encode};
+use crate::error::TenancyError;
+
+#[derive(Serialize, Deserialize, Clone, FromRow)]
 pub struct User {
     pub user_id: Uuid,
     pub user_name: String,
+    /// Never serialized, it is only loaded so axum-login can invalidate
+    /// sessions when the password changes.
+    #[serde(skip)]
+    pub hash_password: String,
     pub display_name: String,
     pub is_admin: bool,
     pub email: String,
     pub mobile_phone: String,
+    pub created_at: DateTime<Utc>,
+    /// Changed by every edit, including passwords and deactivation.
+    pub updated_at: DateTime<Utc>,
+    /// `None` until the user first logs in.
+    pub last_login_at: Option<DateTime<Utc>>,
+    /// Set when the user has been deactivated, cleared by a restore.
+    pub deleted_at: Option<DateTime<Utc>>,
+    /// Bumped by every edit, an update must send back the version it was
+    /// made from.
+    pub version: i64,
 }
 
 impl Default for User {
     fn default() -> User {
+        let now = Utc::now();
         User {
             user_id: Uuid::new_v4(),
             user_name: "".to_string(),
+            hash_password: "".to_string(),
             display_name: "".to_string(),
             is_admin: true,
             email: "".to_string(),
             mobile_phone: "".to_string(),
+            created_at: now,
+            updated_at: now,
+            last_login_at: None,
+            deleted_at: None,
+            version: 1,
         }
     }
 }
 
+// hand written so the password hash stays out of logs
+impl fmt::Debug for User {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("User")
+            .field("user_id", &self.user_id)
+            .field("user_name", &self.user_name)
+            .field("hash_password", &"[redacted]")
+            .field("display_name", &self.display_name)
+            .field("is_admin", &self.is_admin)
+            .field("email", &self.email)
+            .field("mobile_phone", &self.mobile_phone)
+            .field("created_at", &self.created_at)
+            .field("updated_at", &self.updated_at)
+            .field("last_login_at", &self.last_login_at)
+            .field("deleted_at", &self.deleted_at)
+            .field("version", &self.version)
+            .finish()
+    }
+}
+
+impl AuthUser for User {
+    type Id = Uuid;
+
+    fn id(&self) -> Uuid {
+        self.user_id
+    }
+
+    fn session_auth_hash(&self) -> &[u8] {
+        self.hash_password.as_bytes()
+    }
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
 pub enum SortDirection {
     Asc,
     Desc,
@@ -61,11 +123,40 @@ impl SortDirection {
             SortDirection::Desc => "DESC",
         }
     }
+
+    pub fn reverse(&self) -> SortDirection {
+        match self {
+            SortDirection::Asc => SortDirection::Desc,
+            SortDirection::Desc => SortDirection::Asc,
+        }
+    }
+}
+
+/// Whether loads return users that have been deactivated.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Deleted {
+    Exclude,
+    Include,
+}
+
+impl Deleted {
+    pub fn included(&self) -> bool {
+        matches!(self, Deleted::Include)
+    }
 }
 
+/// The columns users can be sorted on. They are the only text that reaches
+/// an ORDER BY, so nothing from a request can end up in the SQL.
+/// Serialized as its column name.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
 pub enum UserSort {
     UserName,
     DisplayName,
+    Email,
+    CreatedAt,
+    UpdatedAt,
+    LastLoginAt,
 }
 
 impl UserSort {
@@ -73,6 +164,493 @@ impl UserSort {
         match self {
             UserSort::UserName => "user_name",
             UserSort::DisplayName => "display_name",
+            UserSort::Email => "email",
+            UserSort::CreatedAt => "created_at",
+            UserSort::UpdatedAt => "updated_at",
+            UserSort::LastLoginAt => "last_login_at",
+        }
+    }
+
+    /// Nulls count as smaller than every value, so they come first when
+    /// ascending and last when descending.
+    pub fn is_nullable(&self) -> bool {
+        matches!(self, UserSort::LastLoginAt)
+    }
+
+    /// Unique columns fix the order on their own, so nothing after them is
+    /// needed.
+    pub fn is_unique(&self) -> bool {
+        matches!(self, UserSort::UserName | UserSort::DisplayName)
+    }
+}
+
+/// A column in a built ORDER BY: one of the sort columns or the user_id
+/// added to break ties.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum UserOrderKey {
+    Sort(UserSort),
+    UserId,
+}
+
+impl UserOrderKey {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserOrderKey::Sort(sort) => sort.as_str(),
+            UserOrderKey::UserId => "user_id",
+        }
+    }
+
+    pub fn value(&self, u: &User) -> UserKeyValue {
+        match self {
+            UserOrderKey::Sort(UserSort::UserName) => UserKeyValue::Text(u.user_name.clone()),
+            UserOrderKey::Sort(UserSort::DisplayName) => UserKeyValue::Text(u.display_name.clone()),
+            UserOrderKey::Sort(UserSort::Email) => UserKeyValue::Text(u.email.clone()),
+            UserOrderKey::Sort(UserSort::CreatedAt) => UserKeyValue::Time(u.created_at),
+            UserOrderKey::Sort(UserSort::UpdatedAt) => UserKeyValue::Time(u.updated_at),
+            UserOrderKey::Sort(UserSort::LastLoginAt) => match u.last_login_at {
+                Some(t) => UserKeyValue::Time(t),
+                None => UserKeyValue::Null,
+            },
+            UserOrderKey::UserId => UserKeyValue::Id(u.user_id),
+        }
+    }
+
+    fn is_nullable(&self) -> bool {
+        matches!(self, UserOrderKey::Sort(sort) if sort.is_nullable())
+    }
+
+    fn parse(&self, s: &str) -> Option<UserKeyValue> {
+        match self {
+            UserOrderKey::Sort(UserSort::LastLoginAt) if s.is_empty() => Some(UserKeyValue::Null),
+            UserOrderKey::Sort(
+                UserSort::CreatedAt | UserSort::UpdatedAt | UserSort::LastLoginAt,
+            ) => DateTime::parse_from_rfc3339(s)
+                .ok()
+                .map(|t| UserKeyValue::Time(t.with_timezone(&Utc))),
+            UserOrderKey::Sort(_) => Some(UserKeyValue::Text(s.to_string())),
+            UserOrderKey::UserId => Uuid::try_parse(s).ok().map(UserKeyValue::Id),
+        }
+    }
+}
+
+/// The value of an order key, typed so backends can bind it.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum UserKeyValue {
+    Text(String),
+    Time(DateTime<Utc>),
+    Id(Uuid),
+    Null,
+}
+
+impl fmt::Display for UserKeyValue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UserKeyValue::Text(s) => f.write_str(s),
+            UserKeyValue::Time(t) => f.write_str(&t.to_rfc3339()),
+            UserKeyValue::Id(id) => write!(f, "{}", id),
+            UserKeyValue::Null => Ok(()),
+        }
+    }
+}
+
+/// The sort for a user listing, one or more columns each with a direction.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserOrder {
+    columns: Vec<(UserSort, SortDirection)>,
+}
+
+impl Default for UserOrder {
+    fn default() -> UserOrder {
+        UserOrder::new(UserSort::UserName, SortDirection::Asc)
+    }
+}
+
+impl From<(UserSort, SortDirection)> for UserOrder {
+    fn from((sort, direction): (UserSort, SortDirection)) -> UserOrder {
+        UserOrder::new(sort, direction)
+    }
+}
+
+impl UserOrder {
+    pub fn new(sort: UserSort, direction: SortDirection) -> UserOrder {
+        UserOrder {
+            columns: vec![(sort, direction)],
+        }
+    }
+
+    /// Adds a column to sort on when the earlier ones are equal, a column
+    /// already in the order is ignored.
+    pub fn then(mut self, sort: UserSort, direction: SortDirection) -> UserOrder {
+        if !self.columns.iter().any(|(s, _)| *s == sort) {
+            self.columns.push((sort, direction));
         }
+        self
+    }
+
+    pub fn columns(&self) -> &[(UserSort, SortDirection)] {
+        &self.columns
+    }
+
+    /// The columns the ORDER BY really needs: up to the first unique one, or
+    /// all of them plus user_id so rows never tie.
+    pub fn keys(&self) -> Vec<(UserOrderKey, SortDirection)> {
+        let mut keys = Vec::new();
+        for (sort, direction) in &self.columns {
+            keys.push((UserOrderKey::Sort(*sort), *direction));
+            if sort.is_unique() {
+                return keys;
+            }
+        }
+        let (_, last) = self.columns[self.columns.len() - 1];
+        keys.push((UserOrderKey::UserId, last));
+        keys
+    }
+
+    /// The ORDER BY list, built only from the whitelisted names.
+    pub fn to_sql(&self) -> String {
+        self.keys()
+            .iter()
+            .map(|(key, direction)| {
+                let nulls = match (key.is_nullable(), direction) {
+                    (false, _) => "",
+                    (true, SortDirection::Asc) => " NULLS FIRST",
+                    (true, SortDirection::Desc) => " NULLS LAST",
+                };
+                format!("{} {}{}", key.as_str(), direction.as_str(), nulls)
+            })
+            .collect::<Vec<_>>()
+            .join(", ")
+    }
+
+    // names the order in a cursor, eg "email~asc,user_id~asc"
+    fn signature(&self) -> String {
+        self.keys()
+            .iter()
+            .map(|(key, direction)| {
+                format!("{}~{}", key.as_str(), direction.as_str().to_lowercase())
+            })
+            .collect::<Vec<_>>()
+            .join(",")
+    }
+}
+
+/// Where a keyset page starts: the order key values of the last user on the
+/// previous page.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct UserCursor {
+    pub values: Vec<UserKeyValue>,
+}
+
+impl UserCursor {
+    pub fn after(order: &UserOrder, u: &User) -> UserCursor {
+        UserCursor {
+            values: order.keys().iter().map(|(key, _)| key.value(u)).collect(),
+        }
+    }
+
+    /// The order is part of the cursor so it can't be reused with a
+    /// different sort.
+    pub fn encode(&self, order: &UserOrder) -> String {
+        let mut cursor = order.signature();
+        for value in &self.values {
+            cursor.push('.');
+            cursor.push_str(&hex_encode(&value.to_string()));
+        }
+        cursor
+    }
+
+    pub fn decode(cursor: &str, order: &UserOrder) -> Result<UserCursor, TenancyError> {
+        let invalid = || TenancyError::Validation("invalid cursor".to_string());
+        let mut parts = cursor.split('.');
+        if parts.next() != Some(order.signature().as_str()) {
+            return Err(invalid());
+        }
+        let keys = order.keys();
+        let values = parts
+            .map(|part| hex_decode(part).ok_or_else(invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+        if values.len() != keys.len() {
+            return Err(invalid());
+        }
+        let values = keys
+            .iter()
+            .zip(values)
+            .map(|((key, _), value)| key.parse(&value).ok_or_else(invalid))
+            .collect::<Result<Vec<_>, _>>()?;
+        Ok(UserCursor { values })
+    }
+
+    /// Pushes the condition for rows after the cursor in `order`.
+    ///
+    /// When every key sorts the same way this is a row comparison, eg
+    /// `(email, user_id) > (?, ?)`, which the database can answer by seeking
+    /// in an index. Mixed directions can't use an index for the order anyway
+    /// and nulls don't compare, so those get
+    /// `(email > ?) OR (email = ? AND created_at < ?) ...`.
+    /// `push_value` binds a value the way the backend needs.
+    pub fn push_after<'a, DB: Database>(
+        &self,
+        qb: &mut QueryBuilder<'a, DB>,
+        order: &UserOrder,
+        push_value: impl Fn(&mut QueryBuilder<'a, DB>, &UserKeyValue),
+    ) {
+        let keys = order.keys();
+        let (_, first) = keys[0];
+        let nullable = keys.iter().any(|(key, _)| key.is_nullable());
+        if !nullable && keys.iter().all(|(_, direction)| *direction == first) {
+            let columns: Vec<&str> = keys.iter().map(|(key, _)| key.as_str()).collect();
+            qb.push("(").push(columns.join(", ")).push(match first {
+                SortDirection::Asc => ") > (",
+                SortDirection::Desc => ") < (",
+            });
+            for (i, value) in self.values.iter().enumerate() {
+                if i > 0 {
+                    qb.push(", ");
+                }
+                push_value(qb, value);
+            }
+            qb.push(")");
+            return;
+        }
+        qb.push("(");
+        for (i, (key, direction)) in keys.iter().enumerate() {
+            if i > 0 {
+                qb.push(" OR ");
+            }
+            qb.push("(");
+            for ((equal, _), value) in keys.iter().zip(&self.values).take(i) {
+                if *value == UserKeyValue::Null {
+                    qb.push(equal.as_str()).push(" IS NULL AND ");
+                } else {
+                    qb.push(equal.as_str()).push(" = ");
+                    push_value(qb, value);
+                    qb.push(" AND ");
+                }
+            }
+            // nulls are the smallest value
+            let value = &self.values[i];
+            match (direction, value) {
+                (SortDirection::Asc, UserKeyValue::Null) => {
+                    qb.push(key.as_str()).push(" IS NOT NULL");
+                }
+                (SortDirection::Desc, UserKeyValue::Null) => {
+                    qb.push("FALSE");
+                }
+                (SortDirection::Asc, _) => {
+                    qb.push(key.as_str()).push(" > ");
+                    push_value(qb, value);
+                }
+                (SortDirection::Desc, _) if key.is_nullable() => {
+                    qb.push("(").push(key.as_str()).push(" < ");
+                    push_value(qb, value);
+                    qb.push(" OR ").push(key.as_str()).push(" IS NULL)");
+                }
+                (SortDirection::Desc, _) => {
+                    qb.push(key.as_str()).push(" < ");
+                    push_value(qb, value);
+                }
+            }
+            qb.push(")");
+        }
+        qb.push(")");
+    }
+}
+
+/// Filters, sort and paging for listing users.
+///
+/// With `after` set the page starts after that cursor (keyset pagination),
+/// otherwise `offset` rows are skipped. `search` matches anywhere in the
+/// user name, display name or email, ignoring case.
+#[derive(Clone, Debug)]
+pub struct UserQuery {
+    pub search: Option<String>,
+    pub is_admin: Option<bool>,
+    pub deleted: Deleted,
+    pub order: UserOrder,
+    pub limit: u32,
+    pub offset: u32,
+    pub after: Option<String>,
+}
+
+impl Default for UserQuery {
+    fn default() -> UserQuery {
+        UserQuery {
+            search: None,
+            is_admin: None,
+            deleted: Deleted::Exclude,
+            order: UserOrder::default(),
+            limit: 50,
+            offset: 0,
+            after: None,
+        }
+    }
+}
+
+impl UserQuery {
+    pub fn new() -> UserQuery {
+        UserQuery::default()
+    }
+
+    pub fn search(mut self, search: &str) -> UserQuery {
+        self.search = Some(search.to_string());
+        self
+    }
+
+    pub fn is_admin(mut self, is_admin: bool) -> UserQuery {
+        self.is_admin = Some(is_admin);
+        self
+    }
+
+    pub fn deleted(mut self, deleted: Deleted) -> UserQuery {
+        self.deleted = deleted;
+        self
+    }
+
+    pub fn sort(mut self, sort: UserSort, direction: SortDirection) -> UserQuery {
+        self.order = UserOrder::new(sort, direction);
+        self
+    }
+
+    pub fn order(mut self, order: UserOrder) -> UserQuery {
+        self.order = order;
+        self
+    }
+
+    pub fn limit(mut self, limit: u32) -> UserQuery {
+        self.limit = limit;
+        self
+    }
+
+    pub fn offset(mut self, offset: u32) -> UserQuery {
+        self.offset = offset;
+        self
+    }
+
+    pub fn after(mut self, cursor: &str) -> UserQuery {
+        self.after = Some(cursor.to_string());
+        self
+    }
+
+    /// Checks the limit and decodes `after`.
+    pub fn cursor(&self) -> Result<Option<UserCursor>, TenancyError> {
+        if self.limit == 0 {
+            return Err(TenancyError::Validation(
+                "limit must be at least 1".to_string(),
+            ));
+        }
+        self.after
+            .as_deref()
+            .map(|c| UserCursor::decode(c, &self.order))
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn order_stops_at_unique_column() {
+        let order = UserOrder::new(UserSort::DisplayName, SortDirection::Desc)
+            .then(UserSort::CreatedAt, SortDirection::Asc);
+        assert_eq!(order.to_sql(), "display_name DESC");
+
+        let order = UserOrder::new(UserSort::Email, SortDirection::Asc)
+            .then(UserSort::CreatedAt, SortDirection::Desc)
+            .then(UserSort::Email, SortDirection::Desc);
+        assert_eq!(order.columns().len(), 2);
+        assert_eq!(order.to_sql(), "email ASC, created_at DESC, user_id DESC");
+    }
+
+    #[test]
+    fn cursor_round_trip() {
+        let u = User {
+            email: "dwarnock@test.com".to_string(),
+            ..Default::default()
+        };
+        let order = UserOrder::new(UserSort::Email, SortDirection::Asc)
+            .then(UserSort::CreatedAt, SortDirection::Asc);
+        let cursor = UserCursor::after(&order, &u);
+        assert_eq!(cursor.values.len(), 3);
+        let encoded = cursor.encode(&order);
+        assert_eq!(UserCursor::decode(&encoded, &order).unwrap(), cursor);
+
+        let other = UserOrder::new(UserSort::Email, SortDirection::Desc);
+        assert!(matches!(
+            UserCursor::decode(&encoded, &other),
+            Err(TenancyError::Validation(_))
+        ));
+        assert!(UserCursor::decode("rubbish", &order).is_err());
+    }
+
+    #[test]
+    fn push_after_row_or_expanded() {
+        let order = UserOrder::new(UserSort::Email, SortDirection::Desc);
+        let cursor = UserCursor::after(&order, &User::default());
+        let mut qb = QueryBuilder::<sqlx::Sqlite>::new("");
+        cursor.push_after(&mut qb, &order, |qb, value| {
+            qb.push_bind(value.to_string());
+        });
+        assert_eq!(qb.sql(), "(email, user_id) < (?, ?)");
+
+        let order = UserOrder::new(UserSort::Email, SortDirection::Asc)
+            .then(UserSort::CreatedAt, SortDirection::Desc);
+        let cursor = UserCursor::after(&order, &User::default());
+        let mut qb = QueryBuilder::<sqlx::Sqlite>::new("");
+        cursor.push_after(&mut qb, &order, |qb, value| {
+            qb.push_bind(value.to_string());
+        });
+        assert_eq!(
+            qb.sql(),
+            "((email > ?) OR (email = ? AND created_at < ?) \
+             OR (email = ? AND created_at = ? AND user_id < ?))"
+        );
+    }
+
+    #[test]
+    fn nulls_are_the_smallest_value() {
+        let order = UserOrder::new(UserSort::LastLoginAt, SortDirection::Desc);
+        assert_eq!(
+            order.to_sql(),
+            "last_login_at DESC NULLS LAST, user_id DESC"
+        );
+
+        // never logged in, only the rest of the nulls come after
+        let cursor = UserCursor::after(&order, &User::default());
+        assert_eq!(cursor.values[0], UserKeyValue::Null);
+        let encoded = cursor.encode(&order);
+        assert_eq!(UserCursor::decode(&encoded, &order).unwrap(), cursor);
+        let mut qb = QueryBuilder::<sqlx::Sqlite>::new("");
+        cursor.push_after(&mut qb, &order, |qb, value| {
+            qb.push_bind(value.to_string());
+        });
+        assert_eq!(
+            qb.sql(),
+            "((FALSE) OR (last_login_at IS NULL AND user_id < ?))"
+        );
+
+        let u = User {
+            last_login_at: Some(Utc::now()),
+            ..Default::default()
+        };
+        let cursor = UserCursor::after(&order, &u);
+        let mut qb = QueryBuilder::<sqlx::Sqlite>::new("");
+        cursor.push_after(&mut qb, &order, |qb, value| {
+            qb.push_bind(value.to_string());
+        });
+        assert_eq!(
+            qb.sql(),
+            "(((last_login_at < ? OR last_login_at IS NULL)) \
+             OR (last_login_at = ? AND user_id < ?))"
+        );
+    }
+
+    #[test]
+    fn query_rejects_zero_limit() {
+        assert!(UserQuery::new().cursor().unwrap().is_none());
+        assert!(matches!(
+            UserQuery::new().limit(0).cursor(),
+            Err(TenancyError::Validation(_))
+        ));
     }
 }
@@ -0,0 +1,65 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+use super::role_core::Role;
+
+/// Gives a User access to a Tenant.
+#[derive(Serialize, Deserialize, Debug, Clone, FromRow)]
+pub struct UserTenant {
+    pub user_tenant_id: Uuid,
+    pub user_id: Uuid,
+    pub tenant_id: Uuid,
+    /// What the user may do in the tenant, see [`RolePermissions`](super::role_core::RolePermissions).
+    #[sqlx(try_from = "String")]
+    pub role: Role,
+}
+
+impl Default for UserTenant {
+    fn default() -> UserTenant {
+        UserTenant {
+            user_tenant_id: Uuid::new_v4(),
+            user_id: Uuid::nil(),
+            tenant_id: Uuid::nil(),
+            role: Role::default(),
+        }
+    }
+}
+
+pub enum UserTenantSort {
+    UserName,
+    TenantName,
+}
+
+impl UserTenantSort {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            UserTenantSort::UserName => "user_name",
+            UserTenantSort::TenantName => "tenant_name",
+        }
+    }
+}
@@ -0,0 +1,383 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Checks and tidies the user fields typed into admin forms before they are
+//! stored.
+
+use std::fmt;
+
+use serde::{Deserialize, Serialize};
+use unicode_normalization::UnicodeNormalization;
+
+/// A problem with one form field.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub message: String,
+}
+
+/// Every problem found in a form, so they can all be shown next to their
+/// inputs at once.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+#[serde(transparent)]
+pub struct ValidationErrors {
+    errors: Vec<FieldError>,
+}
+
+impl ValidationErrors {
+    pub fn new() -> ValidationErrors {
+        ValidationErrors::default()
+    }
+
+    pub fn add(&mut self, field: &'static str, message: impl Into<String>) {
+        self.errors.push(FieldError {
+            field,
+            message: message.into(),
+        });
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.errors.is_empty()
+    }
+
+    /// The first message for `field`.
+    pub fn get(&self, field: &str) -> Option<&str> {
+        self.errors
+            .iter()
+            .find(|e| e.field == field)
+            .map(|e| e.message.as_str())
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &FieldError> {
+        self.errors.iter()
+    }
+
+    /// `Ok(value)` when nothing was added.
+    pub fn into_result<T>(self, value: T) -> Result<T, ValidationErrors> {
+        if self.is_empty() {
+            Ok(value)
+        } else {
+            Err(self)
+        }
+    }
+}
+
+impl fmt::Display for ValidationErrors {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for (i, e) in self.errors.iter().enumerate() {
+            if i > 0 {
+                f.write_str("; ")?;
+            }
+            write!(f, "{}: {}", e.field, e.message)?;
+        }
+        Ok(())
+    }
+}
+
+impl std::error::Error for ValidationErrors {}
+
+/// What is accepted in the user fields.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct UserRules {
+    /// Length limits in characters.
+    pub user_name_min_length: usize,
+    pub user_name_max_length: usize,
+    /// For phone numbers typed without one, e.g. with 44 `01234 567891`
+    /// becomes `+441234567891`. Without it numbers must start with `+` or
+    /// `00`.
+    pub default_country_code: Option<u16>,
+    /// Refuse an email already used by another user, compared in canonical
+    /// form. Only emails saved while this is on are checked.
+    pub unique_email: bool,
+}
+
+impl Default for UserRules {
+    fn default() -> UserRules {
+        UserRules {
+            user_name_min_length: 3,
+            user_name_max_length: 32,
+            default_country_code: None,
+            unique_email: false,
+        }
+    }
+}
+
+/// The editable text fields of a user, as typed or after `normalize`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct UserFields {
+    pub user_name: String,
+    pub display_name: String,
+    pub email: String,
+    pub mobile_phone: String,
+}
+
+impl UserFields {
+    pub fn new(user_name: &str, display_name: &str, email: &str, mobile_phone: &str) -> UserFields {
+        UserFields {
+            user_name: user_name.to_string(),
+            display_name: display_name.to_string(),
+            email: email.to_string(),
+            mobile_phone: mobile_phone.to_string(),
+        }
+    }
+
+    /// The fields as they should be stored, or an error for each bad one.
+    /// Email and mobile phone may be left empty.
+    pub fn normalize(&self, rules: &UserRules) -> Result<UserFields, ValidationErrors> {
+        let mut errors = ValidationErrors::new();
+        let mut check = |field, r: Result<String, String>| match r {
+            Ok(v) => v,
+            Err(message) => {
+                errors.add(field, message);
+                String::new()
+            }
+        };
+        let fields = UserFields {
+            user_name: check("user_name", normalize_user_name(&self.user_name, rules)),
+            display_name: check("display_name", normalize_display_name(&self.display_name)),
+            email: check("email", normalize_email(&self.email)),
+            mobile_phone: check(
+                "mobile_phone",
+                normalize_phone(&self.mobile_phone, rules.default_country_code),
+            ),
+        };
+        errors.into_result(fields)
+    }
+}
+
+/// The form names and emails are compared in for uniqueness and logins: NFKC
+/// then lower case, so "Dave", "dave" and fullwidth "Ｄａｖｅ" are the same.
+/// Lower casing is `to_lowercase`, which is close to but not full Unicode
+/// case folding (ß is not folded to ss).
+pub fn canonical(s: &str) -> String {
+    s.nfkc().collect::<String>().to_lowercase().nfkc().collect()
+}
+
+/// What `canonical_email` is stored as, `None` when not checked for
+/// uniqueness.
+pub fn canonical_email(email: &str, unique_email: bool) -> Option<String> {
+    (unique_email && !email.is_empty()).then(|| canonical(email))
+}
+
+/// Letters, digits, `.`, `_` and `-`, starting with a letter or digit.
+/// Compatibility characters are NFKC normalized first, the case is kept.
+pub fn normalize_user_name(user_name: &str, rules: &UserRules) -> Result<String, String> {
+    let user_name: String = user_name.trim().nfkc().collect();
+    let user_name = user_name.as_str();
+    let length = user_name.chars().count();
+    if length < rules.user_name_min_length || length > rules.user_name_max_length {
+        return Err(format!(
+            "must be {} to {} characters",
+            rules.user_name_min_length, rules.user_name_max_length
+        ));
+    }
+    if !user_name.starts_with(|c: char| c.is_ascii_alphanumeric()) {
+        return Err("must start with a letter or digit".to_string());
+    }
+    if !user_name
+        .chars()
+        .all(|c| c.is_ascii_alphanumeric() || matches!(c, '.' | '_' | '-'))
+    {
+        return Err("may only contain letters, digits, '.', '_' and '-'".to_string());
+    }
+    Ok(user_name.to_string())
+}
+
+pub fn normalize_display_name(display_name: &str) -> Result<String, String> {
+    let display_name = display_name.trim();
+    if display_name.is_empty() {
+        return Err("is required".to_string());
+    }
+    if display_name.chars().any(char::is_control) {
+        return Err("must not contain control characters".to_string());
+    }
+    Ok(display_name.to_string())
+}
+
+/// Checks the shape of `local@domain`, the domain is lower cased. An empty
+/// email is allowed.
+pub fn normalize_email(email: &str) -> Result<String, String> {
+    let email = email.trim();
+    if email.is_empty() {
+        return Ok(String::new());
+    }
+    let invalid = || Err("is not a valid email address".to_string());
+    if email.len() > 254 {
+        return invalid();
+    }
+    let Some((local, domain)) = email.rsplit_once('@') else {
+        return invalid();
+    };
+    let local_ok = !local.is_empty()
+        && local.len() <= 64
+        && !local.starts_with('.')
+        && !local.ends_with('.')
+        && !local.contains("..")
+        && local
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "!#$%&'*+-/=?^_`{|}~.".contains(c));
+    let labels: Vec<&str> = domain.split('.').collect();
+    let domain_ok = labels.len() >= 2
+        && labels.iter().all(|l| {
+            !l.is_empty()
+                && l.len() <= 63
+                && !l.starts_with('-')
+                && !l.ends_with('-')
+                && l.chars().all(|c| c.is_ascii_alphanumeric() || c == '-')
+        });
+    if !(local_ok && domain_ok) {
+        return invalid();
+    }
+    Ok(format!("{}@{}", local, domain.to_ascii_lowercase()))
+}
+
+/// Converts to E.164 (`+` then up to 15 digits). Spaces, `-`, `.`, brackets
+/// and a `(0)` trunk prefix are dropped, a leading `00` is read as `+` and a
+/// leading `0` is replaced by `default_country_code`. An empty number is
+/// allowed.
+pub fn normalize_phone(phone: &str, default_country_code: Option<u16>) -> Result<String, String> {
+    let phone: String = phone
+        .replace("(0)", "")
+        .chars()
+        .filter(|c| !c.is_whitespace() && !matches!(c, '-' | '.' | '(' | ')'))
+        .collect();
+    if phone.is_empty() {
+        return Ok(phone);
+    }
+    let digits = if let Some(rest) = phone.strip_prefix('+') {
+        rest.to_string()
+    } else if let Some(rest) = phone.strip_prefix("00") {
+        rest.to_string()
+    } else {
+        match (phone.strip_prefix('0'), default_country_code) {
+            (Some(rest), Some(cc)) => format!("{}{}", cc, rest),
+            _ => return Err("must start with + and the country code".to_string()),
+        }
+    };
+    if !digits.chars().all(|c| c.is_ascii_digit())
+        || digits.starts_with('0')
+        || !(7..=15).contains(&digits.len())
+    {
+        return Err("is not a valid phone number".to_string());
+    }
+    Ok(format!("+{}", digits))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn user_names() {
+        let rules = UserRules::default();
+        assert_eq!(
+            normalize_user_name(" dave.w ", &rules).as_deref(),
+            Ok("dave.w")
+        );
+        assert!(normalize_user_name("da", &rules).is_err());
+        assert!(normalize_user_name(&"d".repeat(33), &rules).is_err());
+        assert!(normalize_user_name("not dave", &rules).is_err());
+        assert!(normalize_user_name("_dave", &rules).is_err());
+        assert!(normalize_user_name("zoë", &rules).is_err());
+        assert_eq!(
+            normalize_user_name("Ｄａｖｅ", &rules).as_deref(),
+            Ok("Dave")
+        );
+    }
+
+    #[test]
+    fn canonical_forms() {
+        assert_eq!(canonical("Dave"), "dave");
+        assert_eq!(canonical("ＤＡＶＥ"), "dave");
+        assert_eq!(canonical("ﬁle"), "file");
+        assert_eq!(canonical("Zoe\u{0301}"), "zoé");
+        assert_eq!(
+            canonical_email("D@Test.com", true).as_deref(),
+            Some("d@test.com")
+        );
+        assert_eq!(canonical_email("D@Test.com", false), None);
+        assert_eq!(canonical_email("", true), None);
+    }
+
+    #[test]
+    fn emails() {
+        assert_eq!(
+            normalize_email(" Dave.W+tenancy@Test.COM ").as_deref(),
+            Ok("Dave.W+tenancy@test.com")
+        );
+        assert_eq!(normalize_email("").as_deref(), Ok(""));
+        for bad in [
+            "not dwarnock@test.com",
+            "dwarnock",
+            "dwarnock@test",
+            "@test.com",
+            "d..w@test.com",
+            "dwarnock@-test.com",
+            "dwarnock@test..com",
+        ] {
+            assert!(normalize_email(bad).is_err(), "{}", bad);
+        }
+    }
+
+    #[test]
+    fn phones() {
+        assert_eq!(
+            normalize_phone("+44 (0)1234 567891", None).as_deref(),
+            Ok("+441234567891")
+        );
+        assert_eq!(
+            normalize_phone("+44 1234 567-891", None).as_deref(),
+            Ok("+441234567891")
+        );
+        assert_eq!(
+            normalize_phone("0044 1234 567891", None).as_deref(),
+            Ok("+441234567891")
+        );
+        assert_eq!(
+            normalize_phone("01234 567891", Some(44)).as_deref(),
+            Ok("+441234567891")
+        );
+        assert!(normalize_phone("01234 567891", None).is_err());
+        assert!(normalize_phone("+44 1234 5678912345", None).is_err());
+        assert!(normalize_phone("+44 1234 ext 5", None).is_err());
+        assert_eq!(normalize_phone("  ", None).as_deref(), Ok(""));
+    }
+
+    #[test]
+    fn every_bad_field_is_reported() {
+        let fields = UserFields::new("not dave", " ", "not dwarnock@test.com", "12345");
+        let errors = fields.normalize(&UserRules::default()).unwrap_err();
+        let bad: Vec<&str> = errors.iter().map(|e| e.field).collect();
+        assert_eq!(bad, ["user_name", "display_name", "email", "mobile_phone"]);
+        assert_eq!(errors.get("display_name"), Some("is required"));
+        assert!(errors
+            .to_string()
+            .starts_with("user_name: may only contain"));
+
+        let fields = UserFields::new(" dave ", " Dave W ", "", "+441234567891");
+        let ok = fields.normalize(&UserRules::default()).unwrap();
+        assert_eq!(ok, UserFields::new("dave", "Dave W", "", "+441234567891"));
+    }
+}
@@ -0,0 +1,208 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use argon2::password_hash;
+use axum_core::response::{IntoResponse, Response};
+use http::StatusCode;
+use sqlx::{error::ErrorKind, migrate::MigrateError};
+
+use crate::admin_core::validation_core::ValidationErrors;
+
+/// The error returned by the axum-tenancy admin functions.
+#[derive(Debug, thiserror::Error)]
+pub enum TenancyError {
+    #[error("not found")]
+    NotFound,
+    #[error("user name is already in use")]
+    DuplicateUserName,
+    #[error("display name is already in use")]
+    DuplicateDisplayName,
+    #[error("email is already in use")]
+    DuplicateEmail,
+    #[error("tenant name is already in use")]
+    DuplicateTenantName,
+    #[error("tenant display name is already in use")]
+    DuplicateTenantDisplayName,
+    #[error("user already belongs to the tenant")]
+    DuplicateUserTenant,
+    /// The record was edited by someone else since it was loaded.
+    #[error("changed by someone else, reload and try again")]
+    Conflict,
+    #[error("invitation has expired")]
+    InvitationExpired,
+    /// The invitation was already accepted, declined or revoked.
+    #[error("invitation is no longer valid")]
+    InvitationUsed,
+    #[error("{0}")]
+    Validation(String),
+    /// Form input was rejected, with a message for each bad field.
+    #[error("{0}")]
+    InvalidFields(ValidationErrors),
+    #[error("password hashing failed: {0}")]
+    Password(password_hash::Error),
+    #[error(transparent)]
+    Database(sqlx::Error),
+    /// A tenant's own schema or database could not be migrated.
+    #[error("tenant migration failed: {0}")]
+    Migration(MigrateError),
+    /// An admin page failed to render.
+    #[error("template rendering failed: {0}")]
+    Template(String),
+}
+
+impl From<sqlx::Error> for TenancyError {
+    fn from(e: sqlx::Error) -> TenancyError {
+        match e {
+            sqlx::Error::RowNotFound => TenancyError::NotFound,
+            sqlx::Error::Database(ref db) if db.kind() == ErrorKind::UniqueViolation => {
+                // Postgres names the constraint, SQLite only puts the columns
+                // in the message
+                let key = match db.constraint() {
+                    Some(c) => c.to_string(),
+                    None => db
+                        .message()
+                        .trim_start_matches("UNIQUE constraint failed: ")
+                        .to_string(),
+                };
+                match key.as_str() {
+                    "user_user_name_key"
+                    | "user.user_name"
+                    | "user_canonical_user_name"
+                    | "user.canonical_user_name" => TenancyError::DuplicateUserName,
+                    "user_display_name_key" | "user.display_name" => {
+                        TenancyError::DuplicateDisplayName
+                    }
+                    "user_canonical_email" | "user.canonical_email" => TenancyError::DuplicateEmail,
+                    "tenant_tenant_name_key" | "tenant.tenant_name" => {
+                        TenancyError::DuplicateTenantName
+                    }
+                    "tenant_display_name_key" | "tenant.display_name" => {
+                        TenancyError::DuplicateTenantDisplayName
+                    }
+                    "user_tenant_user_id_tenant_id_key"
+                    | "user_tenant.user_id, user_tenant.tenant_id" => {
+                        TenancyError::DuplicateUserTenant
+                    }
+                    _ => TenancyError::Database(e),
+                }
+            }
+            e => TenancyError::Database(e),
+        }
+    }
+}
+
+impl From<ValidationErrors> for TenancyError {
+    fn from(e: ValidationErrors) -> TenancyError {
+        TenancyError::InvalidFields(e)
+    }
+}
+
+impl From<MigrateError> for TenancyError {
+    fn from(e: MigrateError) -> TenancyError {
+        TenancyError::Migration(e)
+    }
+}
+
+impl From<password_hash::Error> for TenancyError {
+    fn from(e: password_hash::Error) -> TenancyError {
+        TenancyError::Password(e)
+    }
+}
+
+impl TenancyError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            TenancyError::NotFound => StatusCode::NOT_FOUND,
+            TenancyError::DuplicateUserName
+            | TenancyError::DuplicateDisplayName
+            | TenancyError::DuplicateEmail
+            | TenancyError::DuplicateTenantName
+            | TenancyError::DuplicateTenantDisplayName
+            | TenancyError::DuplicateUserTenant
+            | TenancyError::Conflict => StatusCode::CONFLICT,
+            TenancyError::InvitationExpired | TenancyError::InvitationUsed => StatusCode::GONE,
+            TenancyError::Validation(_) | TenancyError::InvalidFields(_) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            TenancyError::Password(_)
+            | TenancyError::Database(_)
+            | TenancyError::Migration(_)
+            | TenancyError::Template(_) => StatusCode::INTERNAL_SERVER_ERROR,
+        }
+    }
+}
+
+/// Server errors are logged with `tracing` and answered without details,
+/// everything else sends its message.
+impl IntoResponse for TenancyError {
+    fn into_response(self) -> Response {
+        let status = self.status();
+        if status.is_server_error() {
+            tracing::error!(error = %self, "axum-tenancy request failed");
+            status.into_response()
+        } else {
+            (status, self.to_string()).into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn status_codes() {
+        assert_eq!(TenancyError::NotFound.status(), StatusCode::NOT_FOUND);
+        assert_eq!(
+            TenancyError::DuplicateUserName.status(),
+            StatusCode::CONFLICT
+        );
+        assert_eq!(TenancyError::Conflict.status(), StatusCode::CONFLICT);
+        assert_eq!(TenancyError::InvitationUsed.status(), StatusCode::GONE);
+        assert_eq!(
+            TenancyError::Validation("user name is required".to_string()).status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            TenancyError::InvalidFields(ValidationErrors::new()).status(),
+            StatusCode::UNPROCESSABLE_ENTITY
+        );
+        assert_eq!(
+            TenancyError::Database(sqlx::Error::PoolTimedOut).status(),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn row_not_found_is_not_found() {
+        assert!(matches!(
+            TenancyError::from(sqlx::Error::RowNotFound),
+            TenancyError::NotFound
+        ));
+        assert!(matches!(
+            TenancyError::from(sqlx::Error::PoolClosed),
+            TenancyError::Database(_)
+        ));
+    }
+}
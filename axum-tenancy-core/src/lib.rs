@@ -23,10 +23,52 @@
 */
 
 pub mod admin_core;
+pub mod error;
+pub mod migration;
+pub mod store;
+pub mod tenant_resolver;
 
-#[derive(PartialEq, Eq, Debug)]
+#[derive(PartialEq, Eq, Debug, Clone, Copy)]
 pub enum ActiveDb {
     Postgres,
     Sqlite,
     Undefined,
 }
+
+impl ActiveDb {
+    /// The database a connection url is for, going by its scheme.
+    pub fn from_url(url: &str) -> ActiveDb {
+        match url.split_once(':').map(|(scheme, _)| scheme) {
+            Some("sqlite") => ActiveDb::Sqlite,
+            Some("postgres") | Some("postgresql") => ActiveDb::Postgres,
+            _ => ActiveDb::Undefined,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn active_db_from_url() {
+        assert_eq!(ActiveDb::from_url("sqlite::memory:"), ActiveDb::Sqlite);
+        assert_eq!(
+            ActiveDb::from_url("sqlite:tenancy.sqlite?mode=rwc"),
+            ActiveDb::Sqlite
+        );
+        assert_eq!(
+            ActiveDb::from_url("postgres://localhost/tenancy"),
+            ActiveDb::Postgres
+        );
+        assert_eq!(
+            ActiveDb::from_url("postgresql://localhost/tenancy"),
+            ActiveDb::Postgres
+        );
+        assert_eq!(
+            ActiveDb::from_url("mysql://localhost/tenancy"),
+            ActiveDb::Undefined
+        );
+        assert_eq!(ActiveDb::from_url("tenancy.sqlite"), ActiveDb::Undefined);
+    }
+}
@@ -0,0 +1,44 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Results of running an application's migrations for every tenant, when
+//! each tenant has its own schema or database.
+
+use sqlx::migrate::MigrateError;
+
+use crate::admin_core::tenant_core::Tenant;
+
+/// Which tenants were migrated and which failed, with why. A failure only
+/// rolls back that tenant's migrations.
+#[derive(Debug, Default)]
+pub struct MigrationReport {
+    pub migrated: Vec<Tenant>,
+    pub failed: Vec<(Tenant, MigrateError)>,
+}
+
+impl MigrationReport {
+    pub fn is_ok(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
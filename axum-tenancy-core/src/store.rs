@@ -0,0 +1,253 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! The storage traits each database backend implements.
+//!
+//! Every call runs inside the caller's transaction, so a backend only says
+//! which sqlx database it works with and how to run each query. axum-tenancy
+//! compiles in the backends enabled by cargo features and picks one at runtime
+//! from the pool it is given.
+
+use async_trait::async_trait;
+use chrono::{DateTime, Utc};
+use sqlx::{Database, Transaction};
+use uuid::Uuid;
+
+use crate::{
+    admin_core::{
+        invitation_core::{Invitation, InvitationStatus},
+        page_core::Page,
+        role_core::Role,
+        tenant_core::{Tenant, TenantSort},
+        user_core::{Deleted, SortDirection, User, UserOrder, UserQuery},
+        user_tenant_core::{UserTenant, UserTenantSort},
+    },
+    error::TenancyError,
+};
+
+#[async_trait]
+pub trait UserStore {
+    type Db: Database;
+
+    /// The canonical user name is always stored, the canonical email only
+    /// with `unique_email`.
+    #[allow(clippy::too_many_arguments)]
+    async fn insert(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_name: &str,
+        hash_password: &str,
+        display_name: &str,
+        is_admin: bool,
+        email: &str,
+        mobile_phone: &str,
+        unique_email: bool,
+    ) -> Result<Uuid, TenancyError>;
+
+    async fn load_by_id(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_id: Uuid,
+        deleted: Deleted,
+    ) -> Result<User, TenancyError>;
+
+    async fn load_all_sorted(
+        tx: &mut Transaction<'_, Self::Db>,
+        order: &UserOrder,
+        deleted: Deleted,
+    ) -> Result<Vec<User>, TenancyError>;
+
+    async fn load_page(
+        tx: &mut Transaction<'_, Self::Db>,
+        q: &UserQuery,
+    ) -> Result<Page<User>, TenancyError>;
+
+    /// Only updates the row if `u.version` is still the stored version.
+    /// Returns the new version, or `None` when no row has both `u.user_id`
+    /// and `u.version`. The password hash is left alone, canonical forms are
+    /// stored as for `insert`.
+    async fn update(
+        tx: &mut Transaction<'_, Self::Db>,
+        u: &User,
+        unique_email: bool,
+    ) -> Result<Option<i64>, TenancyError>;
+
+    /// Deactivated users are not returned, so they cannot log in; their hash
+    /// is kept for when they are restored.
+    /// `user_name` is compared in canonical form.
+    async fn load_password_hash(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_name: &str,
+    ) -> Result<Option<(Uuid, String)>, TenancyError>;
+
+    async fn update_password(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_id: &Uuid,
+        hash_password: &str,
+    ) -> Result<u64, TenancyError>;
+
+    /// Sets `last_login_at` to now.
+    async fn record_login(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_id: &Uuid,
+    ) -> Result<u64, TenancyError>;
+
+    /// Also deletes the UserTenants for the user.
+    async fn delete(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_id: &Uuid,
+    ) -> Result<u64, TenancyError>;
+
+    /// Sets `deleted_at`, returns 0 if the user was already deactivated.
+    async fn deactivate(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_id: &Uuid,
+    ) -> Result<u64, TenancyError>;
+
+    /// Clears `deleted_at`, returns 0 if the user was not deactivated.
+    async fn restore(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_id: &Uuid,
+    ) -> Result<u64, TenancyError>;
+
+    /// Brings every stored canonical user name and email up to date,
+    /// returning how many users changed.
+    async fn recanonicalize(
+        tx: &mut Transaction<'_, Self::Db>,
+        unique_email: bool,
+    ) -> Result<u64, TenancyError>;
+}
+
+#[async_trait]
+pub trait TenantStore {
+    type Db: Database;
+
+    async fn insert(
+        tx: &mut Transaction<'_, Self::Db>,
+        tenant_name: &str,
+        display_name: &str,
+    ) -> Result<Uuid, TenancyError>;
+
+    async fn load_by_id(
+        tx: &mut Transaction<'_, Self::Db>,
+        tenant_id: Uuid,
+    ) -> Result<Tenant, TenancyError>;
+
+    async fn load_by_name(
+        tx: &mut Transaction<'_, Self::Db>,
+        tenant_name: &str,
+    ) -> Result<Tenant, TenancyError>;
+
+    async fn load_all_sorted(
+        tx: &mut Transaction<'_, Self::Db>,
+        sort: TenantSort,
+        direction: SortDirection,
+    ) -> Result<Vec<Tenant>, TenancyError>;
+
+    async fn update(tx: &mut Transaction<'_, Self::Db>, t: &Tenant) -> Result<u64, TenancyError>;
+
+    /// Also deletes the UserTenants for the tenant.
+    async fn delete(
+        tx: &mut Transaction<'_, Self::Db>,
+        tenant_id: &Uuid,
+    ) -> Result<u64, TenancyError>;
+}
+
+#[async_trait]
+pub trait UserTenantStore {
+    type Db: Database;
+
+    async fn insert(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_id: &Uuid,
+        tenant_id: &Uuid,
+        role: &Role,
+    ) -> Result<Uuid, TenancyError>;
+
+    async fn load_by_id(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_tenant_id: Uuid,
+    ) -> Result<UserTenant, TenancyError>;
+
+    async fn load_by_user_and_tenant(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_id: &Uuid,
+        tenant_id: &Uuid,
+    ) -> Result<UserTenant, TenancyError>;
+
+    async fn load_by_user(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_id: &Uuid,
+    ) -> Result<Vec<UserTenant>, TenancyError>;
+
+    async fn load_all_sorted(
+        tx: &mut Transaction<'_, Self::Db>,
+        sort: UserTenantSort,
+        direction: SortDirection,
+    ) -> Result<Vec<UserTenant>, TenancyError>;
+
+    async fn update(
+        tx: &mut Transaction<'_, Self::Db>,
+        ut: &UserTenant,
+    ) -> Result<u64, TenancyError>;
+
+    async fn delete(
+        tx: &mut Transaction<'_, Self::Db>,
+        user_tenant_id: &Uuid,
+    ) -> Result<u64, TenancyError>;
+}
+
+#[async_trait]
+pub trait InvitationStore {
+    type Db: Database;
+
+    async fn insert(
+        tx: &mut Transaction<'_, Self::Db>,
+        invitation: &Invitation,
+    ) -> Result<(), TenancyError>;
+
+    async fn load_by_id(
+        tx: &mut Transaction<'_, Self::Db>,
+        invitation_id: Uuid,
+    ) -> Result<Invitation, TenancyError>;
+
+    async fn load_by_token_hash(
+        tx: &mut Transaction<'_, Self::Db>,
+        token_hash: &str,
+    ) -> Result<Invitation, TenancyError>;
+
+    /// Newest first.
+    async fn load_by_tenant(
+        tx: &mut Transaction<'_, Self::Db>,
+        tenant_id: &Uuid,
+    ) -> Result<Vec<Invitation>, TenancyError>;
+
+    /// Moves a pending invitation to `status`. Returns 0 when it was no
+    /// longer pending, so only one response can win.
+    async fn respond(
+        tx: &mut Transaction<'_, Self::Db>,
+        invitation_id: &Uuid,
+        status: InvitationStatus,
+        accepted_by: Option<&Uuid>,
+        now: DateTime<Utc>,
+    ) -> Result<u64, TenancyError>;
+}
@@ -0,0 +1,260 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Ways of working out which tenant a request is for.
+//!
+//! Each resolver returns the tenant_name found in the request, or `None` if
+//! its strategy doesn't apply. Combine them with [`ChainResolver`] when a
+//! deployment identifies tenants in more than one way.
+
+use http::{
+    header::{COOKIE, HOST},
+    request::Parts,
+    HeaderName,
+};
+
+pub trait TenantResolver: Send + Sync {
+    fn resolve(&self, parts: &Parts) -> Option<String>;
+}
+
+impl<F> TenantResolver for F
+where
+    F: Fn(&Parts) -> Option<String> + Send + Sync,
+{
+    fn resolve(&self, parts: &Parts) -> Option<String> {
+        self(parts)
+    }
+}
+
+/// `acme.example.com` gives `acme` when the base domain is `example.com`.
+pub struct SubdomainResolver {
+    base_domain: String,
+}
+
+impl SubdomainResolver {
+    pub fn new(base_domain: &str) -> SubdomainResolver {
+        SubdomainResolver {
+            base_domain: base_domain.trim_start_matches('.').to_ascii_lowercase(),
+        }
+    }
+}
+
+impl TenantResolver for SubdomainResolver {
+    fn resolve(&self, parts: &Parts) -> Option<String> {
+        let host = parts
+            .headers
+            .get(HOST)
+            .and_then(|h| h.to_str().ok())
+            .or_else(|| parts.uri.host())?;
+        let host = host.split(':').next()?.to_ascii_lowercase();
+        let subdomain = host.strip_suffix(&self.base_domain)?.strip_suffix('.')?;
+        if subdomain.is_empty() || subdomain.contains('.') {
+            None
+        } else {
+            Some(subdomain.to_string())
+        }
+    }
+}
+
+/// `/t/acme/invoices` gives `acme` when the prefix is `/t`.
+///
+/// Uses the path the layer sees, so when the router is nested the prefix is
+/// relative to the nest point.
+pub struct PathPrefixResolver {
+    prefix: String,
+}
+
+impl PathPrefixResolver {
+    pub fn new(prefix: &str) -> PathPrefixResolver {
+        PathPrefixResolver {
+            prefix: format!("/{}/", prefix.trim_matches('/')).replace("//", "/"),
+        }
+    }
+}
+
+impl TenantResolver for PathPrefixResolver {
+    fn resolve(&self, parts: &Parts) -> Option<String> {
+        let rest = parts.uri.path().strip_prefix(&self.prefix)?;
+        let tenant_name = rest.split('/').next()?;
+        if tenant_name.is_empty() {
+            None
+        } else {
+            Some(tenant_name.to_string())
+        }
+    }
+}
+
+/// Reads the tenant_name from a header, `X-Tenant` by default, typically set
+/// by a gateway in front of the app.
+pub struct HeaderResolver {
+    name: HeaderName,
+}
+
+impl HeaderResolver {
+    pub fn new(name: HeaderName) -> HeaderResolver {
+        HeaderResolver { name }
+    }
+}
+
+impl Default for HeaderResolver {
+    fn default() -> HeaderResolver {
+        HeaderResolver::new(HeaderName::from_static("x-tenant"))
+    }
+}
+
+impl TenantResolver for HeaderResolver {
+    fn resolve(&self, parts: &Parts) -> Option<String> {
+        let value = parts.headers.get(&self.name)?.to_str().ok()?.trim();
+        if value.is_empty() {
+            None
+        } else {
+            Some(value.to_string())
+        }
+    }
+}
+
+/// Reads the tenant_name from a cookie, such as one holding the last tenant
+/// the user selected.
+pub struct CookieResolver {
+    name: String,
+}
+
+impl CookieResolver {
+    pub fn new(name: &str) -> CookieResolver {
+        CookieResolver {
+            name: name.to_string(),
+        }
+    }
+}
+
+impl TenantResolver for CookieResolver {
+    fn resolve(&self, parts: &Parts) -> Option<String> {
+        parts
+            .headers
+            .get_all(COOKIE)
+            .iter()
+            .filter_map(|h| h.to_str().ok())
+            .flat_map(|h| h.split(';'))
+            .filter_map(|c| c.trim().split_once('='))
+            .find(|(name, value)| *name == self.name && !value.is_empty())
+            .map(|(_, value)| value.to_string())
+    }
+}
+
+/// Tries each resolver in the order added, the first to find a tenant wins.
+#[derive(Default)]
+pub struct ChainResolver {
+    resolvers: Vec<Box<dyn TenantResolver>>,
+}
+
+impl ChainResolver {
+    pub fn new() -> ChainResolver {
+        ChainResolver::default()
+    }
+
+    pub fn or<R: TenantResolver + 'static>(mut self, resolver: R) -> ChainResolver {
+        self.resolvers.push(Box::new(resolver));
+        self
+    }
+}
+
+impl TenantResolver for ChainResolver {
+    fn resolve(&self, parts: &Parts) -> Option<String> {
+        self.resolvers.iter().find_map(|r| r.resolve(parts))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use http::Request;
+
+    use super::*;
+
+    fn parts(uri: &str, headers: &[(&str, &str)]) -> Parts {
+        let mut builder = Request::builder().uri(uri);
+        for (name, value) in headers {
+            builder = builder.header(*name, *value);
+        }
+        builder.body(()).unwrap().into_parts().0
+    }
+
+    #[test]
+    fn subdomain() {
+        let r = SubdomainResolver::new("example.com");
+        let p = parts("/", &[("host", "Acme.Example.com:3000")]);
+        assert_eq!(r.resolve(&p), Some("acme".to_string()));
+        assert_eq!(r.resolve(&parts("/", &[("host", "example.com")])), None);
+        assert_eq!(r.resolve(&parts("/", &[("host", "a.b.example.com")])), None);
+        assert_eq!(r.resolve(&parts("/", &[("host", "acme.other.com")])), None);
+    }
+
+    #[test]
+    fn path_prefix() {
+        let r = PathPrefixResolver::new("/t");
+        assert_eq!(
+            r.resolve(&parts("/t/acme/invoices", &[])),
+            Some("acme".to_string())
+        );
+        assert_eq!(r.resolve(&parts("/t/acme", &[])), Some("acme".to_string()));
+        assert_eq!(r.resolve(&parts("/t/", &[])), None);
+        assert_eq!(r.resolve(&parts("/tenants/acme", &[])), None);
+    }
+
+    #[test]
+    fn header() {
+        let r = HeaderResolver::default();
+        assert_eq!(
+            r.resolve(&parts("/", &[("X-Tenant", "acme")])),
+            Some("acme".to_string())
+        );
+        assert_eq!(r.resolve(&parts("/", &[("X-Tenant", " ")])), None);
+        assert_eq!(r.resolve(&parts("/", &[])), None);
+    }
+
+    #[test]
+    fn cookie() {
+        let r = CookieResolver::new("tenant");
+        let p = parts("/", &[("cookie", "session=abc; tenant=acme")]);
+        assert_eq!(r.resolve(&p), Some("acme".to_string()));
+        assert_eq!(r.resolve(&parts("/", &[("cookie", "tenants=acme")])), None);
+    }
+
+    #[test]
+    fn chain_uses_first_match() {
+        let r = ChainResolver::new()
+            .or(PathPrefixResolver::new("t"))
+            .or(HeaderResolver::default())
+            .or(CookieResolver::new("tenant"));
+        let p = parts(
+            "/t/path/x",
+            &[("x-tenant", "header"), ("cookie", "tenant=cookie")],
+        );
+        assert_eq!(r.resolve(&p), Some("path".to_string()));
+        let p = parts("/x", &[("x-tenant", "header"), ("cookie", "tenant=cookie")]);
+        assert_eq!(r.resolve(&p), Some("header".to_string()));
+        let p = parts("/x", &[("cookie", "tenant=cookie")]);
+        assert_eq!(r.resolve(&p), Some("cookie".to_string()));
+        assert_eq!(r.resolve(&parts("/x", &[])), None);
+    }
+}
@@ -0,0 +1,35 @@
+-- MIT License
+--
+-- Copyright (c) 2024 Dave Warnock
+-- 
+-- Permission is hereby granted, free of charge, to any person obtaining a copy
+-- of this software and associated documentation files (the "Software"), to deal
+-- in the Software without restriction, including without limitation the rights
+-- to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+-- copies of the Software, and to permit persons to whom the Software is
+-- furnished to do so, subject to the following conditions:
+-- 
+-- The above copyright notice and this permission notice shall be included in all
+-- copies or substantial portions of the Software.
+-- 
+-- THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+-- IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+-- FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+-- AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+-- LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+-- OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+-- SOFTWARE.
+
+
+-- NFKC and lower cased copies, set by the application. SQL lower() is not
+-- Rust's to_lowercase, initialize recomputes both columns in Rust after
+-- migrating and fails if two existing users then clash; rename one of them
+-- and restart. Names that already clash here stop the migration itself.
+ALTER TABLE "user" ADD COLUMN IF NOT EXISTS canonical_user_name TEXT NOT NULL DEFAULT '';
+UPDATE "user" SET canonical_user_name = lower(normalize(user_name, NFKC));
+-- NULL unless unique emails are configured, NULLs never clash. Filled in by
+-- the same recomputation.
+ALTER TABLE "user" ADD COLUMN IF NOT EXISTS canonical_email TEXT;
+
+CREATE UNIQUE INDEX IF NOT EXISTS user_canonical_user_name ON "user" (canonical_user_name);
+CREATE UNIQUE INDEX IF NOT EXISTS user_canonical_email ON "user" (canonical_email);
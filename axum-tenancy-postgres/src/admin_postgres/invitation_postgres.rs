@@ -0,0 +1,132 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use axum_tenancy_core::admin_core::invitation_core::{Invitation, InvitationStatus};
+use axum_tenancy_core::error::TenancyError;
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+type DbTransaction<'c> = sqlx::Transaction<'c, sqlx::Postgres>;
+
+pub async fn insert(tx: &mut DbTransaction<'_>, i: &Invitation) -> Result<(), TenancyError> {
+    let r = sqlx::query!(
+        r#"
+        INSERT INTO invitation 
+        (invitation_id, tenant_id, email, role, token_hash, invited_by, status, created_at, expires_at, responded_at, accepted_by) 
+        VALUES
+        ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        "#,
+        i.invitation_id,
+        i.tenant_id,
+        i.email,
+        i.role.as_str(),
+        i.token_hash,
+        i.invited_by,
+        i.status.as_str(),
+        i.created_at,
+        i.expires_at,
+        i.responded_at,
+        i.accepted_by
+    )
+    .execute(&mut **tx)
+    .await;
+
+    match r {
+        Ok(qr) => {
+            if qr.rows_affected() == 1 {
+                Ok(())
+            } else {
+                Err(TenancyError::Database(sqlx::Error::Protocol(format!(
+                    "Insert did not return 1 row affected:{}",
+                    qr.rows_affected()
+                ))))
+            }
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn load_by_id(
+    tx: &mut DbTransaction<'_>,
+    invitation_id: Uuid,
+) -> Result<Invitation, sqlx::Error> {
+    sqlx::query_as!(
+        Invitation,
+        r#"SELECT invitation_id, tenant_id, email, role, token_hash, invited_by, status, created_at, expires_at, responded_at, accepted_by from invitation where invitation_id = $1"#,
+        invitation_id
+    )
+    .fetch_one(&mut **tx)
+    .await
+}
+
+pub async fn load_by_token_hash(
+    tx: &mut DbTransaction<'_>,
+    token_hash: &str,
+) -> Result<Invitation, sqlx::Error> {
+    sqlx::query_as!(
+        Invitation,
+        r#"SELECT invitation_id, tenant_id, email, role, token_hash, invited_by, status, created_at, expires_at, responded_at, accepted_by from invitation where token_hash = $1"#,
+        token_hash
+    )
+    .fetch_one(&mut **tx)
+    .await
+}
+
+pub async fn load_by_tenant(
+    tx: &mut DbTransaction<'_>,
+    tenant_id: &Uuid,
+) -> Result<Vec<Invitation>, sqlx::Error> {
+    sqlx::query_as!(
+        Invitation,
+        r#"SELECT invitation_id, tenant_id, email, role, token_hash, invited_by, status, created_at, expires_at, responded_at, accepted_by from invitation where tenant_id = $1 ORDER BY created_at DESC, invitation_id"#,
+        tenant_id
+    )
+    .fetch_all(&mut **tx)
+    .await
+}
+
+pub async fn respond(
+    tx: &mut DbTransaction<'_>,
+    invitation_id: &Uuid,
+    status: InvitationStatus,
+    accepted_by: Option<&Uuid>,
+    now: DateTime<Utc>,
+) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE invitation 
+            SET status = $2,
+                accepted_by = $3,
+                responded_at = $4
+            WHERE
+                invitation_id = $1 AND status = 'pending'
+        "#,
+        invitation_id,
+        status.as_str(),
+        accepted_by,
+        now
+    )
+    .execute(&mut **tx)
+    .await
+}
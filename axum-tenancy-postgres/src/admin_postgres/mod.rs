@@ -22,4 +22,7 @@
 # SOFTWARE.
 */
 
+pub mod invitation_postgres;
+pub mod tenant_postgres;
 pub mod user_postgres;
+pub mod user_tenant_postgres;
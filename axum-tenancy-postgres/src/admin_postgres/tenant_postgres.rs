@@ -0,0 +1,163 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use axum_tenancy_core::admin_core::{
+    tenant_core::{Tenant, TenantSort},
+    user_core::SortDirection,
+};
+use axum_tenancy_core::error::TenancyError;
+use uuid::Uuid;
+
+type DbTransaction<'c> = sqlx::Transaction<'c, sqlx::Postgres>;
+
+pub async fn insert(
+    tx: &mut DbTransaction<'_>,
+    tenant_name: &str,
+    display_name: &str,
+) -> Result<uuid::Uuid, TenancyError> {
+    let tenant_id = Uuid::new_v4();
+    let r = sqlx::query!(
+        r#"
+        INSERT INTO tenant 
+        (tenant_id, tenant_name, display_name) 
+        VALUES
+        ($1, $2, $3)
+        "#,
+        tenant_id,
+        tenant_name,
+        display_name
+    )
+    .execute(&mut **tx)
+    .await;
+
+    match r {
+        Ok(qr) => {
+            if qr.rows_affected() == 1 {
+                Ok(tenant_id)
+            } else {
+                Err(TenancyError::Database(sqlx::Error::Protocol(format!(
+                    "Insert did not return 1 row affected:{}",
+                    qr.rows_affected()
+                ))))
+            }
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn load_by_id(
+    tx: &mut DbTransaction<'_>,
+    tenant_id: Uuid,
+) -> Result<Tenant, sqlx::Error> {
+    sqlx::query_as!(
+        Tenant,
+        r#"SELECT tenant_id, tenant_name, display_name from tenant where tenant_id = $1"#,
+        tenant_id
+    )
+    .fetch_one(&mut **tx)
+    .await
+}
+
+pub async fn load_by_name(
+    tx: &mut DbTransaction<'_>,
+    tenant_name: &str,
+) -> Result<Tenant, sqlx::Error> {
+    sqlx::query_as!(
+        Tenant,
+        r#"SELECT tenant_id, tenant_name, display_name from tenant where tenant_name = $1"#,
+        tenant_name
+    )
+    .fetch_one(&mut **tx)
+    .await
+}
+
+pub async fn load_all_sorted(
+    tx: &mut DbTransaction<'_>,
+    sort: TenantSort,
+    direction: SortDirection,
+) -> Result<Vec<Tenant>, sqlx::Error> {
+    let str_sort = sort.as_str();
+    match direction {
+        SortDirection::Asc => {
+            sqlx::query_as!(
+                Tenant,
+                r#"SELECT tenant_id, tenant_name, display_name FROM tenant ORDER BY 
+                    CASE 
+                          WHEN $1 = 'tenant_name' THEN tenant_name
+                          WHEN $1 = 'display_name' THEN display_name
+                    END ASC
+                "#,
+                str_sort
+            )
+            .fetch_all(&mut **tx)
+            .await
+        }
+        SortDirection::Desc => {
+            sqlx::query_as!(
+                Tenant,
+                r#"SELECT tenant_id, tenant_name, display_name FROM tenant ORDER BY 
+                    CASE 
+                          WHEN $1 = 'tenant_name' THEN tenant_name
+                          WHEN $1 = 'display_name' THEN display_name
+                    END DESC
+                "#,
+                str_sort
+            )
+            .fetch_all(&mut **tx)
+            .await
+        }
+    }
+}
+
+pub async fn update(
+    tx: &mut DbTransaction<'_>,
+    t: &Tenant,
+) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE tenant 
+            SET tenant_name = $2,
+                display_name = $3 
+            WHERE
+                tenant_id = $1
+        "#,
+        t.tenant_id,
+        t.tenant_name,
+        t.display_name
+    )
+    .execute(&mut **tx)
+    .await
+}
+
+pub async fn delete(
+    tx: &mut DbTransaction<'_>,
+    tenant_id: &Uuid,
+) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM user_tenant WHERE tenant_id = $1"#, tenant_id)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query!(r#"DELETE FROM tenant WHERE tenant_id = $1"#, tenant_id)
+        .execute(&mut **tx)
+        .await
+}
@@ -22,29 +22,40 @@
 # SOFTWARE.
 */
 
-use anyhow::{anyhow, Error, Result};
-use axum_tenancy_core::admin_core::user_core::{SortDirection, User, UserSort};
+use axum_tenancy_core::admin_core::{
+    page_core::{like_pattern, Page},
+    user_core::{Deleted, User, UserCursor, UserKeyValue, UserOrder, UserQuery},
+    validation_core::{canonical, canonical_email},
+};
+use axum_tenancy_core::error::TenancyError;
+use chrono::Utc;
+use sqlx::{Postgres, QueryBuilder};
 use uuid::Uuid;
 
 type DbTransaction<'c> = sqlx::Transaction<'c, sqlx::Postgres>;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn insert(
     tx: &mut DbTransaction<'_>,
     //tx: &mut sqlx::Transaction<'_, Postgres>,
     user_name: &str,
+    hash_password: &str,
     display_name: &str,
     is_admin: bool,
     email: &str,
     mobile_phone: &str,
-) -> Result<uuid::Uuid, Error> {
+    unique_email: bool,
+) -> Result<uuid::Uuid, TenancyError> {
     let user_id = Uuid::new_v4();
-    let hash_password = "".to_string();
+    let now = Utc::now();
+    let user_name_key = canonical(user_name);
+    let email_key = canonical_email(email, unique_email);
     let r = sqlx::query!(
         r#"
         INSERT INTO "user" 
-        (user_id, user_name, hash_password, display_name, is_admin, email, mobile_phone) 
+        (user_id, user_name, hash_password, display_name, is_admin, email, mobile_phone, created_at, updated_at, canonical_user_name, canonical_email) 
         VALUES
-        ($1, $2, $3, $4, $5, $6, $7)
+        ($1, $2, $3, $4, $5, $6, $7, $8, $8, $9, $10)
         "#,
         &user_id,
         user_name,
@@ -52,7 +63,10 @@ pub async fn insert(
         display_name,
         is_admin,
         email,
-        mobile_phone
+        mobile_phone,
+        now,
+        user_name_key,
+        email_key
     )
     .execute(&mut **tx)
     .await;
@@ -62,10 +76,10 @@ pub async fn insert(
             if qr.rows_affected() == 1 {
                 Ok(user_id)
             } else {
-                Err(anyhow!(
+                Err(TenancyError::Database(sqlx::Error::Protocol(format!(
                     "Insert did not return 1 row affected:{}",
                     qr.rows_affected()
-                ))
+                ))))
             }
         }
         Err(e) => Err(e.into()),
@@ -75,75 +89,299 @@ pub async fn insert(
 pub async fn load_by_id(
     tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
     user_id: Uuid,
+    deleted: Deleted,
 ) -> Result<User, sqlx::Error> {
     sqlx::query_as!(
         User,
-        r#"SELECT user_id, user_name, display_name, is_admin, email, mobile_phone from "user" where user_id = $1"#,
-        &user_id
+        r#"SELECT user_id, user_name, hash_password, display_name, is_admin, email, mobile_phone, created_at, updated_at, last_login_at, deleted_at, version from "user" where user_id = $1 AND ($2 OR deleted_at IS NULL)"#,
+        &user_id,
+        deleted.included()
     )
     .fetch_one(&mut **tx)
     .await
 }
 
+const SELECT_USER: &str = r#"SELECT user_id, user_name, hash_password, display_name, is_admin, email, mobile_phone, created_at, updated_at, last_login_at, deleted_at, version FROM "user""#;
+
 pub async fn load_all_sorted(
     tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
-    sort: UserSort,
-    direction: SortDirection,
+    order: &UserOrder,
+    deleted: Deleted,
 ) -> Result<Vec<User>, sqlx::Error> {
-    match direction {
-        SortDirection::Asc => {
-            sqlx::query_as!(
-                User,
-                r#"SELECT user_id, user_name, display_name, is_admin, email, mobile_phone FROM "user" ORDER BY 
-                    CASE 
-                          WHEN $1 = 'user_name' THEN user_name
-                          WHEN $1 = 'display_name' THEN display_name
-                    END ASC
-                "#,
-                sort.as_str()
-            )
-            .fetch_all(&mut **tx)
-            .await
-        }
-        SortDirection::Desc => {
-            sqlx::query_as!(
-                User,
-                r#"SELECT user_id, user_name, display_name, is_admin, email, mobile_phone FROM "user" ORDER BY 
-                    CASE 
-                          WHEN $1 = 'user_name' THEN user_name
-                          WHEN $1 = 'display_name' THEN display_name
-                    END DESC
-                "#,
-                sort.as_str()
-            )
-            .fetch_all(&mut **tx)
-            .await
+    let mut qb = QueryBuilder::<Postgres>::new(SELECT_USER);
+    if !deleted.included() {
+        qb.push(" WHERE deleted_at IS NULL");
+    }
+    qb.push(" ORDER BY ").push(order.to_sql());
+    qb.build_query_as::<User>().fetch_all(&mut **tx).await
+}
+
+// pushes the WHERE for the filters, returns what joins the next condition
+fn push_filters(qb: &mut QueryBuilder<'_, Postgres>, q: &UserQuery) -> &'static str {
+    let mut next = " WHERE ";
+    if !q.deleted.included() {
+        qb.push(next).push("deleted_at IS NULL");
+        next = " AND ";
+    }
+    if let Some(is_admin) = q.is_admin {
+        qb.push(next).push("is_admin = ").push_bind(is_admin);
+        next = " AND ";
+    }
+    if let Some(search) = &q.search {
+        let pattern = like_pattern(search);
+        qb.push(next).push("(");
+        for (i, column) in ["user_name", "display_name", "email"].iter().enumerate() {
+            if i > 0 {
+                qb.push(" OR ");
+            }
+            qb.push(column).push(" ILIKE ").push_bind(pattern.clone());
+            qb.push(" ESCAPE '\\'");
         }
+        qb.push(")");
+        next = " AND ";
+    }
+    next
+}
+
+fn push_value(qb: &mut QueryBuilder<'_, Postgres>, value: &UserKeyValue) {
+    match value {
+        UserKeyValue::Text(s) => qb.push_bind(s.clone()),
+        UserKeyValue::Time(t) => qb.push_bind(*t),
+        UserKeyValue::Id(id) => qb.push_bind(*id),
+        UserKeyValue::Null => qb.push("NULL"),
+    };
+}
+
+fn page_query<'a>(
+    prefix: &str,
+    q: &UserQuery,
+    cursor: Option<&UserCursor>,
+) -> QueryBuilder<'a, Postgres> {
+    let mut qb = QueryBuilder::new(prefix);
+    qb.push(SELECT_USER);
+    let next = push_filters(&mut qb, q);
+    if let Some(cursor) = cursor {
+        qb.push(next);
+        cursor.push_after(&mut qb, &q.order, push_value);
     }
+    qb.push(" ORDER BY ").push(q.order.to_sql());
+    // one extra row shows whether there is a next page
+    qb.push(" LIMIT ").push_bind(i64::from(q.limit) + 1);
+    qb.push(" OFFSET ").push_bind(i64::from(q.offset));
+    qb
+}
+
+/// One page of users, see `UserQuery`.
+pub async fn load_page(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    q: &UserQuery,
+) -> Result<Page<User>, TenancyError> {
+    let cursor = q.cursor()?;
+    let users = page_query("", q, cursor.as_ref())
+        .build_query_as::<User>()
+        .fetch_all(&mut **tx)
+        .await?;
+    let mut count = QueryBuilder::<Postgres>::new(r#"SELECT COUNT(*) FROM "user""#);
+    push_filters(&mut count, q);
+    let total: i64 = count.build_query_scalar().fetch_one(&mut **tx).await?;
+    Ok(Page::new(users, q.limit, total, |u| {
+        UserCursor::after(&q.order, u).encode(&q.order)
+    }))
 }
 
+/// The `EXPLAIN` output for the `load_page` query, one line per row, for the
+/// index tests.
+#[cfg(feature = "test-support")]
+pub async fn explain_page(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    q: &UserQuery,
+) -> Result<Vec<String>, TenancyError> {
+    use sqlx::Row;
+
+    let cursor = q.cursor()?;
+    let rows = page_query("EXPLAIN ", q, cursor.as_ref())
+        .build()
+        .fetch_all(&mut **tx)
+        .await?;
+    Ok(rows.iter().map(|r| r.get::<String, _>(0)).collect())
+}
+
+/// Only updates the row when `u.version` is still current, returning the new
+/// version.
 pub async fn update(
     tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
     u: &User,
-) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
-    sqlx::query!(
+    unique_email: bool,
+) -> Result<Option<i64>, sqlx::Error> {
+    let now = Utc::now();
+    let user_name_key = canonical(&u.user_name);
+    let email_key = canonical_email(&u.email, unique_email);
+    sqlx::query_scalar!(
         r#"
         UPDATE "user" 
             SET user_name = $2,
                 display_name = $3, 
                 is_admin = $4, 
                 email = $5, 
-                mobile_phone = $6 
+                mobile_phone = $6,
+                updated_at = $7,
+                version = version + 1,
+                canonical_user_name = $9,
+                canonical_email = $10
             WHERE
-                user_id = $1
+                user_id = $1 AND version = $8
+            RETURNING version
         "#,
         u.user_id,
         u.user_name,
         u.display_name,
         u.is_admin,
         u.email,
-        u.mobile_phone
+        u.mobile_phone,
+        now,
+        u.version,
+        user_name_key,
+        email_key
+    )
+    .fetch_optional(&mut **tx)
+    .await
+}
+
+/// `user_name` is compared in canonical form.
+pub async fn load_password_hash(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_name: &str,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let user_name_key = canonical(user_name);
+    let r = sqlx::query!(
+        r#"SELECT user_id, hash_password FROM "user" WHERE canonical_user_name = $1 AND deleted_at IS NULL"#,
+        user_name_key
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    Ok(r.map(|row| (row.user_id, row.hash_password)))
+}
+
+pub async fn update_password(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+    hash_password: &str,
+) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        UPDATE "user" 
+            SET hash_password = $2,
+                updated_at = $3,
+                version = version + 1
+            WHERE
+                user_id = $1
+        "#,
+        user_id,
+        hash_password,
+        now
     )
     .execute(&mut **tx)
     .await
 }
+
+/// Called after a successful login, it is not an edit so `updated_at` is
+/// left alone.
+pub async fn record_login(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"UPDATE "user" SET last_login_at = $2 WHERE user_id = $1"#,
+        user_id,
+        now
+    )
+    .execute(&mut **tx)
+    .await
+}
+
+pub async fn delete(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+    sqlx::query!(r#"DELETE FROM user_tenant WHERE user_id = $1"#, user_id)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query!(r#"DELETE FROM "user" WHERE user_id = $1"#, user_id)
+        .execute(&mut **tx)
+        .await
+}
+
+pub async fn deactivate(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE "user" 
+            SET deleted_at = $2,
+                updated_at = $2,
+                version = version + 1
+            WHERE
+                user_id = $1 AND deleted_at IS NULL
+        "#,
+        user_id,
+        Utc::now()
+    )
+    .execute(&mut **tx)
+    .await
+}
+
+pub async fn restore(
+    tx: &mut sqlx::Transaction<'_, sqlx::Postgres>,
+    user_id: &Uuid,
+) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        UPDATE "user" 
+            SET deleted_at = NULL,
+                updated_at = $2,
+                version = version + 1
+            WHERE
+                user_id = $1 AND deleted_at IS NOT NULL
+        "#,
+        user_id,
+        now
+    )
+    .execute(&mut **tx)
+    .await
+}
+
+/// Stores the canonical user name and email of every user whose stored forms
+/// differ from what `canonical` gives now, returning how many were changed.
+/// Leaves `version` alone as the user has not been edited.
+pub async fn recanonicalize(
+    tx: &mut DbTransaction<'_>,
+    unique_email: bool,
+) -> Result<u64, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT user_id, user_name, email, canonical_user_name, canonical_email FROM "user""#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    let mut changed = 0;
+    for row in rows {
+        let user_name_key = canonical(&row.user_name);
+        let email_key = canonical_email(&row.email, unique_email);
+        if user_name_key == row.canonical_user_name && email_key == row.canonical_email {
+            continue;
+        }
+        changed += sqlx::query!(
+            r#"UPDATE "user" SET canonical_user_name = $2, canonical_email = $3 WHERE user_id = $1"#,
+            row.user_id,
+            user_name_key,
+            email_key
+        )
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+    }
+    Ok(changed)
+}
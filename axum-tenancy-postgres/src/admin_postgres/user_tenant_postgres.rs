@@ -0,0 +1,191 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use axum_tenancy_core::admin_core::{
+    role_core::Role,
+    user_core::SortDirection,
+    user_tenant_core::{UserTenant, UserTenantSort},
+};
+use axum_tenancy_core::error::TenancyError;
+use uuid::Uuid;
+
+type DbTransaction<'c> = sqlx::Transaction<'c, sqlx::Postgres>;
+
+pub async fn insert(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+    tenant_id: &Uuid,
+    role: &Role,
+) -> Result<uuid::Uuid, TenancyError> {
+    let user_tenant_id = Uuid::new_v4();
+    let r = sqlx::query!(
+        r#"
+        INSERT INTO user_tenant 
+        (user_tenant_id, user_id, tenant_id, role) 
+        VALUES
+        ($1, $2, $3, $4)
+        "#,
+        user_tenant_id,
+        user_id,
+        tenant_id,
+        role.as_str()
+    )
+    .execute(&mut **tx)
+    .await;
+
+    match r {
+        Ok(qr) => {
+            if qr.rows_affected() == 1 {
+                Ok(user_tenant_id)
+            } else {
+                Err(TenancyError::Database(sqlx::Error::Protocol(format!(
+                    "Insert did not return 1 row affected:{}",
+                    qr.rows_affected()
+                ))))
+            }
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn load_by_id(
+    tx: &mut DbTransaction<'_>,
+    user_tenant_id: Uuid,
+) -> Result<UserTenant, sqlx::Error> {
+    sqlx::query_as!(
+        UserTenant,
+        r#"SELECT user_tenant_id, user_id, tenant_id, role from user_tenant where user_tenant_id = $1"#,
+        user_tenant_id
+    )
+    .fetch_one(&mut **tx)
+    .await
+}
+
+pub async fn load_by_user_and_tenant(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+    tenant_id: &Uuid,
+) -> Result<UserTenant, sqlx::Error> {
+    sqlx::query_as!(
+        UserTenant,
+        r#"SELECT user_tenant_id, user_id, tenant_id, role from user_tenant where user_id = $1 and tenant_id = $2"#,
+        user_id,
+        tenant_id
+    )
+    .fetch_one(&mut **tx)
+    .await
+}
+
+pub async fn load_by_user(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+) -> Result<Vec<UserTenant>, sqlx::Error> {
+    sqlx::query_as!(
+        UserTenant,
+        r#"SELECT user_tenant_id, user_id, tenant_id, role from user_tenant where user_id = $1"#,
+        user_id
+    )
+    .fetch_all(&mut **tx)
+    .await
+}
+
+pub async fn load_all_sorted(
+    tx: &mut DbTransaction<'_>,
+    sort: UserTenantSort,
+    direction: SortDirection,
+) -> Result<Vec<UserTenant>, sqlx::Error> {
+    let str_sort = sort.as_str();
+    match direction {
+        SortDirection::Asc => {
+            sqlx::query_as!(
+                UserTenant,
+                r#"SELECT ut.user_tenant_id, ut.user_id, ut.tenant_id, ut.role
+                    FROM user_tenant ut
+                    JOIN "user" u ON u.user_id = ut.user_id
+                    JOIN tenant t ON t.tenant_id = ut.tenant_id
+                    ORDER BY 
+                    CASE 
+                          WHEN $1 = 'user_name' THEN u.user_name
+                          WHEN $1 = 'tenant_name' THEN t.tenant_name
+                    END ASC
+                "#,
+                str_sort
+            )
+            .fetch_all(&mut **tx)
+            .await
+        }
+        SortDirection::Desc => {
+            sqlx::query_as!(
+                UserTenant,
+                r#"SELECT ut.user_tenant_id, ut.user_id, ut.tenant_id, ut.role
+                    FROM user_tenant ut
+                    JOIN "user" u ON u.user_id = ut.user_id
+                    JOIN tenant t ON t.tenant_id = ut.tenant_id
+                    ORDER BY 
+                    CASE 
+                          WHEN $1 = 'user_name' THEN u.user_name
+                          WHEN $1 = 'tenant_name' THEN t.tenant_name
+                    END DESC
+                "#,
+                str_sort
+            )
+            .fetch_all(&mut **tx)
+            .await
+        }
+    }
+}
+
+pub async fn update(
+    tx: &mut DbTransaction<'_>,
+    ut: &UserTenant,
+) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE user_tenant 
+            SET user_id = $2,
+                tenant_id = $3,
+                role = $4
+            WHERE
+                user_tenant_id = $1
+        "#,
+        ut.user_tenant_id,
+        ut.user_id,
+        ut.tenant_id,
+        ut.role.as_str()
+    )
+    .execute(&mut **tx)
+    .await
+}
+
+pub async fn delete(
+    tx: &mut DbTransaction<'_>,
+    user_tenant_id: &Uuid,
+) -> Result<sqlx::postgres::PgQueryResult, sqlx::Error> {
+    sqlx::query!(
+        r#"DELETE FROM user_tenant WHERE user_tenant_id = $1"#,
+        user_tenant_id
+    )
+    .execute(&mut **tx)
+    .await
+}
@@ -22,4 +22,14 @@
 # SOFTWARE.
 */
 
+use sqlx::migrate::Migrator;
+
 pub mod admin_postgres;
+pub mod rls;
+pub mod schema;
+mod store_postgres;
+
+pub use store_postgres::PostgresStore;
+
+/// The migrations in this crate, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
@@ -0,0 +1,207 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Row level security for an application's tenant owned tables.
+//!
+//! A table with a `tenant_id` column can be given a policy so that queries
+//! only see, and may only write, the rows of the tenant in the
+//! `app.current_tenant` setting. Set it at the start of each transaction with
+//! [`set_current_tenant`], then a query missing its `WHERE tenant_id = ...`
+//! still can't read another tenant's rows. With no tenant set nothing is
+//! visible.
+//!
+//! Put the statements from [`TenantPolicy`] in a migration:
+//!
+//! ```
+//! use axum_tenancy_postgres::rls::TenantPolicy;
+//!
+//! let sql = TenantPolicy::new("invoice").up_sql();
+//! assert!(sql.starts_with("ALTER TABLE \"invoice\" ENABLE ROW LEVEL SECURITY;"));
+//! ```
+//!
+//! Postgres never applies policies to superusers or roles with `BYPASSRLS`,
+//! so the application must connect as an ordinary role. The policies are
+//! forced, so they apply to the table's owner too.
+
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+/// The setting policies read the current tenant's id from.
+pub const CURRENT_TENANT: &str = "app.current_tenant";
+
+/// Sets [`CURRENT_TENANT`] until the end of the transaction, like
+/// `SET LOCAL`.
+pub async fn set_current_tenant(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT set_config($1, $2, true)")
+        .bind(CURRENT_TENANT)
+        .bind(tenant_id.to_string())
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// The row level security statements for one table.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TenantPolicy {
+    table: String,
+    column: String,
+}
+
+impl TenantPolicy {
+    /// `table` may be schema qualified, e.g. `billing.invoice`.
+    pub fn new(table: &str) -> TenantPolicy {
+        TenantPolicy {
+            table: table.to_string(),
+            column: "tenant_id".to_string(),
+        }
+    }
+
+    /// The uuid column holding the tenant id, `tenant_id` by default.
+    pub fn column(mut self, column: &str) -> TenantPolicy {
+        self.column = column.to_string();
+        self
+    }
+
+    fn policy_name(&self) -> String {
+        let table = self.table.rsplit('.').next().unwrap_or_default();
+        quote_ident(&format!("{}_tenant_isolation", table))
+    }
+
+    /// Enables and forces row level security, adds the policy and makes the
+    /// current tenant the column's default, so inserts can leave it out.
+    pub fn up_statements(&self) -> Vec<String> {
+        let table = quote_qualified(&self.table);
+        let column = quote_ident(&self.column);
+        let current = format!(
+            "NULLIF(current_setting('{}', true), '')::uuid",
+            CURRENT_TENANT
+        );
+        vec![
+            format!("ALTER TABLE {} ENABLE ROW LEVEL SECURITY;", table),
+            format!("ALTER TABLE {} FORCE ROW LEVEL SECURITY;", table),
+            format!(
+                "CREATE POLICY {} ON {} USING ({} = {}) WITH CHECK ({} = {});",
+                self.policy_name(),
+                table,
+                column,
+                current,
+                column,
+                current
+            ),
+            format!(
+                "ALTER TABLE {} ALTER COLUMN {} SET DEFAULT {};",
+                table, column, current
+            ),
+        ]
+    }
+
+    /// Undoes [`up_statements`](TenantPolicy::up_statements).
+    pub fn down_statements(&self) -> Vec<String> {
+        let table = quote_qualified(&self.table);
+        vec![
+            format!(
+                "ALTER TABLE {} ALTER COLUMN {} DROP DEFAULT;",
+                table,
+                quote_ident(&self.column)
+            ),
+            format!("DROP POLICY IF EXISTS {} ON {};", self.policy_name(), table),
+            format!("ALTER TABLE {} NO FORCE ROW LEVEL SECURITY;", table),
+            format!("ALTER TABLE {} DISABLE ROW LEVEL SECURITY;", table),
+        ]
+    }
+
+    /// For an up migration, one statement per line.
+    pub fn up_sql(&self) -> String {
+        self.up_statements().join("\n")
+    }
+
+    /// For a down migration, one statement per line.
+    pub fn down_sql(&self) -> String {
+        self.down_statements().join("\n")
+    }
+
+    /// Runs the up statements, for applications not using migration files.
+    pub async fn apply(&self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        for statement in self.up_statements() {
+            sqlx::query(&statement).execute(&mut **tx).await?;
+        }
+        Ok(())
+    }
+
+    pub async fn remove(&self, tx: &mut Transaction<'_, Postgres>) -> Result<(), sqlx::Error> {
+        for statement in self.down_statements() {
+            sqlx::query(&statement).execute(&mut **tx).await?;
+        }
+        Ok(())
+    }
+}
+
+fn quote_ident(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+fn quote_qualified(name: &str) -> String {
+    name.split('.')
+        .map(quote_ident)
+        .collect::<Vec<_>>()
+        .join(".")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn up_and_down() {
+        let p = TenantPolicy::new("billing.invoice").column("owner_id");
+        assert_eq!(
+            p.up_statements(),
+            [
+                r#"ALTER TABLE "billing"."invoice" ENABLE ROW LEVEL SECURITY;"#,
+                r#"ALTER TABLE "billing"."invoice" FORCE ROW LEVEL SECURITY;"#,
+                r#"CREATE POLICY "invoice_tenant_isolation" ON "billing"."invoice" USING ("owner_id" = NULLIF(current_setting('app.current_tenant', true), '')::uuid) WITH CHECK ("owner_id" = NULLIF(current_setting('app.current_tenant', true), '')::uuid);"#,
+                r#"ALTER TABLE "billing"."invoice" ALTER COLUMN "owner_id" SET DEFAULT NULLIF(current_setting('app.current_tenant', true), '')::uuid;"#,
+            ]
+        );
+        assert_eq!(
+            p.down_sql(),
+            [
+                r#"ALTER TABLE "billing"."invoice" ALTER COLUMN "owner_id" DROP DEFAULT;"#,
+                r#"DROP POLICY IF EXISTS "invoice_tenant_isolation" ON "billing"."invoice";"#,
+                r#"ALTER TABLE "billing"."invoice" NO FORCE ROW LEVEL SECURITY;"#,
+                r#"ALTER TABLE "billing"."invoice" DISABLE ROW LEVEL SECURITY;"#,
+            ]
+            .join("\n")
+        );
+    }
+
+    #[test]
+    fn names_are_quoted() {
+        assert_eq!(quote_ident(r#"odd"name"#), r#""odd""name""#);
+        assert_eq!(quote_qualified("a.b"), r#""a"."b""#);
+    }
+}
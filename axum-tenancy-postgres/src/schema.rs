@@ -0,0 +1,230 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Schema per tenant, for tenants whose data must be kept physically apart.
+//!
+//! Each tenant gets a schema named from its id by [`schema_name`], holding
+//! the application's tables for that tenant. The application's migrations
+//! are run in the schema when it is created, and can be run again for every
+//! tenant with [`migrate_all`]. Each schema keeps its own
+//! `_sqlx_migrations` table.
+//!
+//! [`set_search_path`] puts the tenant's schema first for the rest of a
+//! transaction, so unqualified table names are the tenant's while the
+//! axum-tenancy tables are still found in the schemas after it.
+
+use axum_tenancy_core::{
+    admin_core::{tenant_core::TenantSort, user_core::SortDirection},
+    migration::MigrationReport,
+};
+use std::collections::HashMap;
+
+use sqlx::{
+    migrate::{Migrate, MigrateError, Migrator},
+    PgConnection, PgPool, Postgres, Transaction,
+};
+use uuid::Uuid;
+
+use crate::admin_postgres::tenant_postgres;
+
+/// `tenant_` and the id without hyphens.
+pub fn schema_name(tenant_id: &Uuid) -> String {
+    format!("tenant_{}", tenant_id.simple())
+}
+
+/// Puts the tenant's schema at the front of `search_path` until the end of
+/// the transaction.
+pub async fn set_search_path(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "SELECT set_config('search_path', quote_ident($1) || ', ' || current_setting('search_path'), true)",
+    )
+    .bind(schema_name(tenant_id))
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// The first key of the advisory locks taken while creating or migrating a
+/// tenant's schema, the second is `hashtext` of the schema name. Locks taken
+/// with the two key form never clash with single key ones, an application
+/// only has to keep clear of this class. "atsc" in ASCII.
+pub const SCHEMA_LOCK_CLASS: i32 = 0x6174_7363;
+
+// Held until the transaction ends, so two transactions creating or migrating
+// the same tenant's schema take turns. Taking it again in the same
+// transaction doesn't block, and two tenants whose names hash the same only
+// wait for each other.
+async fn lock_schema(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("SELECT pg_advisory_xact_lock($1, hashtext($2))")
+        .bind(SCHEMA_LOCK_CLASS)
+        .bind(schema_name(tenant_id))
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// Creates the tenant's schema if it is missing and runs `migrator` in it.
+pub async fn create_schema(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &Uuid,
+    migrator: Option<&Migrator>,
+) -> Result<(), MigrateError> {
+    lock_schema(tx, tenant_id).await?;
+    sqlx::query(&format!(
+        "CREATE SCHEMA IF NOT EXISTS \"{}\"",
+        schema_name(tenant_id)
+    ))
+    .execute(&mut **tx)
+    .await?;
+    match migrator {
+        Some(migrator) => migrate_schema(tx, tenant_id, migrator).await,
+        None => Ok(()),
+    }
+}
+
+/// Runs `migrator` in the tenant's schema. The search path is put back
+/// afterwards, so the rest of the transaction is unaffected. Holds a
+/// transaction level advisory lock for the tenant, see [`SCHEMA_LOCK_CLASS`],
+/// so concurrent runs for the same tenant wait for each other rather than
+/// racing.
+pub async fn migrate_schema(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &Uuid,
+    migrator: &Migrator,
+) -> Result<(), MigrateError> {
+    lock_schema(tx, tenant_id).await?;
+    let search_path: String = sqlx::query_scalar("SELECT current_setting('search_path')")
+        .fetch_one(&mut **tx)
+        .await?;
+    set_search_path(tx, tenant_id).await?;
+    apply_migrations(tx, migrator).await?;
+    sqlx::query("SELECT set_config('search_path', $1, true)")
+        .bind(search_path)
+        .execute(&mut **tx)
+        .await?;
+    Ok(())
+}
+
+/// What `Migrator::run` does, with `migrate_schema` taking the lock instead.
+/// Its future isn't `Send` when given a connection borrowed from a
+/// transaction, which would stop handlers creating tenants.
+async fn apply_migrations(
+    conn: &mut PgConnection,
+    migrator: &Migrator,
+) -> Result<(), MigrateError> {
+    conn.ensure_migrations_table().await?;
+    if let Some(version) = conn.dirty_version().await? {
+        return Err(MigrateError::Dirty(version));
+    }
+    let applied: HashMap<_, _> = conn
+        .list_applied_migrations()
+        .await?
+        .into_iter()
+        .map(|m| (m.version, m))
+        .collect();
+    if !migrator.ignore_missing {
+        if let Some(version) = applied
+            .keys()
+            .find(|v| !migrator.iter().any(|m| m.version == **v))
+        {
+            return Err(MigrateError::VersionMissing(*version));
+        }
+    }
+    for migration in migrator.iter() {
+        if migration.migration_type.is_down_migration() {
+            continue;
+        }
+        match applied.get(&migration.version) {
+            Some(done) if done.checksum != migration.checksum => {
+                return Err(MigrateError::VersionMismatch(migration.version));
+            }
+            Some(_) => {}
+            None => {
+                conn.apply(migration).await?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Drops the tenant's schema and everything in it.
+pub async fn drop_schema(
+    tx: &mut Transaction<'_, Postgres>,
+    tenant_id: &Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(&format!(
+        "DROP SCHEMA IF EXISTS \"{}\" CASCADE",
+        schema_name(tenant_id)
+    ))
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Creates any missing schema and runs `migrator` for every tenant, each in
+/// its own transaction. Only failing to list the tenants is an error, a
+/// tenant that fails is in the report.
+pub async fn migrate_all(
+    pool: &PgPool,
+    migrator: &Migrator,
+) -> Result<MigrationReport, sqlx::Error> {
+    let mut tx = pool.begin().await?;
+    let tenants =
+        tenant_postgres::load_all_sorted(&mut tx, TenantSort::TenantName, SortDirection::Asc)
+            .await?;
+    tx.commit().await?;
+
+    let mut report = MigrationReport::default();
+    for tenant in tenants {
+        let result = async {
+            let mut tx = pool.begin().await?;
+            create_schema(&mut tx, &tenant.tenant_id, Some(migrator)).await?;
+            tx.commit().await?;
+            Ok::<_, MigrateError>(())
+        }
+        .await;
+        match result {
+            Ok(()) => report.migrated.push(tenant),
+            Err(e) => report.failed.push((tenant, e)),
+        }
+    }
+    Ok(report)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn schema_names() {
+        let id = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        assert_eq!(schema_name(&id), "tenant_67e5504410b1426f9247bb680e5fe0c8");
+    }
+}
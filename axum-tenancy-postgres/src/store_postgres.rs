@@ -0,0 +1,317 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use async_trait::async_trait;
+use axum_tenancy_core::{
+    admin_core::{
+        invitation_core::{Invitation, InvitationStatus},
+        page_core::Page,
+        role_core::Role,
+        tenant_core::{Tenant, TenantSort},
+        user_core::{Deleted, SortDirection, User, UserOrder, UserQuery},
+        user_tenant_core::{UserTenant, UserTenantSort},
+    },
+    error::TenancyError,
+    store::{InvitationStore, TenantStore, UserStore, UserTenantStore},
+};
+use chrono::{DateTime, Utc};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use crate::admin_postgres::{
+    invitation_postgres, tenant_postgres, user_postgres, user_tenant_postgres,
+};
+
+/// The Postgres implementation of the axum-tenancy storage traits.
+pub struct PostgresStore;
+
+#[async_trait]
+impl UserStore for PostgresStore {
+    type Db = Postgres;
+
+    async fn insert(
+        tx: &mut Transaction<'_, Postgres>,
+        user_name: &str,
+        hash_password: &str,
+        display_name: &str,
+        is_admin: bool,
+        email: &str,
+        mobile_phone: &str,
+        unique_email: bool,
+    ) -> Result<Uuid, TenancyError> {
+        user_postgres::insert(
+            tx,
+            user_name,
+            hash_password,
+            display_name,
+            is_admin,
+            email,
+            mobile_phone,
+            unique_email,
+        )
+        .await
+    }
+
+    async fn load_by_id(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: Uuid,
+        deleted: Deleted,
+    ) -> Result<User, TenancyError> {
+        Ok(user_postgres::load_by_id(tx, user_id, deleted).await?)
+    }
+
+    async fn load_all_sorted(
+        tx: &mut Transaction<'_, Postgres>,
+        order: &UserOrder,
+        deleted: Deleted,
+    ) -> Result<Vec<User>, TenancyError> {
+        Ok(user_postgres::load_all_sorted(tx, order, deleted).await?)
+    }
+
+    async fn load_page(
+        tx: &mut Transaction<'_, Postgres>,
+        q: &UserQuery,
+    ) -> Result<Page<User>, TenancyError> {
+        user_postgres::load_page(tx, q).await
+    }
+
+    async fn update(
+        tx: &mut Transaction<'_, Postgres>,
+        u: &User,
+        unique_email: bool,
+    ) -> Result<Option<i64>, TenancyError> {
+        Ok(user_postgres::update(tx, u, unique_email).await?)
+    }
+
+    async fn load_password_hash(
+        tx: &mut Transaction<'_, Postgres>,
+        user_name: &str,
+    ) -> Result<Option<(Uuid, String)>, TenancyError> {
+        Ok(user_postgres::load_password_hash(tx, user_name).await?)
+    }
+
+    async fn update_password(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &Uuid,
+        hash_password: &str,
+    ) -> Result<u64, TenancyError> {
+        Ok(user_postgres::update_password(tx, user_id, hash_password)
+            .await?
+            .rows_affected())
+    }
+
+    async fn record_login(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &Uuid,
+    ) -> Result<u64, TenancyError> {
+        Ok(user_postgres::record_login(tx, user_id)
+            .await?
+            .rows_affected())
+    }
+
+    async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &Uuid,
+    ) -> Result<u64, TenancyError> {
+        Ok(user_postgres::delete(tx, user_id).await?.rows_affected())
+    }
+
+    async fn deactivate(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &Uuid,
+    ) -> Result<u64, TenancyError> {
+        Ok(user_postgres::deactivate(tx, user_id)
+            .await?
+            .rows_affected())
+    }
+
+    async fn restore(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &Uuid,
+    ) -> Result<u64, TenancyError> {
+        Ok(user_postgres::restore(tx, user_id).await?.rows_affected())
+    }
+
+    async fn recanonicalize(
+        tx: &mut Transaction<'_, Postgres>,
+        unique_email: bool,
+    ) -> Result<u64, TenancyError> {
+        Ok(user_postgres::recanonicalize(tx, unique_email).await?)
+    }
+}
+
+#[async_trait]
+impl TenantStore for PostgresStore {
+    type Db = Postgres;
+
+    async fn insert(
+        tx: &mut Transaction<'_, Postgres>,
+        tenant_name: &str,
+        display_name: &str,
+    ) -> Result<Uuid, TenancyError> {
+        tenant_postgres::insert(tx, tenant_name, display_name).await
+    }
+
+    async fn load_by_id(
+        tx: &mut Transaction<'_, Postgres>,
+        tenant_id: Uuid,
+    ) -> Result<Tenant, TenancyError> {
+        Ok(tenant_postgres::load_by_id(tx, tenant_id).await?)
+    }
+
+    async fn load_by_name(
+        tx: &mut Transaction<'_, Postgres>,
+        tenant_name: &str,
+    ) -> Result<Tenant, TenancyError> {
+        Ok(tenant_postgres::load_by_name(tx, tenant_name).await?)
+    }
+
+    async fn load_all_sorted(
+        tx: &mut Transaction<'_, Postgres>,
+        sort: TenantSort,
+        direction: SortDirection,
+    ) -> Result<Vec<Tenant>, TenancyError> {
+        Ok(tenant_postgres::load_all_sorted(tx, sort, direction).await?)
+    }
+
+    async fn update(tx: &mut Transaction<'_, Postgres>, t: &Tenant) -> Result<u64, TenancyError> {
+        Ok(tenant_postgres::update(tx, t).await?.rows_affected())
+    }
+
+    async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        tenant_id: &Uuid,
+    ) -> Result<u64, TenancyError> {
+        Ok(tenant_postgres::delete(tx, tenant_id)
+            .await?
+            .rows_affected())
+    }
+}
+
+#[async_trait]
+impl UserTenantStore for PostgresStore {
+    type Db = Postgres;
+
+    async fn insert(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &Uuid,
+        tenant_id: &Uuid,
+        role: &Role,
+    ) -> Result<Uuid, TenancyError> {
+        user_tenant_postgres::insert(tx, user_id, tenant_id, role).await
+    }
+
+    async fn load_by_id(
+        tx: &mut Transaction<'_, Postgres>,
+        user_tenant_id: Uuid,
+    ) -> Result<UserTenant, TenancyError> {
+        Ok(user_tenant_postgres::load_by_id(tx, user_tenant_id).await?)
+    }
+
+    async fn load_by_user_and_tenant(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &Uuid,
+        tenant_id: &Uuid,
+    ) -> Result<UserTenant, TenancyError> {
+        Ok(user_tenant_postgres::load_by_user_and_tenant(tx, user_id, tenant_id).await?)
+    }
+
+    async fn load_by_user(
+        tx: &mut Transaction<'_, Postgres>,
+        user_id: &Uuid,
+    ) -> Result<Vec<UserTenant>, TenancyError> {
+        Ok(user_tenant_postgres::load_by_user(tx, user_id).await?)
+    }
+
+    async fn load_all_sorted(
+        tx: &mut Transaction<'_, Postgres>,
+        sort: UserTenantSort,
+        direction: SortDirection,
+    ) -> Result<Vec<UserTenant>, TenancyError> {
+        Ok(user_tenant_postgres::load_all_sorted(tx, sort, direction).await?)
+    }
+
+    async fn update(
+        tx: &mut Transaction<'_, Postgres>,
+        ut: &UserTenant,
+    ) -> Result<u64, TenancyError> {
+        Ok(user_tenant_postgres::update(tx, ut).await?.rows_affected())
+    }
+
+    async fn delete(
+        tx: &mut Transaction<'_, Postgres>,
+        user_tenant_id: &Uuid,
+    ) -> Result<u64, TenancyError> {
+        Ok(user_tenant_postgres::delete(tx, user_tenant_id)
+            .await?
+            .rows_affected())
+    }
+}
+
+#[async_trait]
+impl InvitationStore for PostgresStore {
+    type Db = Postgres;
+
+    async fn insert(
+        tx: &mut Transaction<'_, Postgres>,
+        invitation: &Invitation,
+    ) -> Result<(), TenancyError> {
+        invitation_postgres::insert(tx, invitation).await
+    }
+
+    async fn load_by_id(
+        tx: &mut Transaction<'_, Postgres>,
+        invitation_id: Uuid,
+    ) -> Result<Invitation, TenancyError> {
+        Ok(invitation_postgres::load_by_id(tx, invitation_id).await?)
+    }
+
+    async fn load_by_token_hash(
+        tx: &mut Transaction<'_, Postgres>,
+        token_hash: &str,
+    ) -> Result<Invitation, TenancyError> {
+        Ok(invitation_postgres::load_by_token_hash(tx, token_hash).await?)
+    }
+
+    async fn load_by_tenant(
+        tx: &mut Transaction<'_, Postgres>,
+        tenant_id: &Uuid,
+    ) -> Result<Vec<Invitation>, TenancyError> {
+        Ok(invitation_postgres::load_by_tenant(tx, tenant_id).await?)
+    }
+
+    async fn respond(
+        tx: &mut Transaction<'_, Postgres>,
+        invitation_id: &Uuid,
+        status: InvitationStatus,
+        accepted_by: Option<&Uuid>,
+        now: DateTime<Utc>,
+    ) -> Result<u64, TenancyError> {
+        Ok(
+            invitation_postgres::respond(tx, invitation_id, status, accepted_by, now)
+                .await?
+                .rows_affected(),
+        )
+    }
+}
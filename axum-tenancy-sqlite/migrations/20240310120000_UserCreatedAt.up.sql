@@ -0,0 +1,32 @@
+-- MIT License
+--
+-- Copyright (c) 2024 Dave Warnock
+-- 
+-- Permission is hereby granted, free of charge, to any person obtaining a copy
+-- of this software and associated documentation files (the "Software"), to deal
+-- in the Software without restriction, including without limitation the rights
+-- to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+-- copies of the Software, and to permit persons to whom the Software is
+-- furnished to do so, subject to the following conditions:
+-- 
+-- The above copyright notice and this permission notice shall be included in all
+-- copies or substantial portions of the Software.
+-- 
+-- THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+-- IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+-- FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+-- AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+-- LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+-- OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+-- SOFTWARE.
+
+
+-- ALTER TABLE needs a constant default, so existing rows are set afterwards.
+-- The format matches the RFC 3339 text sqlx writes, which sorts correctly.
+ALTER TABLE user ADD COLUMN created_at DATETIME NOT NULL DEFAULT '1970-01-01T00:00:00+00:00';
+UPDATE user SET created_at = strftime('%Y-%m-%dT%H:%M:%f+00:00', 'now');
+
+-- WITHOUT ROWID tables add the primary key to every index, so these also
+-- cover the user_id tie-break.
+CREATE INDEX IF NOT EXISTS user_email ON user (email);
+CREATE INDEX IF NOT EXISTS user_created_at ON user (created_at);
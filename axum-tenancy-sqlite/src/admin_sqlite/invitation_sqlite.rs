@@ -0,0 +1,183 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use axum_tenancy_core::admin_core::{
+    invitation_core::{Invitation, InvitationStatus},
+    role_core::Role,
+};
+use axum_tenancy_core::error::TenancyError;
+use chrono::{DateTime, Utc};
+use uuid::{fmt::Hyphenated, Uuid};
+
+type DbTransaction<'c> = sqlx::Transaction<'c, sqlx::Sqlite>;
+
+pub async fn insert(tx: &mut DbTransaction<'_>, i: &Invitation) -> Result<(), TenancyError> {
+    let str_invitation_id = i.invitation_id.to_string();
+    let str_tenant_id = i.tenant_id.to_string();
+    let str_role = i.role.as_str();
+    let str_invited_by = i.invited_by.map(|u| u.to_string());
+    let str_status = i.status.as_str();
+    let str_accepted_by = i.accepted_by.map(|u| u.to_string());
+    let r = sqlx::query!(
+        r#"
+        INSERT INTO invitation 
+        (invitation_id, tenant_id, email, role, token_hash, invited_by, status, created_at, expires_at, responded_at, accepted_by) 
+        VALUES
+        ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+        "#,
+        str_invitation_id,
+        str_tenant_id,
+        i.email,
+        str_role,
+        i.token_hash,
+        str_invited_by,
+        str_status,
+        i.created_at,
+        i.expires_at,
+        i.responded_at,
+        str_accepted_by
+    )
+    .execute(&mut **tx)
+    .await;
+
+    match r {
+        Ok(qr) => {
+            if qr.rows_affected() == 1 {
+                Ok(())
+            } else {
+                Err(TenancyError::Database(sqlx::Error::Protocol(format!(
+                    "Insert did not return 1 row affected:{}",
+                    qr.rows_affected()
+                ))))
+            }
+        }
+        Err(e) => Err(e.into()),
+    }
+}
+
+pub async fn load_by_id(
+    tx: &mut DbTransaction<'_>,
+    invitation_id: Uuid,
+) -> Result<Invitation, sqlx::Error> {
+    let str_invitation_id = invitation_id.to_string();
+    sqlx::query_as!(
+        InvitationRow,
+        r#"SELECT invitation_id as "invitation_id: Hyphenated", tenant_id as "tenant_id: Hyphenated", email, role, token_hash, invited_by as "invited_by?: Hyphenated", status, created_at as "created_at: DateTime<Utc>", expires_at as "expires_at: DateTime<Utc>", responded_at as "responded_at?: DateTime<Utc>", accepted_by as "accepted_by?: Hyphenated" from invitation where invitation_id = $1"#,
+        str_invitation_id
+    )
+    .fetch_one(&mut **tx)
+    .await
+    .map(Invitation::from)
+}
+
+pub async fn load_by_token_hash(
+    tx: &mut DbTransaction<'_>,
+    token_hash: &str,
+) -> Result<Invitation, sqlx::Error> {
+    sqlx::query_as!(
+        InvitationRow,
+        r#"SELECT invitation_id as "invitation_id: Hyphenated", tenant_id as "tenant_id: Hyphenated", email, role, token_hash, invited_by as "invited_by?: Hyphenated", status, created_at as "created_at: DateTime<Utc>", expires_at as "expires_at: DateTime<Utc>", responded_at as "responded_at?: DateTime<Utc>", accepted_by as "accepted_by?: Hyphenated" from invitation where token_hash = $1"#,
+        token_hash
+    )
+    .fetch_one(&mut **tx)
+    .await
+    .map(Invitation::from)
+}
+
+pub async fn load_by_tenant(
+    tx: &mut DbTransaction<'_>,
+    tenant_id: &Uuid,
+) -> Result<Vec<Invitation>, sqlx::Error> {
+    let str_tenant_id = tenant_id.to_string();
+    let rows = sqlx::query_as!(
+        InvitationRow,
+        r#"SELECT invitation_id as "invitation_id: Hyphenated", tenant_id as "tenant_id: Hyphenated", email, role, token_hash, invited_by as "invited_by?: Hyphenated", status, created_at as "created_at: DateTime<Utc>", expires_at as "expires_at: DateTime<Utc>", responded_at as "responded_at?: DateTime<Utc>", accepted_by as "accepted_by?: Hyphenated" from invitation where tenant_id = $1 ORDER BY created_at DESC, invitation_id"#,
+        str_tenant_id
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    Ok(rows.into_iter().map(Invitation::from).collect())
+}
+
+pub async fn respond(
+    tx: &mut DbTransaction<'_>,
+    invitation_id: &Uuid,
+    status: InvitationStatus,
+    accepted_by: Option<&Uuid>,
+    now: DateTime<Utc>,
+) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    let str_invitation_id = invitation_id.to_string();
+    let str_status = status.as_str();
+    let str_accepted_by = accepted_by.map(|u| u.to_string());
+    sqlx::query!(
+        r#"
+        UPDATE invitation 
+            SET status = $2,
+                accepted_by = $3,
+                responded_at = $4
+            WHERE
+                invitation_id = $1 AND status = 'pending'
+        "#,
+        str_invitation_id,
+        str_status,
+        str_accepted_by,
+        now
+    )
+    .execute(&mut **tx)
+    .await
+}
+
+// nullable text ids can't be converted by query_as!, so rows are read
+// through this
+struct InvitationRow {
+    invitation_id: Hyphenated,
+    tenant_id: Hyphenated,
+    email: String,
+    role: String,
+    token_hash: String,
+    invited_by: Option<Hyphenated>,
+    status: String,
+    created_at: DateTime<Utc>,
+    expires_at: DateTime<Utc>,
+    responded_at: Option<DateTime<Utc>>,
+    accepted_by: Option<Hyphenated>,
+}
+
+impl From<InvitationRow> for Invitation {
+    fn from(r: InvitationRow) -> Invitation {
+        Invitation {
+            invitation_id: r.invitation_id.into(),
+            tenant_id: r.tenant_id.into(),
+            email: r.email,
+            role: Role::from(r.role),
+            token_hash: r.token_hash,
+            invited_by: r.invited_by.map(Uuid::from),
+            status: InvitationStatus::from(r.status),
+            created_at: r.created_at,
+            expires_at: r.expires_at,
+            responded_at: r.responded_at,
+            accepted_by: r.accepted_by.map(Uuid::from),
+        }
+    }
+}
@@ -22,4 +22,7 @@
 # SOFTWARE.
 */
 
+pub mod invitation_sqlite;
+pub mod tenant_sqlite;
 pub mod user_sqlite;
+pub mod user_tenant_sqlite;
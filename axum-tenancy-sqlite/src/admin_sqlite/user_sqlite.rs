@@ -22,30 +22,41 @@
 # SOFTWARE.
 */
 
-use anyhow::{anyhow, Error, Result};
-use axum_tenancy_core::admin_core::user_core::{SortDirection, User, UserSort};
-use uuid::Uuid;
-use sqlx::FromRow;
+use axum_tenancy_core::admin_core::{
+    page_core::{like_pattern, Page},
+    user_core::{Deleted, User, UserCursor, UserKeyValue, UserOrder, UserQuery},
+    validation_core::{canonical, canonical_email},
+};
+use axum_tenancy_core::error::TenancyError;
+use chrono::{DateTime, Utc};
+use sqlx::{FromRow, QueryBuilder, Sqlite};
+use uuid::{fmt::Hyphenated, Uuid};
 
 type DbTransaction<'c> = sqlx::Transaction<'c, sqlx::Sqlite>;
 
+#[allow(clippy::too_many_arguments)]
 pub async fn insert(
     tx: &mut DbTransaction<'_>,
     user_name: &str,
+    hash_password: &str,
     display_name: &str,
     is_admin: bool,
     email: &str,
     mobile_phone: &str,
-) -> Result<uuid::Uuid, Error> {
+    unique_email: bool,
+) -> Result<uuid::Uuid, TenancyError> {
     let user_id = Uuid::new_v4();
     let str_user_id = user_id.to_string();
-    let hash_password = "".to_string();
+    // set here, a column default would not be in the format sqlx reads
+    let now = Utc::now();
+    let user_name_key = canonical(user_name);
+    let email_key = canonical_email(email, unique_email);
     let r = sqlx::query!(
         r#"
         INSERT INTO user 
-        (user_id, user_name, hash_password, display_name, is_admin, email, mobile_phone) 
+        (user_id, user_name, hash_password, display_name, is_admin, email, mobile_phone, created_at, updated_at, canonical_user_name, canonical_email) 
         VALUES
-        ($1, $2, $3, $4, $5, $6, $7)
+        ($1, $2, $3, $4, $5, $6, $7, $8, $8, $9, $10)
         "#,
         str_user_id,
         user_name,
@@ -53,7 +64,10 @@ pub async fn insert(
         display_name,
         is_admin,
         email,
-        mobile_phone
+        mobile_phone,
+        now,
+        user_name_key,
+        email_key
     )
     .execute(&mut **tx)
     .await;
@@ -63,10 +77,10 @@ pub async fn insert(
             if qr.rows_affected() == 1 {
                 Ok(user_id)
             } else {
-                Err(anyhow!(
+                Err(TenancyError::Database(sqlx::Error::Protocol(format!(
                     "Insert did not return 1 row affected:{}",
                     qr.rows_affected()
-                ))
+                ))))
             }
         }
         Err(e) => Err(e.into()),
@@ -76,76 +90,348 @@ pub async fn insert(
 pub async fn load_by_id(
     tx: &mut DbTransaction<'_>,
     user_id: Uuid,
+    deleted: Deleted,
 ) -> Result<User, sqlx::Error> {
+    let str_user_id = user_id.to_string();
+    let included = deleted.included();
     sqlx::query_as!(
         User,
-        r#"SELECT user_id, user_name, display_name, is_admin, email, mobile_phone from user where user_id = $1"#,
-        &user_id.to_string()
+        r#"SELECT user_id as "user_id: Hyphenated", user_name, hash_password, display_name, is_admin as "is_admin!", email, mobile_phone, created_at as "created_at: DateTime<Utc>", updated_at as "updated_at: DateTime<Utc>", last_login_at as "last_login_at?: DateTime<Utc>", deleted_at as "deleted_at?: DateTime<Utc>", version from user where user_id = $1 AND ($2 OR deleted_at IS NULL)"#,
+        str_user_id,
+        included
     )
     .fetch_one(&mut **tx)
     .await
 }
 
+// text ids can't be read straight into a Uuid, so dynamic queries go
+// through this
+#[derive(FromRow)]
+struct UserRow {
+    user_id: Hyphenated,
+    user_name: String,
+    hash_password: String,
+    display_name: String,
+    is_admin: bool,
+    email: String,
+    mobile_phone: String,
+    created_at: DateTime<Utc>,
+    updated_at: DateTime<Utc>,
+    last_login_at: Option<DateTime<Utc>>,
+    deleted_at: Option<DateTime<Utc>>,
+    version: i64,
+}
+
+impl From<UserRow> for User {
+    fn from(r: UserRow) -> User {
+        User {
+            user_id: r.user_id.into(),
+            user_name: r.user_name,
+            hash_password: r.hash_password,
+            display_name: r.display_name,
+            is_admin: r.is_admin,
+            email: r.email,
+            mobile_phone: r.mobile_phone,
+            created_at: r.created_at,
+            updated_at: r.updated_at,
+            last_login_at: r.last_login_at,
+            deleted_at: r.deleted_at,
+            version: r.version,
+        }
+    }
+}
+
+const SELECT_USER: &str = "SELECT user_id, user_name, hash_password, display_name, is_admin, email, mobile_phone, created_at, updated_at, last_login_at, deleted_at, version FROM user";
+
 pub async fn load_all_sorted(
     tx: &mut DbTransaction<'_>,
-    sort: UserSort,
-    direction: SortDirection,
+    order: &UserOrder,
+    deleted: Deleted,
 ) -> Result<Vec<User>, sqlx::Error> {
-    match direction {
-        SortDirection::Asc => {
-            sqlx::query_as!(
-                User,
-                r#"SELECT user_id, user_name, display_name, is_admin, email, mobile_phone FROM user ORDER BY 
-                    CASE 
-                          WHEN $1 = 'user_name' THEN user_name
-                          WHEN $1 = 'display_name' THEN display_name
-                    END ASC
-                "#,
-                sort.as_str()
-            )
-            .fetch_all(&mut **tx)
-            .await
-        }
-        SortDirection::Desc => {
-            sqlx::query_as!(
-                User,
-                r#"SELECT user_id, user_name, display_name, is_admin, email, mobile_phone FROM user ORDER BY 
-                    CASE 
-                          WHEN $1 = 'user_name' THEN user_name
-                          WHEN $1 = 'display_name' THEN display_name
-                    END DESC
-                "#,
-                sort.as_str()
-            )
-            .fetch_all(&mut **tx)
-            .await
+    let mut qb = QueryBuilder::<Sqlite>::new(SELECT_USER);
+    if !deleted.included() {
+        qb.push(" WHERE deleted_at IS NULL");
+    }
+    qb.push(" ORDER BY ").push(order.to_sql());
+    let rows = qb.build_query_as::<UserRow>().fetch_all(&mut **tx).await?;
+    Ok(rows.into_iter().map(User::from).collect())
+}
+
+// pushes the WHERE for the filters, returns what joins the next condition
+fn push_filters(qb: &mut QueryBuilder<'_, Sqlite>, q: &UserQuery) -> &'static str {
+    let mut next = " WHERE ";
+    if !q.deleted.included() {
+        qb.push(next).push("deleted_at IS NULL");
+        next = " AND ";
+    }
+    if let Some(is_admin) = q.is_admin {
+        qb.push(next).push("is_admin = ").push_bind(is_admin);
+        next = " AND ";
+    }
+    if let Some(search) = &q.search {
+        let pattern = like_pattern(search);
+        qb.push(next).push("(");
+        for (i, column) in ["user_name", "display_name", "email"].iter().enumerate() {
+            if i > 0 {
+                qb.push(" OR ");
+            }
+            qb.push(column).push(" LIKE ").push_bind(pattern.clone());
+            qb.push(" ESCAPE '\\'");
         }
+        qb.push(")");
+        next = " AND ";
+    }
+    next
+}
+
+fn push_value(qb: &mut QueryBuilder<'_, Sqlite>, value: &UserKeyValue) {
+    match value {
+        UserKeyValue::Text(s) => qb.push_bind(s.clone()),
+        UserKeyValue::Time(t) => qb.push_bind(*t),
+        UserKeyValue::Id(id) => qb.push_bind(id.to_string()),
+        UserKeyValue::Null => qb.push("NULL"),
+    };
+}
+
+fn page_query<'a>(
+    prefix: &str,
+    q: &UserQuery,
+    cursor: Option<&UserCursor>,
+) -> QueryBuilder<'a, Sqlite> {
+    let mut qb = QueryBuilder::new(prefix);
+    qb.push(SELECT_USER);
+    let next = push_filters(&mut qb, q);
+    if let Some(cursor) = cursor {
+        qb.push(next);
+        cursor.push_after(&mut qb, &q.order, push_value);
     }
+    qb.push(" ORDER BY ").push(q.order.to_sql());
+    // one extra row shows whether there is a next page
+    qb.push(" LIMIT ").push_bind(i64::from(q.limit) + 1);
+    qb.push(" OFFSET ").push_bind(i64::from(q.offset));
+    qb
+}
+
+/// One page of users, see `UserQuery`.
+pub async fn load_page(
+    tx: &mut DbTransaction<'_>,
+    q: &UserQuery,
+) -> Result<Page<User>, TenancyError> {
+    let cursor = q.cursor()?;
+    let rows = page_query("", q, cursor.as_ref())
+        .build_query_as::<UserRow>()
+        .fetch_all(&mut **tx)
+        .await?;
+    let users = rows.into_iter().map(User::from).collect();
+    let mut count = QueryBuilder::<Sqlite>::new("SELECT COUNT(*) FROM user");
+    push_filters(&mut count, q);
+    let total: i64 = count.build_query_scalar().fetch_one(&mut **tx).await?;
+    Ok(Page::new(users, q.limit, total, |u| {
+        UserCursor::after(&q.order, u).encode(&q.order)
+    }))
 }
 
+/// The `EXPLAIN QUERY PLAN` details for the `load_page` query, for the index
+/// tests.
+#[cfg(feature = "test-support")]
+pub async fn explain_page(
+    tx: &mut DbTransaction<'_>,
+    q: &UserQuery,
+) -> Result<Vec<String>, TenancyError> {
+    use sqlx::Row;
+
+    let cursor = q.cursor()?;
+    let rows = page_query("EXPLAIN QUERY PLAN ", q, cursor.as_ref())
+        .build()
+        .fetch_all(&mut **tx)
+        .await?;
+    Ok(rows.iter().map(|r| r.get::<String, _>("detail")).collect())
+}
+
+/// Only updates the row when `u.version` is still current, returning the new
+/// version.
 pub async fn update(
     tx: &mut DbTransaction<'_>,
     u: &User,
-) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    unique_email: bool,
+) -> Result<Option<i64>, sqlx::Error> {
     let str_user_id = &u.user_id.to_string();
-    sqlx::query!(
+    let now = Utc::now();
+    let user_name_key = canonical(&u.user_name);
+    let email_key = canonical_email(&u.email, unique_email);
+    sqlx::query_scalar!(
         r#"
         UPDATE "user" 
             SET user_name = $2,
                 display_name = $3, 
                 is_admin = $4, 
                 email = $5, 
-                mobile_phone = $6 
+                mobile_phone = $6,
+                updated_at = $7,
+                version = version + 1,
+                canonical_user_name = $9,
+                canonical_email = $10
             WHERE
-                user_id = $1
+                user_id = $1 AND version = $8
+            RETURNING version
         "#,
         str_user_id,
         u.user_name,
         u.display_name,
         u.is_admin,
         u.email,
-        u.mobile_phone
+        u.mobile_phone,
+        now,
+        u.version,
+        user_name_key,
+        email_key
+    )
+    .fetch_optional(&mut **tx)
+    .await
+}
+
+/// `user_name` is compared in canonical form.
+pub async fn load_password_hash(
+    tx: &mut DbTransaction<'_>,
+    user_name: &str,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let user_name_key = canonical(user_name);
+    let r = sqlx::query!(
+        r#"SELECT user_id as "user_id: Hyphenated", hash_password FROM user WHERE canonical_user_name = $1 AND deleted_at IS NULL"#,
+        user_name_key
+    )
+    .fetch_optional(&mut **tx)
+    .await?;
+    Ok(r.map(|row| (row.user_id.into(), row.hash_password)))
+}
+
+pub async fn update_password(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+    hash_password: &str,
+) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    let str_user_id = &user_id.to_string();
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        UPDATE "user" 
+            SET hash_password = $2,
+                updated_at = $3,
+                version = version + 1
+            WHERE
+                user_id = $1
+        "#,
+        str_user_id,
+        hash_password,
+        now
+    )
+    .execute(&mut **tx)
+    .await
+}
+
+/// Called after a successful login, it is not an edit so `updated_at` is
+/// left alone.
+pub async fn record_login(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    let str_user_id = &user_id.to_string();
+    let now = Utc::now();
+    sqlx::query!(
+        r#"UPDATE "user" SET last_login_at = $2 WHERE user_id = $1"#,
+        str_user_id,
+        now
+    )
+    .execute(&mut **tx)
+    .await
+}
+
+pub async fn delete(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    let str_user_id = &user_id.to_string();
+    // don't rely on PRAGMA foreign_keys being on for the cascade
+    sqlx::query!(r#"DELETE FROM user_tenant WHERE user_id = $1"#, str_user_id)
+        .execute(&mut **tx)
+        .await?;
+    sqlx::query!(r#"DELETE FROM user WHERE user_id = $1"#, str_user_id)
+        .execute(&mut **tx)
+        .await
+}
+
+pub async fn deactivate(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    let str_user_id = &user_id.to_string();
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        UPDATE "user" 
+            SET deleted_at = $2,
+                updated_at = $2,
+                version = version + 1
+            WHERE
+                user_id = $1 AND deleted_at IS NULL
+        "#,
+        str_user_id,
+        now
+    )
+    .execute(&mut **tx)
+    .await
+}
+
+pub async fn restore(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+) -> Result<sqlx::sqlite::SqliteQueryResult, sqlx::Error> {
+    let str_user_id = &user_id.to_string();
+    let now = Utc::now();
+    sqlx::query!(
+        r#"
+        UPDATE "user" 
+            SET deleted_at = NULL,
+                updated_at = $2,
+                version = version + 1
+            WHERE
+                user_id = $1 AND deleted_at IS NOT NULL
+        "#,
+        str_user_id,
+        now
     )
     .execute(&mut **tx)
     .await
 }
+
+/// Stores the canonical user name and email of every user whose stored forms
+/// differ from what `canonical` gives now, returning how many were changed.
+/// Leaves `version` alone as the user has not been edited.
+pub async fn recanonicalize(
+    tx: &mut DbTransaction<'_>,
+    unique_email: bool,
+) -> Result<u64, sqlx::Error> {
+    let rows = sqlx::query!(
+        r#"SELECT user_id, user_name, email, canonical_user_name, canonical_email FROM user"#
+    )
+    .fetch_all(&mut **tx)
+    .await?;
+    let mut changed = 0;
+    for row in rows {
+        let user_name_key = canonical(&row.user_name);
+        let email_key = canonical_email(&row.email, unique_email);
+        if user_name_key == row.canonical_user_name && email_key == row.canonical_email {
+            continue;
+        }
+        changed += sqlx::query!(
+            r#"UPDATE user SET canonical_user_name = $2, canonical_email = $3 WHERE user_id = $1"#,
+            row.user_id,
+            user_name_key,
+            email_key
+        )
+        .execute(&mut **tx)
+        .await?
+        .rows_affected();
+    }
+    Ok(changed)
+}
@@ -0,0 +1,451 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Database file per tenant, for small deployments where backing up or
+//! moving one tenant should mean copying one file.
+//!
+//! The users, tenants and memberships stay in the main database. Each
+//! tenant's application tables are in `tenant_<id>.sqlite` under a directory
+//! given to [`TenantPools`], which opens a pool per file when it is first
+//! used. The number of open pools is bounded, the least recently used is
+//! closed to make room, and a pool unused for the idle timeout is closed.
+//! A pool with a connection checked out is never closed, so the bound is
+//! exceeded while every open tenant is in the middle of a transaction.
+//! Only [`TenantPools::create`] makes a file, opening a missing one fails.
+
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::PathBuf,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+use axum_tenancy_core::{
+    admin_core::{tenant_core::TenantSort, user_core::SortDirection},
+    migration::MigrationReport,
+};
+use sqlx::{
+    migrate::{MigrateError, Migrator},
+    sqlite::{SqliteConnectOptions, SqlitePoolOptions},
+    Connection, SqliteConnection, SqlitePool,
+};
+use tokio::task::JoinHandle;
+use uuid::Uuid;
+
+use crate::admin_sqlite::tenant_sqlite;
+
+#[derive(Debug)]
+struct OpenPool {
+    pool: SqlitePool,
+    last_used: Instant,
+}
+
+impl OpenPool {
+    // closing it would fail whatever the connections are being used for
+    fn in_use(&self) -> bool {
+        self.pool.size() as usize > self.pool.num_idle()
+    }
+}
+
+// closing waits for connections in use to be returned, so it is left to a
+// task rather than holding up whoever needed the room
+fn close_later(closing: Vec<SqlitePool>) {
+    if let Ok(runtime) = tokio::runtime::Handle::try_current() {
+        for pool in closing {
+            runtime.spawn(async move { pool.close().await });
+        }
+    }
+}
+
+/// The per tenant pools, keyed by tenant id.
+#[derive(Debug)]
+pub struct TenantPools {
+    dir: PathBuf,
+    max_open: usize,
+    idle_timeout: Duration,
+    max_connections: u32,
+    pools: Mutex<HashMap<Uuid, OpenPool>>,
+}
+
+impl TenantPools {
+    /// Files in `dir`, at most 32 pools of 2 connections open, each dropped
+    /// after 5 minutes unused.
+    pub fn new(dir: impl Into<PathBuf>) -> TenantPools {
+        TenantPools {
+            dir: dir.into(),
+            max_open: 32,
+            idle_timeout: Duration::from_secs(5 * 60),
+            max_connections: 2,
+            pools: Mutex::new(HashMap::new()),
+        }
+    }
+
+    pub fn max_open(mut self, max_open: usize) -> TenantPools {
+        self.max_open = max_open.max(1);
+        self
+    }
+
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> TenantPools {
+        self.idle_timeout = idle_timeout;
+        self
+    }
+
+    /// Connections in each tenant's pool.
+    pub fn max_connections(mut self, max_connections: u32) -> TenantPools {
+        self.max_connections = max_connections.max(1);
+        self
+    }
+
+    pub fn dir(&self) -> &PathBuf {
+        &self.dir
+    }
+
+    /// `tenant_` and the id without hyphens, in the directory.
+    pub fn file_path(&self, tenant_id: &Uuid) -> PathBuf {
+        self.dir
+            .join(format!("tenant_{}.sqlite", tenant_id.simple()))
+    }
+
+    fn options(&self, tenant_id: &Uuid) -> SqliteConnectOptions {
+        SqliteConnectOptions::new().filename(self.file_path(tenant_id))
+    }
+
+    // removes the pools unused for the idle timeout
+    fn take_idle(&self, pools: &mut HashMap<Uuid, OpenPool>, now: Instant) -> Vec<SqlitePool> {
+        let ids: Vec<Uuid> = pools
+            .iter()
+            .filter(|(_, p)| now.duration_since(p.last_used) >= self.idle_timeout && !p.in_use())
+            .map(|(id, _)| *id)
+            .collect();
+        ids.iter()
+            .filter_map(|id| pools.remove(id))
+            .map(|p| p.pool)
+            .collect()
+    }
+
+    /// The tenant's pool, opening it if needed. Connecting is lazy, and
+    /// fails if the tenant's file hasn't been made by [`create`](Self::create).
+    /// Idle pools, and the least recently used when there are too many, are
+    /// closed in the background unless they have connections checked out.
+    /// One can still be closed between being returned here and used, see
+    /// [`is_closed`](sqlx::Pool::is_closed); calling this again opens it
+    /// afresh.
+    pub fn pool(&self, tenant_id: &Uuid) -> SqlitePool {
+        let now = Instant::now();
+        let mut pools = self.pools.lock().unwrap_or_else(|e| e.into_inner());
+        let mut closing = self.take_idle(&mut pools, now);
+        if let Some(open) = pools.get_mut(tenant_id) {
+            open.last_used = now;
+            let pool = open.pool.clone();
+            drop(pools);
+            close_later(closing);
+            return pool;
+        }
+        // pools in use may have pushed past the bound, so catch up here
+        while pools.len() >= self.max_open {
+            let oldest = pools
+                .iter()
+                .filter(|(_, p)| !p.in_use())
+                .min_by_key(|(_, p)| p.last_used)
+                .map(|(id, _)| *id);
+            match oldest.and_then(|id| pools.remove(&id)) {
+                Some(open) => closing.push(open.pool),
+                None => break,
+            }
+        }
+        let options = self.options(tenant_id).create_if_missing(false);
+        let pool = SqlitePoolOptions::new()
+            .max_connections(self.max_connections)
+            .min_connections(0)
+            .idle_timeout(self.idle_timeout)
+            .connect_lazy_with(options);
+        pools.insert(
+            *tenant_id,
+            OpenPool {
+                pool: pool.clone(),
+                last_used: now,
+            },
+        );
+        drop(pools);
+        close_later(closing);
+        pool
+    }
+
+    /// How many tenant pools are open.
+    pub fn open_count(&self) -> usize {
+        self.pools.lock().unwrap_or_else(|e| e.into_inner()).len()
+    }
+
+    /// Closes the pools unused for longer than the idle timeout. They are
+    /// also closed whenever a pool is opened, this releases the files of a
+    /// quiet server, see [`spawn_close_idle`](Self::spawn_close_idle).
+    pub async fn close_idle(&self) {
+        let idle = {
+            let mut pools = self.pools.lock().unwrap_or_else(|e| e.into_inner());
+            self.take_idle(&mut pools, Instant::now())
+        };
+        for pool in idle {
+            pool.close().await;
+        }
+    }
+
+    /// Runs [`close_idle`](Self::close_idle) every idle timeout, at most once
+    /// a second, until the pools are dropped.
+    pub fn spawn_close_idle(self: &Arc<Self>) -> JoinHandle<()> {
+        let pools = Arc::downgrade(self);
+        let period = self.idle_timeout.max(Duration::from_secs(1));
+        tokio::spawn(async move {
+            let mut interval = tokio::time::interval(period);
+            interval.tick().await;
+            loop {
+                interval.tick().await;
+                match pools.upgrade() {
+                    Some(pools) => pools.close_idle().await,
+                    None => break,
+                }
+            }
+        })
+    }
+
+    /// Creates the tenant's file if it is missing and runs `migrator` in it.
+    pub async fn create(
+        &self,
+        tenant_id: &Uuid,
+        migrator: Option<&Migrator>,
+    ) -> Result<(), MigrateError> {
+        fs::create_dir_all(&self.dir).map_err(sqlx::Error::Io)?;
+        SqliteConnection::connect_with(&self.options(tenant_id).create_if_missing(true))
+            .await?
+            .close()
+            .await?;
+        match migrator {
+            Some(migrator) => migrator.run(&self.pool(tenant_id)).await,
+            None => Ok(()),
+        }
+    }
+
+    /// Closes the tenant's pool and deletes its file.
+    pub async fn remove(&self, tenant_id: &Uuid) -> Result<(), sqlx::Error> {
+        let open = self
+            .pools
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .remove(tenant_id);
+        if let Some(open) = open {
+            open.pool.close().await;
+        }
+        let path = self.file_path(tenant_id);
+        for suffix in ["", "-wal", "-shm", "-journal"] {
+            let mut file = path.clone().into_os_string();
+            file.push(suffix);
+            match fs::remove_file(&file) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e.into()),
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// Creates any missing file and runs `migrator` for every tenant in the
+    /// main database `catalog`. Only failing to list the tenants is an
+    /// error, a tenant that fails is in the report.
+    pub async fn migrate_all(
+        &self,
+        catalog: &SqlitePool,
+        migrator: &Migrator,
+    ) -> Result<MigrationReport, sqlx::Error> {
+        let mut tx = catalog.begin().await?;
+        let tenants =
+            tenant_sqlite::load_all_sorted(&mut tx, TenantSort::TenantName, SortDirection::Asc)
+                .await?;
+        tx.commit().await?;
+
+        let mut report = MigrationReport::default();
+        for tenant in tenants {
+            match self.create(&tenant.tenant_id, Some(migrator)).await {
+                Ok(()) => report.migrated.push(tenant),
+                Err(e) => report.failed.push((tenant, e)),
+            }
+        }
+        Ok(report)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn file_names() {
+        let id = Uuid::parse_str("67e55044-10b1-426f-9247-bb680e5fe0c8").unwrap();
+        assert_eq!(
+            TenantPools::new("/var/lib/hannah").file_path(&id),
+            PathBuf::from("/var/lib/hannah/tenant_67e5504410b1426f9247bb680e5fe0c8.sqlite")
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use std::env;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn pools_are_bounded_and_files_removed() {
+        let dir = env::temp_dir().join(format!("axum_tenancy_files_{}", Uuid::new_v4().simple()));
+        let pools = TenantPools::new(&dir).max_open(2);
+        let ids = [Uuid::new_v4(), Uuid::new_v4(), Uuid::new_v4()];
+        for id in &ids {
+            pools.create(id, None).await.unwrap();
+            assert!(pools.file_path(id).exists());
+        }
+        let first = pools.pool(&ids[0]);
+        pools.pool(&ids[1]);
+        pools.pool(&ids[2]);
+        assert_eq!(pools.open_count(), 2);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(first.is_closed());
+
+        // the least recently used was closed, using it again reopens it
+        let pool = pools.pool(&ids[0]);
+        assert_eq!(pools.open_count(), 2);
+        sqlx::query("CREATE TABLE note (body TEXT)")
+            .execute(&pool)
+            .await
+            .unwrap();
+        let other: i64 =
+            sqlx::query_scalar("SELECT count(*) FROM sqlite_master WHERE name = 'note'")
+                .fetch_one(&pools.pool(&ids[1]))
+                .await
+                .unwrap();
+        assert_eq!(other, 0);
+
+        for id in &ids {
+            pools.remove(id).await.unwrap();
+            assert!(!pools.file_path(id).exists());
+        }
+        assert_eq!(pools.open_count(), 0);
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pools_in_use_are_not_closed() {
+        let dir = env::temp_dir().join(format!("axum_tenancy_files_{}", Uuid::new_v4().simple()));
+        let pools = Arc::new(TenantPools::new(&dir).max_open(2));
+        let ids = [
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+            Uuid::new_v4(),
+        ];
+        for id in &ids {
+            pools.create(id, None).await.unwrap();
+        }
+        let first = pools.pool(&ids[0]);
+        let mut tx = first.begin().await.unwrap();
+        sqlx::query("CREATE TABLE note (body TEXT)")
+            .execute(&mut *tx)
+            .await
+            .unwrap();
+
+        // more tenants than max_open at once, the first is the least
+        // recently used but has a transaction open
+        let others: Vec<_> = ids[1..3]
+            .iter()
+            .map(|id| {
+                let (pools, id) = (pools.clone(), *id);
+                tokio::spawn(async move {
+                    let mut tx = pools.pool(&id).begin().await.unwrap();
+                    sqlx::query("SELECT 1").execute(&mut *tx).await.unwrap();
+                    tx.commit().await.unwrap();
+                })
+            })
+            .collect();
+        for other in others {
+            other.await.unwrap();
+        }
+        assert_eq!(pools.open_count(), 3);
+        tokio::time::sleep(Duration::from_millis(50)).await;
+        assert!(!first.is_closed());
+        first.begin().await.unwrap();
+        tx.commit().await.unwrap();
+
+        // nothing is in use any more, so the next one gets back under the bound
+        pools.pool(&ids[3]);
+        assert_eq!(pools.open_count(), 2);
+        for id in &ids {
+            pools.remove(id).await.unwrap();
+        }
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn only_create_makes_files() {
+        let dir = env::temp_dir().join(format!("axum_tenancy_files_{}", Uuid::new_v4().simple()));
+        fs::create_dir_all(&dir).unwrap();
+        let pools = TenantPools::new(&dir);
+        let id = Uuid::new_v4();
+        assert!(pools.pool(&id).acquire().await.is_err());
+        assert!(!pools.file_path(&id).exists());
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn idle_pools_are_closed() {
+        let dir = env::temp_dir().join(format!("axum_tenancy_files_{}", Uuid::new_v4().simple()));
+        let pools = TenantPools::new(&dir).idle_timeout(Duration::from_millis(50));
+        let id = Uuid::new_v4();
+        pools.create(&id, None).await.unwrap();
+        let pool = pools.pool(&id);
+        assert_eq!(pools.open_count(), 1);
+        tokio::time::sleep(Duration::from_millis(100)).await;
+        pools.close_idle().await;
+        assert_eq!(pools.open_count(), 0);
+        assert!(pool.is_closed());
+        pools.remove(&id).await.unwrap();
+        fs::remove_dir(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn idle_pools_are_closed_on_a_timer() {
+        let dir = env::temp_dir().join(format!("axum_tenancy_files_{}", Uuid::new_v4().simple()));
+        let pools = Arc::new(TenantPools::new(&dir).idle_timeout(Duration::from_millis(50)));
+        let closer = pools.spawn_close_idle();
+        let id = Uuid::new_v4();
+        pools.create(&id, None).await.unwrap();
+        pools.pool(&id);
+        tokio::time::sleep(Duration::from_millis(2500)).await;
+        assert_eq!(pools.open_count(), 0);
+
+        pools.remove(&id).await.unwrap();
+        fs::remove_dir(&dir).unwrap();
+        drop(pools);
+        tokio::time::timeout(Duration::from_secs(3), closer)
+            .await
+            .unwrap()
+            .unwrap();
+    }
+}
@@ -22,4 +22,13 @@
 # SOFTWARE.
 */
 
+use sqlx::migrate::Migrator;
+
 pub mod admin_sqlite;
+pub mod files;
+mod store_sqlite;
+
+pub use store_sqlite::SqliteStore;
+
+/// The migrations in this crate, embedded at compile time.
+pub static MIGRATOR: Migrator = sqlx::migrate!();
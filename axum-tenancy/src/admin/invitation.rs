@@ -0,0 +1,420 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use axum_tenancy_core::{
+    admin_core::{
+        invitation_core::{hash_token, new_token, Invitation, InvitationStatus},
+        password_core::PasswordPolicy,
+        role_core::Role,
+        validation_core::{normalize_email, UserRules, ValidationErrors},
+    },
+    error::TenancyError,
+    store::InvitationStore,
+};
+use chrono::{Duration, Utc};
+use uuid::Uuid;
+
+use super::{user, user_tenant, DbTransaction};
+
+/// Invites `email` to the tenant with `role`. Returns the invitation and its
+/// token, which goes in the link sent to the invitee. Only the token's hash
+/// is stored so this is the only chance to read it.
+pub async fn invite(
+    tx: &mut DbTransaction<'_>,
+    tenant_id: &Uuid,
+    email: &str,
+    role: &Role,
+    invited_by: Option<&Uuid>,
+    valid_for: Duration,
+) -> Result<(Invitation, String), TenancyError> {
+    let email = match normalize_email(email) {
+        Ok(email) if !email.is_empty() => email,
+        Ok(_) => return Err(invalid_email("is required")),
+        Err(e) => return Err(invalid_email(e)),
+    };
+    let token = new_token();
+    let now = Utc::now();
+    let invitation = Invitation {
+        invitation_id: Uuid::new_v4(),
+        tenant_id: *tenant_id,
+        email,
+        role: role.clone(),
+        token_hash: hash_token(&token),
+        invited_by: invited_by.copied(),
+        status: InvitationStatus::Pending,
+        created_at: now,
+        expires_at: now + valid_for,
+        responded_at: None,
+        accepted_by: None,
+    };
+    with_store!(tx, |tx, Store| <Store as InvitationStore>::insert(
+        tx,
+        &invitation
+    )
+    .await)?;
+    Ok((invitation, token))
+}
+
+fn invalid_email(message: impl Into<String>) -> TenancyError {
+    let mut errors = ValidationErrors::new();
+    errors.add("email", message);
+    errors.into()
+}
+
+pub async fn load_by_id(
+    tx: &mut DbTransaction<'_>,
+    invitation_id: Uuid,
+) -> Result<Invitation, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as InvitationStore>::load_by_id(
+        tx,
+        invitation_id
+    )
+    .await)
+}
+
+/// `TenancyError::NotFound` for an unknown token.
+pub async fn load_by_token(
+    tx: &mut DbTransaction<'_>,
+    token: &str,
+) -> Result<Invitation, TenancyError> {
+    let token_hash = hash_token(token);
+    with_store!(tx, |tx, Store| {
+        <Store as InvitationStore>::load_by_token_hash(tx, &token_hash).await
+    })
+}
+
+/// As `load_by_token`, but fails with `InvitationUsed` or
+/// `InvitationExpired` unless the invitation can still be answered.
+pub async fn load_open(
+    tx: &mut DbTransaction<'_>,
+    token: &str,
+) -> Result<Invitation, TenancyError> {
+    let invitation = load_by_token(tx, token).await?;
+    if invitation.status != InvitationStatus::Pending {
+        Err(TenancyError::InvitationUsed)
+    } else if !invitation.is_open(Utc::now()) {
+        Err(TenancyError::InvitationExpired)
+    } else {
+        Ok(invitation)
+    }
+}
+
+/// Newest first, whatever their status.
+pub async fn load_by_tenant(
+    tx: &mut DbTransaction<'_>,
+    tenant_id: &Uuid,
+) -> Result<Vec<Invitation>, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as InvitationStore>::load_by_tenant(
+        tx, tenant_id
+    )
+    .await)
+}
+
+async fn respond(
+    tx: &mut DbTransaction<'_>,
+    invitation_id: &Uuid,
+    status: InvitationStatus,
+    accepted_by: Option<&Uuid>,
+) -> Result<(), TenancyError> {
+    let now = Utc::now();
+    let n = with_store!(tx, |tx, Store| {
+        <Store as InvitationStore>::respond(tx, invitation_id, status, accepted_by, now).await
+    })?;
+    if n == 1 {
+        Ok(())
+    } else {
+        Err(TenancyError::InvitationUsed)
+    }
+}
+
+/// Adds an existing user to the tenant with the invited role and spends the
+/// token. The token is the proof, the user's email need not match the
+/// invitation's. Returns the new UserTenant id, a user who already belongs
+/// to the tenant gets `DuplicateUserTenant`.
+pub async fn accept(
+    tx: &mut DbTransaction<'_>,
+    token: &str,
+    user_id: &Uuid,
+) -> Result<Uuid, TenancyError> {
+    let invitation = load_open(tx, token).await?;
+    respond(
+        tx,
+        &invitation.invitation_id,
+        InvitationStatus::Accepted,
+        Some(user_id),
+    )
+    .await?;
+    user_tenant::insert(tx, user_id, &invitation.tenant_id, &invitation.role).await
+}
+
+/// Creates a user with the invitation's email, as `user::insert`, then
+/// accepts for them. Returns the user and UserTenant ids.
+#[allow(clippy::too_many_arguments)]
+pub async fn accept_new_user(
+    tx: &mut DbTransaction<'_>,
+    token: &str,
+    user_name: &str,
+    password: &str,
+    display_name: &str,
+    mobile_phone: &str,
+    rules: &UserRules,
+    policy: &PasswordPolicy,
+) -> Result<(Uuid, Uuid), TenancyError> {
+    let invitation = load_open(tx, token).await?;
+    let user_id = user::insert(
+        tx,
+        user_name,
+        password,
+        display_name,
+        false,
+        &invitation.email,
+        mobile_phone,
+        rules,
+        policy,
+    )
+    .await?;
+    let user_tenant_id = accept(tx, token, &user_id).await?;
+    Ok((user_id, user_tenant_id))
+}
+
+pub async fn decline(tx: &mut DbTransaction<'_>, token: &str) -> Result<(), TenancyError> {
+    let invitation = load_open(tx, token).await?;
+    respond(
+        tx,
+        &invitation.invitation_id,
+        InvitationStatus::Declined,
+        None,
+    )
+    .await
+}
+
+/// Withdraws a pending invitation of the tenant, expired or not.
+pub async fn revoke(
+    tx: &mut DbTransaction<'_>,
+    tenant_id: &Uuid,
+    invitation_id: &Uuid,
+) -> Result<(), TenancyError> {
+    let invitation = load_by_id(tx, *invitation_id).await?;
+    if invitation.tenant_id != *tenant_id {
+        return Err(TenancyError::NotFound);
+    }
+    respond(tx, invitation_id, InvitationStatus::Revoked, None).await
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use axum_tenancy_core::admin_core::user_core::Deleted;
+    use test_context::test_context;
+
+    use super::*;
+    use crate::admin::{tenant, test_support::*};
+
+    async fn setup(tx: &mut DbTransaction<'_>) -> (Uuid, Uuid) {
+        let user_id = user::insert(
+            tx,
+            "Dave",
+            "password",
+            "Dave Warnock",
+            false,
+            "dave@example.com",
+            "",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        let tenant_id = tenant::insert(tx, "Hannah", "Hannah Church").await.unwrap();
+        (user_id, tenant_id)
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn invite_then_accept(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let (user_id, tenant_id) = setup(&mut tx).await;
+
+        let (invitation, token) = invite(
+            &mut tx,
+            &tenant_id,
+            " Anna@Example.COM ",
+            &Role::Viewer,
+            Some(&user_id),
+            Duration::days(1),
+        )
+        .await
+        .unwrap();
+        assert_eq!(invitation.email, "Anna@example.com");
+        assert_ne!(invitation.token_hash, token);
+
+        let loaded = load_by_token(&mut tx, &token).await.unwrap();
+        assert_eq!(loaded.invitation_id, invitation.invitation_id);
+        assert_eq!(loaded.invited_by, Some(user_id));
+        assert_eq!(loaded.role, Role::Viewer);
+        assert!(matches!(
+            load_by_token(&mut tx, "not a token").await,
+            Err(TenancyError::NotFound)
+        ));
+
+        let (anna_id, user_tenant_id) = accept_new_user(
+            &mut tx,
+            &token,
+            "Anna",
+            "password",
+            "Anna Warnock",
+            "",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        let anna = user::load_by_id(&mut tx, anna_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert_eq!(anna.email, "Anna@example.com");
+        let ut = user_tenant::load_by_id(&mut tx, user_tenant_id)
+            .await
+            .unwrap();
+        assert_eq!(ut.tenant_id, tenant_id);
+        assert_eq!(ut.role, Role::Viewer);
+
+        let loaded = load_by_id(&mut tx, invitation.invitation_id).await.unwrap();
+        assert_eq!(loaded.status, InvitationStatus::Accepted);
+        assert_eq!(loaded.accepted_by, Some(anna_id));
+        assert!(loaded.responded_at.is_some());
+
+        // single use
+        assert!(matches!(
+            accept(&mut tx, &token, &user_id).await,
+            Err(TenancyError::InvitationUsed)
+        ));
+        assert!(matches!(
+            decline(&mut tx, &token).await,
+            Err(TenancyError::InvitationUsed)
+        ));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn existing_user_accepts(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let (user_id, tenant_id) = setup(&mut tx).await;
+
+        let (_, token) = invite(
+            &mut tx,
+            &tenant_id,
+            "someone@example.com",
+            &Role::Admin,
+            None,
+            Duration::days(1),
+        )
+        .await
+        .unwrap();
+        let user_tenant_id = accept(&mut tx, &token, &user_id).await.unwrap();
+        let ut = user_tenant::load_by_user_and_tenant(&mut tx, &user_id, &tenant_id)
+            .await
+            .unwrap();
+        assert_eq!(ut.user_tenant_id, user_tenant_id);
+        assert_eq!(ut.role, Role::Admin);
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn decline_revoke_and_expire(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let (user_id, tenant_id) = setup(&mut tx).await;
+        let (t, d) = (tenant_id, Duration::days(1));
+        let (_, declined) = invite(&mut tx, &t, "a@example.com", &Role::Member, None, d)
+            .await
+            .unwrap();
+        decline(&mut tx, &declined).await.unwrap();
+        assert!(matches!(
+            accept(&mut tx, &declined, &user_id).await,
+            Err(TenancyError::InvitationUsed)
+        ));
+
+        let (revoked, token) = invite(&mut tx, &t, "b@example.com", &Role::Member, None, d)
+            .await
+            .unwrap();
+        let other_tenant = tenant::insert(&mut tx, "Other", "Other Church")
+            .await
+            .unwrap();
+        assert!(matches!(
+            revoke(&mut tx, &other_tenant, &revoked.invitation_id).await,
+            Err(TenancyError::NotFound)
+        ));
+        revoke(&mut tx, &tenant_id, &revoked.invitation_id)
+            .await
+            .unwrap();
+        assert!(matches!(
+            accept(&mut tx, &token, &user_id).await,
+            Err(TenancyError::InvitationUsed)
+        ));
+        assert!(matches!(
+            revoke(&mut tx, &tenant_id, &revoked.invitation_id).await,
+            Err(TenancyError::InvitationUsed)
+        ));
+
+        let (_, expired) = invite(
+            &mut tx,
+            &t,
+            "c@example.com",
+            &Role::Member,
+            None,
+            Duration::seconds(-1),
+        )
+        .await
+        .unwrap();
+        assert!(matches!(
+            accept(&mut tx, &expired, &user_id).await,
+            Err(TenancyError::InvitationExpired)
+        ));
+
+        assert!(matches!(
+            invite(&mut tx, &t, "not an email", &Role::Member, None, d).await,
+            Err(TenancyError::InvalidFields(e)) if e.get("email").is_some()
+        ));
+
+        let all = load_by_tenant(&mut tx, &tenant_id).await.unwrap();
+        let statuses: Vec<_> = all.iter().map(|i| i.status).collect();
+        assert_eq!(all.len(), 3);
+        assert!(statuses.contains(&InvitationStatus::Declined));
+        assert!(statuses.contains(&InvitationStatus::Revoked));
+        assert!(statuses.contains(&InvitationStatus::Pending));
+
+        Ok(())
+    }
+}
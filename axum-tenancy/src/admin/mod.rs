@@ -22,4 +22,206 @@
 # SOFTWARE.
 */
 
+//! Admin functions for the User, Tenant, UserTenant and Invitation tables.
+//!
+//! Each backend enabled with a cargo feature is compiled in, the pool the
+//! application passes in decides which one is used.
+
+use axum_tenancy_core::ActiveDb;
+use sqlx::{
+    migrate::{Migrate, MigrateError},
+    Acquire,
+};
+use uuid::Uuid;
+
+use crate::config::TenantIsolation;
+
+#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
+compile_error!(
+    "At least one of the features \"sqlite\" or \"postgres\" must be enabled for this crate."
+);
+
+#[derive(Clone, Debug)]
+pub enum DbPool {
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlx::SqlitePool),
+    #[cfg(feature = "postgres")]
+    Postgres(sqlx::PgPool),
+}
+
+// only ever lives for the length of a request, boxing would just add an
+// allocation per transaction
+#[allow(clippy::large_enum_variant)]
+#[derive(Debug)]
+pub enum DbTransaction<'c> {
+    #[cfg(feature = "sqlite")]
+    Sqlite(sqlx::Transaction<'c, sqlx::Sqlite>),
+    #[cfg(feature = "postgres")]
+    Postgres(sqlx::Transaction<'c, sqlx::Postgres>),
+}
+
+impl DbPool {
+    pub fn active_db(&self) -> ActiveDb {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(_) => ActiveDb::Sqlite,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(_) => ActiveDb::Postgres,
+        }
+    }
+
+    /// Runs the embedded migrations for the pool's backend, returning
+    /// whether any had not been run before.
+    pub async fn migrate(&self) -> Result<bool, MigrateError> {
+        let before = self.applied_migrations().await?;
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => axum_tenancy_sqlite::MIGRATOR.run(pool).await?,
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => axum_tenancy_postgres::MIGRATOR.run(pool).await?,
+        }
+        Ok(self.applied_migrations().await? != before)
+    }
+
+    async fn applied_migrations(&self) -> Result<usize, MigrateError> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => {
+                let mut conn = pool.acquire().await?;
+                conn.ensure_migrations_table().await?;
+                Ok(conn.list_applied_migrations().await?.len())
+            }
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => {
+                let mut conn = pool.acquire().await?;
+                conn.ensure_migrations_table().await?;
+                Ok(conn.list_applied_migrations().await?.len())
+            }
+        }
+    }
+
+    pub async fn begin(&self) -> Result<DbTransaction<'static>, sqlx::Error> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => Ok(DbTransaction::Sqlite(pool.begin().await?)),
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => Ok(DbTransaction::Postgres(pool.begin().await?)),
+        }
+    }
+
+    /// A transaction for the work of one tenant. On Postgres it sets
+    /// `app.current_tenant`, so tables given a `TenantPolicy` (see
+    /// `axum_tenancy::rls`) only show that tenant's rows, and with
+    /// [`TenantIsolation::Schema`] puts the tenant's schema first in the
+    /// search path. SQLite has no row level security, queries there must
+    /// filter by tenant themselves. [`TenantIsolation::File`] needs the
+    /// tenant's own pool, so is only handled by `TenantDb`.
+    pub async fn begin_for_tenant(
+        &self,
+        tenant_id: &Uuid,
+        isolation: TenantIsolation,
+    ) -> Result<DbTransaction<'static>, sqlx::Error> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbPool::Sqlite(pool) => match isolation {
+                TenantIsolation::Shared => {
+                    let _ = tenant_id;
+                    Ok(DbTransaction::Sqlite(pool.begin().await?))
+                }
+                TenantIsolation::Schema => Err(sqlx::Error::Configuration(
+                    "schema isolation needs Postgres".into(),
+                )),
+                TenantIsolation::File => Err(sqlx::Error::Configuration(
+                    "file isolation opens each tenant's database through TenantDb".into(),
+                )),
+            },
+            #[cfg(feature = "postgres")]
+            DbPool::Postgres(pool) => {
+                if isolation == TenantIsolation::File {
+                    return Err(sqlx::Error::Configuration(
+                        "file isolation needs SQLite".into(),
+                    ));
+                }
+                let mut tx = pool.begin().await?;
+                axum_tenancy_postgres::rls::set_current_tenant(&mut tx, tenant_id).await?;
+                if isolation == TenantIsolation::Schema {
+                    axum_tenancy_postgres::schema::set_search_path(&mut tx, tenant_id).await?;
+                }
+                Ok(DbTransaction::Postgres(tx))
+            }
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl From<sqlx::SqlitePool> for DbPool {
+    fn from(pool: sqlx::SqlitePool) -> DbPool {
+        DbPool::Sqlite(pool)
+    }
+}
+
+#[cfg(feature = "postgres")]
+impl From<sqlx::PgPool> for DbPool {
+    fn from(pool: sqlx::PgPool) -> DbPool {
+        DbPool::Postgres(pool)
+    }
+}
+
+impl DbTransaction<'_> {
+    /// A savepoint, rolling it back undoes only what was done through it.
+    /// Postgres refuses further statements in a transaction after an error,
+    /// so run anything expected to fail (like a duplicate insert) in one.
+    pub async fn begin(&mut self) -> Result<DbTransaction<'_>, sqlx::Error> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbTransaction::Sqlite(tx) => Ok(DbTransaction::Sqlite(tx.begin().await?)),
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(tx) => Ok(DbTransaction::Postgres(tx.begin().await?)),
+        }
+    }
+
+    pub async fn commit(self) -> Result<(), sqlx::Error> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbTransaction::Sqlite(tx) => tx.commit().await,
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(tx) => tx.commit().await,
+        }
+    }
+
+    pub async fn rollback(self) -> Result<(), sqlx::Error> {
+        match self {
+            #[cfg(feature = "sqlite")]
+            DbTransaction::Sqlite(tx) => tx.rollback().await,
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(tx) => tx.rollback().await,
+        }
+    }
+}
+
+/// Runs `$call` with `$tx` bound to the backend's sqlx transaction and
+/// `$store` naming the backend's store type.
+macro_rules! with_store {
+    ($tx:expr, |$inner:ident, $store:ident| $call:expr) => {
+        match $tx {
+            #[cfg(feature = "sqlite")]
+            $crate::admin::DbTransaction::Sqlite($inner) => {
+                type $store = axum_tenancy_sqlite::SqliteStore;
+                $call
+            }
+            #[cfg(feature = "postgres")]
+            $crate::admin::DbTransaction::Postgres($inner) => {
+                type $store = axum_tenancy_postgres::PostgresStore;
+                $call
+            }
+        }
+    };
+}
+
+pub mod invitation;
+pub mod tenant;
 pub mod user;
+pub mod user_tenant;
+
+#[cfg(test)]
+pub(crate) mod test_support;
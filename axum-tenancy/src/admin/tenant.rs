@@ -0,0 +1,283 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use axum_tenancy_core::{
+    admin_core::{
+        tenant_core::{Tenant, TenantSort},
+        user_core::SortDirection,
+    },
+    error::TenancyError,
+    store::TenantStore,
+};
+use sqlx::migrate::Migrator;
+use uuid::Uuid;
+
+use super::DbTransaction;
+
+pub async fn insert(
+    tx: &mut DbTransaction<'_>,
+    tenant_name: &str,
+    display_name: &str,
+) -> Result<uuid::Uuid, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as TenantStore>::insert(
+        tx,
+        tenant_name,
+        display_name
+    )
+    .await)
+}
+
+pub async fn load_by_id(
+    tx: &mut DbTransaction<'_>,
+    tenant_id: Uuid,
+) -> Result<Tenant, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as TenantStore>::load_by_id(
+        tx, tenant_id
+    )
+    .await)
+}
+
+pub async fn load_by_name(
+    tx: &mut DbTransaction<'_>,
+    tenant_name: &str,
+) -> Result<Tenant, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as TenantStore>::load_by_name(
+        tx,
+        tenant_name
+    )
+    .await)
+}
+
+pub async fn load_all_sorted(
+    tx: &mut DbTransaction<'_>,
+    sort: TenantSort,
+    direction: SortDirection,
+) -> Result<Vec<Tenant>, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as TenantStore>::load_all_sorted(
+        tx, sort, direction
+    )
+    .await)
+}
+
+pub async fn update(
+    tx: &mut DbTransaction<'_>,
+    tenant_id: &Uuid,
+    tenant_name: &str,
+    display_name: &str,
+) -> Result<u64, TenancyError> {
+    let t = Tenant {
+        tenant_id: *tenant_id,
+        tenant_name: tenant_name.to_string(),
+        display_name: display_name.to_string(),
+    };
+    with_store!(tx, |tx, Store| <Store as TenantStore>::update(tx, &t).await)
+}
+
+/// Delete a tenant along with every UserTenant giving access to it.
+pub async fn delete(tx: &mut DbTransaction<'_>, tenant_id: &Uuid) -> Result<u64, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as TenantStore>::delete(
+        tx, tenant_id
+    )
+    .await)
+}
+
+/// Creates the tenant's own schema and runs `migrator` in it, Postgres only.
+pub async fn create_schema(
+    tx: &mut DbTransaction<'_>,
+    tenant_id: &Uuid,
+    migrator: Option<&Migrator>,
+) -> Result<(), TenancyError> {
+    match tx {
+        #[cfg(feature = "sqlite")]
+        DbTransaction::Sqlite(_) => {
+            let _ = (tenant_id, migrator);
+            Err(no_schemas())
+        }
+        #[cfg(feature = "postgres")]
+        DbTransaction::Postgres(tx) => {
+            Ok(axum_tenancy_postgres::schema::create_schema(tx, tenant_id, migrator).await?)
+        }
+    }
+}
+
+/// Drops the tenant's schema with everything in it, Postgres only.
+pub async fn drop_schema(tx: &mut DbTransaction<'_>, tenant_id: &Uuid) -> Result<(), TenancyError> {
+    match tx {
+        #[cfg(feature = "sqlite")]
+        DbTransaction::Sqlite(_) => {
+            let _ = tenant_id;
+            Err(no_schemas())
+        }
+        #[cfg(feature = "postgres")]
+        DbTransaction::Postgres(tx) => {
+            Ok(axum_tenancy_postgres::schema::drop_schema(tx, tenant_id).await?)
+        }
+    }
+}
+
+#[cfg(feature = "sqlite")]
+fn no_schemas() -> TenancyError {
+    sqlx::Error::Configuration("schema isolation needs Postgres".into()).into()
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use test_context::test_context;
+
+    use super::*;
+    use crate::admin::test_support::*;
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_tenant_no_dup_tenant_name(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let tenant_result = insert(&mut tx, "Hannah", "Prayer of Hannah").await;
+        assert_eq!(&tenant_result.is_ok(), &true);
+        assert!(matches!(
+            insert(&mut tx, "Hannah", "not Prayer of Hannah").await,
+            Err(TenancyError::DuplicateTenantName)
+        ));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_tenant_no_dup_display_name(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let tenant_result = insert(&mut tx, "Hannah", "Prayer of Hannah").await;
+        assert_eq!(&tenant_result.is_ok(), &true);
+        assert!(matches!(
+            insert(&mut tx, "NotHannah", "Prayer of Hannah").await,
+            Err(TenancyError::DuplicateTenantDisplayName)
+        ));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_then_check_load_tenant(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let tenant_result = insert(&mut tx, "Hannah", "Prayer of Hannah").await;
+        assert_eq!(&tenant_result.is_ok(), &true);
+
+        let inserted_uuid = tenant_result.unwrap_or_default();
+        assert_ne!(&inserted_uuid.to_string(), ""); // uuid must not be empty
+
+        let load_result = load_by_id(&mut tx, inserted_uuid).await;
+        assert_eq!(&load_result.is_ok(), &true);
+
+        let loaded_tenant = load_result.unwrap_or_default();
+        assert_eq!(&loaded_tenant.tenant_id, &inserted_uuid);
+        assert_eq!(&loaded_tenant.tenant_name.to_string(), &"Hannah");
+        assert_eq!(&loaded_tenant.display_name.to_string(), &"Prayer of Hannah");
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_update_then_check(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let insert_result = insert(&mut tx, "Hannah", "Prayer of Hannah").await;
+        assert_eq!(&insert_result.is_ok(), &true);
+        let inserted_uuid = insert_result.unwrap_or_default();
+
+        let update_result = update(&mut tx, &inserted_uuid, "Samuel", "Book of Samuel").await;
+        assert_eq!(&update_result.is_ok(), &true);
+        assert_eq!(update_result.unwrap(), 1);
+
+        let loaded_tenant = load_by_id(&mut tx, inserted_uuid).await.unwrap_or_default();
+        assert_eq!(&loaded_tenant.tenant_id, &inserted_uuid);
+        assert_eq!(&loaded_tenant.tenant_name.to_string(), &"Samuel");
+        assert_eq!(&loaded_tenant.display_name.to_string(), &"Book of Samuel");
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_two_check_load_all(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let inserted_uuid1 = insert(&mut tx, "zHannah", "Prayer of Hannah")
+            .await
+            .unwrap_or_default();
+        let inserted_uuid2 = insert(&mut tx, "Samuel", "Book of Samuel")
+            .await
+            .unwrap_or_default();
+
+        let load_result =
+            load_all_sorted(&mut tx, TenantSort::TenantName, SortDirection::Asc).await;
+        assert_eq!(&load_result.is_ok(), &true);
+        let vec_tenants = &load_result.unwrap();
+        assert_eq!(&vec_tenants.len(), &2usize);
+        assert_eq!(&vec_tenants[0].tenant_id, &inserted_uuid2);
+        assert_eq!(&vec_tenants[1].tenant_id, &inserted_uuid1);
+
+        let load_result =
+            load_all_sorted(&mut tx, TenantSort::DisplayName, SortDirection::Desc).await;
+        let vec_tenants = &load_result.unwrap();
+        assert_eq!(&vec_tenants[0].tenant_id, &inserted_uuid1);
+        assert_eq!(&vec_tenants[1].tenant_id, &inserted_uuid2);
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_then_delete(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let inserted_uuid = insert(&mut tx, "Hannah", "Prayer of Hannah")
+            .await
+            .unwrap_or_default();
+
+        let delete_result = delete(&mut tx, &inserted_uuid).await;
+        assert_eq!(delete_result.unwrap(), 1);
+        assert!(matches!(
+            load_by_id(&mut tx, inserted_uuid).await,
+            Err(TenancyError::NotFound)
+        ));
+
+        Ok(())
+    }
+}
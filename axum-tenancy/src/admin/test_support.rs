@@ -0,0 +1,185 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Shared database setup for the admin tests.
+
+use std::env;
+
+use axum_tenancy_core::admin_core::{
+    password_core::{PasswordHashParams, PasswordPolicy},
+    validation_core::UserRules,
+};
+use dotenvy::dotenv;
+use test_context::AsyncTestContext;
+use tokio::sync::OnceCell;
+
+use super::DbPool;
+
+pub(crate) struct TenancyTestContext {
+    _value: String,
+}
+
+// cheapest argon2 settings, the defaults make the tests crawl in debug builds
+pub(crate) const TEST_PASSWORD_PARAMS: PasswordHashParams = PasswordHashParams {
+    memory_kib: 8,
+    iterations: 1,
+    parallelism: 1,
+};
+
+// any password will do, with the cheap hashing
+pub(crate) const TEST_PASSWORD_POLICY: PasswordPolicy = PasswordPolicy {
+    min_length: 1,
+    hash: TEST_PASSWORD_PARAMS,
+};
+
+// lets the tests use one letter or uuid suffixed user names and local numbers
+// like 01234567891
+pub(crate) const TEST_USER_RULES: UserRules = UserRules {
+    user_name_min_length: 1,
+    user_name_max_length: 64,
+    default_country_code: Some(44),
+    unique_email: false,
+};
+
+// SQLite first, with both features enabled the tests run against it.
+//
+// Each test has its own runtime, so each gets its own pool: connections left
+// in a pool that outlives the runtime are never closed, and a Postgres
+// transaction a test didn't finish would hold its locks for the rest of the
+// run. The migrations only run once.
+cfg_if::cfg_if! {
+    if #[cfg(feature = "sqlite")] {
+        use sqlx::sqlite::SqlitePoolOptions;
+        static TEST_DB_MIGRATED: OnceCell<()> = OnceCell::const_new();
+
+        async fn connect_sqlite(url: &str, migrated: &OnceCell<()>) -> DbPool {
+            let pool = SqlitePoolOptions::new()
+                .max_connections(5)
+                .connect(url)
+                .await
+                .expect("Could not create sqlite test db pool");
+            migrated
+                .get_or_init(|| async {
+                    axum_tenancy_sqlite::MIGRATOR
+                        .run(&pool)
+                        .await
+                        .expect("Sqlite Migration failed");
+                })
+                .await;
+            DbPool::Sqlite(pool)
+        }
+
+        pub(crate) async fn get_test_db_pool() -> DbPool {
+            dotenv().expect(".env file not found");
+            let database_url: String =
+                env::var("SQLITE_TEST_DATABASE_URL").expect("env missing SQLITE_TEST_DATABASE_URL");
+            connect_sqlite(&database_url, &TEST_DB_MIGRATED).await
+        }
+
+        static COMMITTED_TEST_DB_MIGRATED: OnceCell<()> = OnceCell::const_new();
+
+        /// For tests that have to commit, a fresh database file per test run so
+        /// the rows don't show up in the rollback only tests.
+        pub(crate) async fn get_committed_test_db_pool() -> DbPool {
+            let path = env::temp_dir()
+                .join(format!("axum_tenancy_committed_{}.sqlite", std::process::id()));
+            if !COMMITTED_TEST_DB_MIGRATED.initialized() {
+                let _ = std::fs::remove_file(&path);
+            }
+            connect_sqlite(
+                &format!("sqlite:{}?mode=rwc", path.display()),
+                &COMMITTED_TEST_DB_MIGRATED,
+            )
+            .await
+        }
+    } else {
+        use std::str::FromStr;
+
+        use sqlx::postgres::{PgConnectOptions, PgPoolOptions};
+        static TEST_DB_MIGRATED: OnceCell<()> = OnceCell::const_new();
+
+        fn postgres_test_options() -> PgConnectOptions {
+            dotenv().expect(".env file not found");
+            let database_url: String =
+                env::var("POSTGRES_TEST_DATABASE_URL").expect("env missing POSTGRES_TEST_DATABASE_URL");
+            PgConnectOptions::from_str(&database_url).expect("Invalid POSTGRES_TEST_DATABASE_URL")
+        }
+
+        async fn connect_postgres(options: PgConnectOptions, migrated: &OnceCell<()>) -> DbPool {
+            let pool = PgPoolOptions::new()
+                .max_connections(5)
+                .connect_with(options)
+                .await
+                .expect("Could not create postgres test db pool");
+            migrated
+                .get_or_init(|| async {
+                    axum_tenancy_postgres::MIGRATOR
+                        .run(&pool)
+                        .await
+                        .expect("Postgres Migration failed");
+                })
+                .await;
+            DbPool::Postgres(pool)
+        }
+
+        pub(crate) async fn get_test_db_pool() -> DbPool {
+            connect_postgres(postgres_test_options(), &TEST_DB_MIGRATED).await
+        }
+
+        static COMMITTED_TEST_DB_MIGRATED: OnceCell<()> = OnceCell::const_new();
+
+        /// For tests that have to commit, kept in its own schema so the
+        /// rows don't show up in the rollback only tests.
+        pub(crate) async fn get_committed_test_db_pool() -> DbPool {
+            let options = postgres_test_options().options([("search_path", "committed_tests")]);
+            if !COMMITTED_TEST_DB_MIGRATED.initialized() {
+                let pool = PgPoolOptions::new()
+                    .max_connections(1)
+                    .connect_with(options.clone())
+                    .await
+                    .expect("Could not create postgres committed test db pool");
+                sqlx::query("CREATE SCHEMA IF NOT EXISTS committed_tests")
+                    .execute(&pool)
+                    .await
+                    .expect("Could not create postgres committed_tests schema");
+            }
+            connect_postgres(options, &COMMITTED_TEST_DB_MIGRATED).await
+        }
+    }
+}
+
+#[async_trait::async_trait]
+impl AsyncTestContext for TenancyTestContext {
+    async fn setup() -> TenancyTestContext {
+        let _pool = get_test_db_pool();
+
+        TenancyTestContext {
+            _value: "hello world".to_string(),
+        }
+    }
+
+    async fn teardown(self) {
+        // no teardown at the moment
+    }
+}
@@ -22,153 +22,246 @@
 # SOFTWARE.
 */
 
-use anyhow::{Error, Result};
-use axum_tenancy_core::admin_core::user_core::{SortDirection, User, UserSort};
+use axum_tenancy_core::{
+    admin_core::{
+        page_core::Page,
+        password_core::{
+            hash_password, verify_password_hash, PasswordHashParams, PasswordPolicy,
+            PasswordVerification,
+        },
+        user_core::{Deleted, User, UserOrder, UserQuery},
+        validation_core::{UserFields, UserRules, ValidationErrors},
+    },
+    error::TenancyError,
+    store::UserStore,
+};
 use uuid::Uuid;
 
-#[cfg(not(any(feature = "sqlite", feature = "postgres")))]
-compile_error!("Either feature \"sqlite\" or \"postgres\" must be enabled for this crate.");
-
-#[cfg(feature = "postgres")]
-use axum_tenancy_postgres::admin_postgres::user_postgres as user_db;
-#[cfg(feature = "sqlite")]
-use axum_tenancy_sqlite::admin_sqlite::user_sqlite as user_db;
-
-
-#[cfg(feature = "postgres")]
-type DbTransaction<'c> = sqlx::Transaction<'c, sqlx::Postgres>;
-#[cfg(feature = "sqlite")]
-type DbTransaction<'c> = sqlx::Transaction<'c, sqlx::Sqlite>;
+use super::DbTransaction;
 
+/// The fields are checked and normalized with `rules` first, see
+/// `UserFields::normalize`, and the password with `policy`. Every bad field
+/// is reported at once.
+#[allow(clippy::too_many_arguments)]
 pub async fn insert(
     tx: &mut DbTransaction<'_>,
     user_name: &str,
+    password: &str,
     display_name: &str,
     is_admin: bool,
     email: &str,
     mobile_phone: &str,
-) -> Result<uuid::Uuid, Error> {
-    user_db::insert(tx, user_name, display_name, is_admin, email, mobile_phone).await
+    rules: &UserRules,
+    policy: &PasswordPolicy,
+) -> Result<uuid::Uuid, TenancyError> {
+    let (f, mut errors) =
+        match UserFields::new(user_name, display_name, email, mobile_phone).normalize(rules) {
+            Ok(f) => (Some(f), ValidationErrors::new()),
+            Err(errors) => (None, errors),
+        };
+    if let Err(message) = policy.check(password) {
+        errors.add("password", message);
+    }
+    let f = match f {
+        Some(f) if errors.is_empty() => f,
+        _ => return Err(errors.into()),
+    };
+    let hash = hash_password(password, &policy.hash)?;
+    with_store!(tx, |tx, Store| {
+        <Store as UserStore>::insert(
+            tx,
+            &f.user_name,
+            &hash,
+            &f.display_name,
+            is_admin,
+            &f.email,
+            &f.mobile_phone,
+            rules.unique_email,
+        )
+        .await
+    })
 }
 
-pub async fn load_by_id(tx: &mut DbTransaction<'_>, user_id: Uuid) -> Result<User, sqlx::Error> {
-    user_db::load_by_id(tx, user_id).await
+/// Deactivated users are only returned with `Deleted::Include`.
+pub async fn load_by_id(
+    tx: &mut DbTransaction<'_>,
+    user_id: Uuid,
+    deleted: Deleted,
+) -> Result<User, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserStore>::load_by_id(
+        tx, user_id, deleted
+    )
+    .await)
 }
+
+/// `order` is a `UserOrder` or a `(UserSort, SortDirection)` pair.
 pub async fn load_all_sorted(
     tx: &mut DbTransaction<'_>,
-    sort: UserSort,
-    direction: SortDirection,
-) -> Result<Vec<User>, sqlx::Error> {
-    user_db::load_all_sorted(tx, sort, direction).await
+    order: impl Into<UserOrder>,
+    deleted: Deleted,
+) -> Result<Vec<User>, TenancyError> {
+    let order = order.into();
+    with_store!(tx, |tx, Store| <Store as UserStore>::load_all_sorted(
+        tx, &order, deleted
+    )
+    .await)
 }
 
+/// One page of users matching `q`, with the total count and a cursor for the
+/// next page.
+pub async fn load_page(
+    tx: &mut DbTransaction<'_>,
+    q: &UserQuery,
+) -> Result<Page<User>, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserStore>::load_page(tx, q).await)
+}
+
+/// `version` is the `User::version` the edit was made from, the new version is
+/// returned. If the user has been changed since then nothing is written and
+/// the error is `TenancyError::Conflict`. The fields are checked as for
+/// `insert`.
+#[allow(clippy::too_many_arguments)]
 pub async fn update(
     tx: &mut DbTransaction<'_>,
     user_id: &Uuid,
+    version: i64,
     user_name: &str,
     display_name: &str,
     is_admin: bool,
     email: &str,
     mobile_phone: &str,
-) -> Result<u64, Error> {
+    rules: &UserRules,
+) -> Result<i64, TenancyError> {
+    let f = UserFields::new(user_name, display_name, email, mobile_phone).normalize(rules)?;
     let u = User {
         user_id: *user_id,
-        user_name: user_name.to_string(),
-        display_name: display_name.to_string(),
+        version,
+        user_name: f.user_name,
+        display_name: f.display_name,
         is_admin,
-        email: email.to_string(),
-        mobile_phone: mobile_phone.to_string(),
+        email: f.email,
+        mobile_phone: f.mobile_phone,
+        ..Default::default()
     };
-    let r = user_db::update(tx, &u).await;
-    match r {
-        Ok(qr) => Ok(qr.rows_affected()),
-        Err(e) => Err(e.into()),
+    match with_store!(tx, |tx, Store| <Store as UserStore>::update(
+        tx,
+        &u,
+        rules.unique_email
+    )
+    .await)?
+    {
+        Some(version) => Ok(version),
+        None => {
+            // NotFound when the user has gone rather than changed
+            load_by_id(tx, *user_id, Deleted::Include).await?;
+            Err(TenancyError::Conflict)
+        }
     }
 }
 
-#[cfg(test)]
-mod tests_tokio {
-    use std::env;
+/// The password is checked with `policy` first.
+pub async fn set_password(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+    password: &str,
+    policy: &PasswordPolicy,
+) -> Result<u64, TenancyError> {
+    if let Err(message) = policy.check(password) {
+        let mut errors = ValidationErrors::new();
+        errors.add("password", message);
+        return Err(errors.into());
+    }
+    let hash = hash_password(password, &policy.hash)?;
+    with_store!(tx, |tx, Store| <Store as UserStore>::update_password(
+        tx, user_id, &hash
+    )
+    .await)
+}
 
-    use async_trait;
-    use dotenvy::dotenv;
-    use test_context::{test_context, AsyncTestContext};
-    use tokio::sync::OnceCell;
+/// Sets `last_login_at`, call it after a successful login.
+pub async fn record_login(tx: &mut DbTransaction<'_>, user_id: &Uuid) -> Result<u64, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserStore>::record_login(
+        tx, user_id
+    )
+    .await)
+}
 
-    use super::*;
+/// Removes the user and their tenant memberships for good.
+pub async fn delete(tx: &mut DbTransaction<'_>, user_id: &Uuid) -> Result<u64, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserStore>::delete(tx, user_id)
+        .await)
+}
 
-    struct TenancyTestContext {
-        _value: String,
-    }
+/// Soft delete: the user is kept but hidden from loads and cannot log in.
+pub async fn deactivate(tx: &mut DbTransaction<'_>, user_id: &Uuid) -> Result<u64, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserStore>::deactivate(
+        tx, user_id
+    )
+    .await)
+}
 
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "postgres")] {
-            use sqlx::{postgres::PgPoolOptions, PgPool};
-            static TEST_DB_POOL: OnceCell<PgPool> = OnceCell::const_new();
-   
-            async fn get_test_db_pool() -> &'static PgPool {
-                TEST_DB_POOL
-                    .get_or_init(|| async {
-                        dotenv().expect(".env file not found");
-                        let database_url: String =
-                            env::var("POSTGRES_TEST_DATABASE_URL").expect("env missing POSTGRES_TEST_DATABASE_URL");
-                        let pool: PgPool = PgPoolOptions::new()
-                            .max_connections(5)
-                            .connect(&database_url)
-                            .await
-                            .expect("Could not create postgres test db pool");
-                        let _m = sqlx::migrate!("../axum-tenancy-postgres/migrations")
-                            .run(&pool)
-                            .await
-                            .expect("Postgres Migration failed");
-                        return pool;
-                    })
-                    .await
-            }
-        }
-    }
-        
-    cfg_if::cfg_if! {
-        if #[cfg(feature = "sqlite")] {
-            use sqlx::{sqlite::SqlitePoolOptions, SqlitePool};
-            static TEST_DB_POOL: OnceCell<SqlitePool> = OnceCell::const_new();
-   
-            async fn get_test_db_pool() -> &'static SqlitePool {
-                TEST_DB_POOL
-                    .get_or_init(|| async {
-                        dotenv().expect(".env file not found");
-                        let database_url: String =
-                            env::var("SQLITE_TEST_DATABASE_URL").expect("env missing SQLITE_TEST_DATABASE_URL");
-                        let pool: SqlitePool = SqlitePoolOptions::new()
-                            .max_connections(5)
-                            .connect(&database_url)
-                            .await
-                            .expect("Could not create sqlite test db pool");
-                        let _m = sqlx::migrate!("../axum-tenancy-sqlite/migrations")
-                            .run(&pool)
-                            .await
-                            .expect("Sqlite Migration failed");
-                        return pool;
-                    })
-                    .await
-            }
-        }
+/// Undo a `deactivate`.
+pub async fn restore(tx: &mut DbTransaction<'_>, user_id: &Uuid) -> Result<u64, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserStore>::restore(tx, user_id)
+        .await)
+}
+
+/// Recomputes every user's canonical user name and email from the stored
+/// values, returning how many users changed. The migration that added the
+/// canonical columns could only approximate them in SQL, and leaves emails
+/// unset, so [`initialize`](crate::initialize) runs this whenever it has
+/// applied a migration. Reads every user, so the application calls it itself
+/// after turning `unique_email` on or off. Fails with
+/// `DuplicateUserName` or `DuplicateEmail` when two existing users turn out to
+/// share a canonical form, one of them has to be renamed first.
+pub async fn recanonicalize(
+    tx: &mut DbTransaction<'_>,
+    rules: &UserRules,
+) -> Result<u64, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserStore>::recanonicalize(
+        tx,
+        rules.unique_email
+    )
+    .await)
+}
+
+/// Check a user name and password.
+///
+/// Unknown user names are still run through argon2 so the time taken does not
+/// show whether the user exists; both cases, and deactivated users, return
+/// `PasswordVerification::Rejected`. `params` should be the parameters new
+/// passwords are hashed with.
+pub async fn verify_password(
+    tx: &mut DbTransaction<'_>,
+    user_name: &str,
+    password: &str,
+    params: &PasswordHashParams,
+) -> Result<PasswordVerification, TenancyError> {
+    let stored = with_store!(tx, |tx, Store| <Store as UserStore>::load_password_hash(
+        tx, user_name
+    )
+    .await)?;
+    let hash = stored.as_ref().map(|(_, hash)| hash.as_str());
+    match (verify_password_hash(password, hash, params), stored) {
+        (true, Some((user_id, _))) => Ok(PasswordVerification::Verified(user_id)),
+        _ => Ok(PasswordVerification::Rejected),
     }
+}
 
-    #[async_trait::async_trait]
-    impl AsyncTestContext for TenancyTestContext {
-        async fn setup() -> TenancyTestContext {
-            let _pool = get_test_db_pool();
+#[cfg(test)]
+mod tests_tokio {
+    use test_context::test_context;
 
-            TenancyTestContext {
-                _value: "hello world".to_string(),
-            }
-        }
+    use axum_tenancy_core::{
+        admin_core::{
+            role_core::Role,
+            user_core::{SortDirection, UserSort},
+        },
+        ActiveDb,
+    };
 
-        async fn teardown(self) {
-            // no teardown at the moment
-        }
-    }
+    use super::*;
+    use crate::admin::{tenant, test_support::*, user_tenant};
 
     #[test_context(TenancyTestContext)]
     #[tokio::test(flavor = "multi_thread")]
@@ -180,23 +273,29 @@ mod tests_tokio {
         let user_result = insert(
             &mut tx,
             "Dave",
+            "password",
             "Dave Warnock",
             true,
             "dwarnock@test.com",
             "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
         )
         .await;
         assert_eq!(&user_result.is_ok(), &true);
-        assert!(insert(
+        let dup = insert(
             &mut tx,
             "Dave",
+            "password",
             "not Dave Warnock",
             true,
             "dwarnock@test.com",
-            "01234567891"
+            "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
         )
-        .await
-        .is_err());
+        .await;
+        assert!(matches!(dup, Err(TenancyError::DuplicateUserName)));
 
         Ok(())
     }
@@ -211,23 +310,29 @@ mod tests_tokio {
         let user_result = insert(
             &mut tx,
             "Dave",
+            "password",
             "Dave Warnock",
             true,
             "dwarnock@test.com",
             "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
         )
         .await;
         assert_eq!(&user_result.is_ok(), &true);
-        assert!(insert(
+        let dup = insert(
             &mut tx,
             "NotDave",
+            "password",
             "Dave Warnock",
             true,
             "dwarnock@test.com",
-            "01234567891"
+            "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
         )
-        .await
-        .is_err());
+        .await;
+        assert!(matches!(dup, Err(TenancyError::DuplicateDisplayName)));
 
         Ok(())
     }
@@ -242,10 +347,13 @@ mod tests_tokio {
         let user_result = insert(
             &mut tx,
             "Dave",
+            "password",
             "Dave Warnock",
             true,
             "dwarnock@test.com",
             "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
         )
         .await;
         assert_eq!(&user_result.is_ok(), &true);
@@ -253,7 +361,7 @@ mod tests_tokio {
         let inserted_uuid = user_result.unwrap_or_default();
         assert_ne!(&inserted_uuid.to_string(), ""); // uuid must not be empty
 
-        let load_result = load_by_id(&mut tx, inserted_uuid).await;
+        let load_result = load_by_id(&mut tx, inserted_uuid, Deleted::Exclude).await;
         assert_eq!(&load_result.is_ok(), &true); // load_by_id reult is ok
 
         let loaded_user = load_result.unwrap_or_default();
@@ -262,7 +370,7 @@ mod tests_tokio {
         assert_eq!(&loaded_user.display_name.to_string(), &"Dave Warnock");
         assert_eq!(&loaded_user.is_admin, &true);
         assert_eq!(&loaded_user.email.to_string(), &"dwarnock@test.com");
-        assert_eq!(&loaded_user.mobile_phone.to_string(), &"01234567891");
+        assert_eq!(&loaded_user.mobile_phone.to_string(), &"+441234567891");
 
         Ok(())
     }
@@ -277,10 +385,13 @@ mod tests_tokio {
         let insert_result = insert(
             &mut tx,
             "Dave",
+            "password",
             "Dave Warnock",
             true,
             "dwarnock@test.com",
             "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
         )
         .await;
         assert_eq!(&insert_result.is_ok(), &true);
@@ -291,27 +402,29 @@ mod tests_tokio {
         let update_result = update(
             &mut tx,
             &inserted_uuid,
-            "not Dave",
+            1,
+            "notDave",
             "not Dave Warnock",
             false,
-            "not dwarnock@test.com",
-            "6601234567891",
+            "not.dwarnock@test.com",
+            "+66 (0)1234 567891",
+            &TEST_USER_RULES,
         )
         .await;
         assert_eq!(&update_result.is_ok(), &true);
-        let rows_affected = update_result.unwrap();
-        assert_eq!(rows_affected, 1);
+        let version = update_result.unwrap();
+        assert_eq!(version, 2);
 
-        let load_result = load_by_id(&mut tx, inserted_uuid).await;
+        let load_result = load_by_id(&mut tx, inserted_uuid, Deleted::Exclude).await;
         assert_eq!(&load_result.is_ok(), &true); // load_by_id reult is ok
 
         let loaded_user = load_result.unwrap_or_default();
         assert_eq!(&loaded_user.user_id, &inserted_uuid);
-        assert_eq!(&loaded_user.user_name.to_string(), &"not Dave");
+        assert_eq!(&loaded_user.user_name.to_string(), &"notDave");
         assert_eq!(&loaded_user.display_name.to_string(), &"not Dave Warnock");
         assert_eq!(&loaded_user.is_admin, &false);
-        assert_eq!(&loaded_user.email.to_string(), &"not dwarnock@test.com");
-        assert_eq!(&loaded_user.mobile_phone.to_string(), &"6601234567891");
+        assert_eq!(&loaded_user.email.to_string(), &"not.dwarnock@test.com");
+        assert_eq!(&loaded_user.mobile_phone.to_string(), &"+661234567891");
 
         Ok(())
     }
@@ -326,10 +439,13 @@ mod tests_tokio {
         let user_result1 = insert(
             &mut tx,
             "zDave",
+            "password",
             "Dave Warnock",
             true,
             "dwarnock@test.com",
             "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
         )
         .await;
         assert_eq!(&user_result1.is_ok(), &true);
@@ -340,10 +456,13 @@ mod tests_tokio {
         let user_result2 = insert(
             &mut tx,
             "Dave2",
+            "password",
             "Dave Warnock2",
             false,
             "dwarnock@test.com2",
             "012345678912",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
         )
         .await;
         assert_eq!(&user_result2.is_ok(), &true);
@@ -351,7 +470,12 @@ mod tests_tokio {
         let inserted_uuid2 = user_result2.unwrap_or_default();
         assert_ne!(&inserted_uuid2.to_string(), ""); // uuid must not be empty
 
-        let load_result = load_all_sorted(&mut tx, UserSort::UserName, SortDirection::Asc).await;
+        let load_result = load_all_sorted(
+            &mut tx,
+            (UserSort::UserName, SortDirection::Asc),
+            Deleted::Exclude,
+        )
+        .await;
         assert_eq!(&load_result.is_ok(), &true); // load_by_id reult is ok
         let vec_users = &load_result.unwrap();
         assert_eq!(&vec_users.len(), &2usize); // load_by_id reult is ok
@@ -362,7 +486,7 @@ mod tests_tokio {
         assert_eq!(&loaded_user2.display_name.to_string(), &"Dave Warnock2");
         assert_eq!(&loaded_user2.is_admin, &false);
         assert_eq!(&loaded_user2.email.to_string(), &"dwarnock@test.com2");
-        assert_eq!(&loaded_user2.mobile_phone.to_string(), &"012345678912");
+        assert_eq!(&loaded_user2.mobile_phone.to_string(), &"+4412345678912");
 
         let loaded_user1 = &vec_users[1];
         assert_eq!(&loaded_user1.user_id, &inserted_uuid1);
@@ -370,7 +494,7 @@ mod tests_tokio {
         assert_eq!(&loaded_user1.display_name.to_string(), &"Dave Warnock");
         assert_eq!(&loaded_user1.is_admin, &true);
         assert_eq!(&loaded_user1.email.to_string(), &"dwarnock@test.com");
-        assert_eq!(&loaded_user1.mobile_phone.to_string(), &"01234567891");
+        assert_eq!(&loaded_user1.mobile_phone.to_string(), &"+441234567891");
 
         Ok(())
     }
@@ -385,10 +509,13 @@ mod tests_tokio {
         let user_result1 = insert(
             &mut tx,
             "zDave",
+            "password",
             "Dave Warnock",
             true,
             "dwarnock@test.com",
             "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
         )
         .await;
         assert_eq!(&user_result1.is_ok(), &true);
@@ -399,10 +526,13 @@ mod tests_tokio {
         let user_result2 = insert(
             &mut tx,
             "Dave2",
+            "password",
             "Dave Warnock2",
             false,
             "dwarnock@test.com2",
             "012345678912",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
         )
         .await;
         assert_eq!(&user_result2.is_ok(), &true);
@@ -410,8 +540,12 @@ mod tests_tokio {
         let inserted_uuid2 = user_result2.unwrap_or_default();
         assert_ne!(&inserted_uuid2.to_string(), ""); // uuid must not be empty
 
-        let load_result =
-            load_all_sorted(&mut tx, UserSort::DisplayName, SortDirection::Desc).await;
+        let load_result = load_all_sorted(
+            &mut tx,
+            (UserSort::DisplayName, SortDirection::Desc),
+            Deleted::Exclude,
+        )
+        .await;
         assert_eq!(&load_result.is_ok(), &true); // load_by_id reult is ok
         let vec_users = &load_result.unwrap();
         assert_eq!(&vec_users.len(), &2usize); // load_by_id reult is ok
@@ -422,7 +556,7 @@ mod tests_tokio {
         assert_eq!(&loaded_user2.display_name.to_string(), &"Dave Warnock2");
         assert_eq!(&loaded_user2.is_admin, &false);
         assert_eq!(&loaded_user2.email.to_string(), &"dwarnock@test.com2");
-        assert_eq!(&loaded_user2.mobile_phone.to_string(), &"012345678912");
+        assert_eq!(&loaded_user2.mobile_phone.to_string(), &"+4412345678912");
 
         let loaded_user1 = &vec_users[1];
         assert_eq!(&loaded_user1.user_id, &inserted_uuid1);
@@ -430,7 +564,971 @@ mod tests_tokio {
         assert_eq!(&loaded_user1.display_name.to_string(), &"Dave Warnock");
         assert_eq!(&loaded_user1.is_admin, &true);
         assert_eq!(&loaded_user1.email.to_string(), &"dwarnock@test.com");
-        assert_eq!(&loaded_user1.mobile_phone.to_string(), &"01234567891");
+        assert_eq!(&loaded_user1.mobile_phone.to_string(), &"+441234567891");
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_then_verify_password(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let insert_result = insert(
+            &mut tx,
+            "Dave",
+            "correct horse",
+            "Dave Warnock",
+            true,
+            "dwarnock@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await;
+        assert_eq!(&insert_result.is_ok(), &true);
+        let inserted_uuid = insert_result.unwrap_or_default();
+
+        let verified =
+            verify_password(&mut tx, "Dave", "correct horse", &TEST_PASSWORD_PARAMS).await;
+        assert_eq!(
+            verified.unwrap(),
+            PasswordVerification::Verified(inserted_uuid)
+        );
+
+        let wrong = verify_password(&mut tx, "Dave", "battery staple", &TEST_PASSWORD_PARAMS).await;
+        assert_eq!(wrong.unwrap(), PasswordVerification::Rejected);
+
+        // an unknown user looks exactly like a wrong password
+        let unknown =
+            verify_password(&mut tx, "NotDave", "correct horse", &TEST_PASSWORD_PARAMS).await;
+        assert_eq!(unknown.unwrap(), PasswordVerification::Rejected);
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn set_password_then_verify(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let insert_result = insert(
+            &mut tx,
+            "Dave",
+            "correct horse",
+            "Dave Warnock",
+            true,
+            "dwarnock@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await;
+        let inserted_uuid = insert_result.unwrap_or_default();
+
+        let set_result = set_password(
+            &mut tx,
+            &inserted_uuid,
+            "battery staple",
+            &TEST_PASSWORD_POLICY,
+        )
+        .await;
+        assert_eq!(set_result.unwrap(), 1);
+
+        let old = verify_password(&mut tx, "Dave", "correct horse", &TEST_PASSWORD_PARAMS).await;
+        assert_eq!(old.unwrap(), PasswordVerification::Rejected);
+
+        let new = verify_password(&mut tx, "Dave", "battery staple", &TEST_PASSWORD_PARAMS).await;
+        assert_eq!(new.unwrap(), PasswordVerification::Verified(inserted_uuid));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn deactivate_then_restore(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let user_id = insert(
+            &mut tx,
+            "Dave",
+            "password",
+            "Dave Warnock",
+            true,
+            "dwarnock@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+
+        assert_eq!(deactivate(&mut tx, &user_id).await.unwrap(), 1);
+        assert_eq!(deactivate(&mut tx, &user_id).await.unwrap(), 0); // already deactivated
+        let hidden = load_by_id(&mut tx, user_id, Deleted::Exclude).await;
+        assert!(matches!(hidden, Err(TenancyError::NotFound)));
+        let loaded = load_by_id(&mut tx, user_id, Deleted::Include)
+            .await
+            .unwrap();
+        assert!(loaded.deleted_at.is_some());
+        let all = load_all_sorted(
+            &mut tx,
+            (UserSort::UserName, SortDirection::Asc),
+            Deleted::Exclude,
+        )
+        .await
+        .unwrap();
+        assert!(all.is_empty());
+        let all = load_all_sorted(
+            &mut tx,
+            (UserSort::UserName, SortDirection::Asc),
+            Deleted::Include,
+        )
+        .await
+        .unwrap();
+        assert_eq!(all.len(), 1);
+        let verified = verify_password(&mut tx, "Dave", "password", &TEST_PASSWORD_PARAMS).await;
+        assert_eq!(verified.unwrap(), PasswordVerification::Rejected);
+
+        assert_eq!(restore(&mut tx, &user_id).await.unwrap(), 1);
+        assert_eq!(restore(&mut tx, &user_id).await.unwrap(), 0); // not deactivated
+        let loaded = load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert!(loaded.deleted_at.is_none());
+        let verified = verify_password(&mut tx, "Dave", "password", &TEST_PASSWORD_PARAMS).await;
+        assert_eq!(verified.unwrap(), PasswordVerification::Verified(user_id));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn delete_removes_memberships(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let user_id = insert(
+            &mut tx,
+            "Dave",
+            "password",
+            "Dave Warnock",
+            true,
+            "dwarnock@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        let tenant_id = tenant::insert(&mut tx, "Tenant", "A Tenant").await.unwrap();
+        user_tenant::insert(&mut tx, &user_id, &tenant_id, &Role::Member)
+            .await
+            .unwrap();
+
+        assert_eq!(delete(&mut tx, &user_id).await.unwrap(), 1);
+        let gone = load_by_id(&mut tx, user_id, Deleted::Include).await;
+        assert!(matches!(gone, Err(TenancyError::NotFound)));
+        let memberships = user_tenant::load_by_user(&mut tx, &user_id).await.unwrap();
+        assert!(memberships.is_empty());
+        assert_eq!(delete(&mut tx, &user_id).await.unwrap(), 0);
+
+        Ok(())
+    }
+
+    /// Inserts users "user0".."user{n-1}", even numbers are admins.
+    async fn insert_users(tx: &mut DbTransaction<'_>, n: usize) -> Vec<Uuid> {
+        let mut ids = Vec::new();
+        for i in 0..n {
+            let id = insert(
+                tx,
+                &format!("user{}", i),
+                "password",
+                &format!("User {}", i),
+                i % 2 == 0,
+                &format!("user{}@test.com", i),
+                "01234567891",
+                &TEST_USER_RULES,
+                &TEST_PASSWORD_POLICY,
+            )
+            .await
+            .unwrap();
+            ids.push(id);
+        }
+        ids
+    }
+
+    fn user_names(page: &Page<User>) -> Vec<&str> {
+        page.items.iter().map(|u| u.user_name.as_str()).collect()
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn load_page_offset_and_limit(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        insert_users(&mut tx, 5).await;
+
+        let page = load_page(&mut tx, &UserQuery::new().limit(2))
+            .await
+            .unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(user_names(&page), vec!["user0", "user1"]);
+        assert!(page.next_cursor.is_some());
+
+        let page = load_page(&mut tx, &UserQuery::new().limit(2).offset(4))
+            .await
+            .unwrap();
+        assert_eq!(page.total, 5);
+        assert_eq!(user_names(&page), vec!["user4"]);
+        assert!(page.next_cursor.is_none());
+
+        let zero = load_page(&mut tx, &UserQuery::new().limit(0)).await;
+        assert!(matches!(zero, Err(TenancyError::Validation(_))));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn load_page_follows_cursor(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        insert_users(&mut tx, 5).await;
+
+        let q = UserQuery::new()
+            .sort(UserSort::DisplayName, SortDirection::Desc)
+            .limit(2);
+        let mut seen = Vec::new();
+        let mut page = load_page(&mut tx, &q).await.unwrap();
+        loop {
+            seen.extend(page.items.iter().map(|u| u.display_name.clone()));
+            match page.next_cursor {
+                Some(cursor) => page = load_page(&mut tx, &q.clone().after(&cursor)).await.unwrap(),
+                None => break,
+            }
+        }
+        assert_eq!(seen, vec!["User 4", "User 3", "User 2", "User 1", "User 0"]);
+
+        // a cursor is tied to the sort it was made with
+        let first = load_page(&mut tx, &q).await.unwrap();
+        let cursor = first.next_cursor.unwrap();
+        let wrong_sort = load_page(&mut tx, &UserQuery::new().after(&cursor)).await;
+        assert!(matches!(wrong_sort, Err(TenancyError::Validation(_))));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn load_page_filters(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let ids = insert_users(&mut tx, 12).await;
+        deactivate(&mut tx, &ids[0]).await.unwrap();
+
+        let admins = load_page(&mut tx, &UserQuery::new().is_admin(true))
+            .await
+            .unwrap();
+        assert_eq!(admins.total, 5);
+        assert!(admins.items.iter().all(|u| u.is_admin));
+
+        let admins = load_page(
+            &mut tx,
+            &UserQuery::new().is_admin(true).deleted(Deleted::Include),
+        )
+        .await
+        .unwrap();
+        assert_eq!(admins.total, 6);
+
+        // matched on the email, ignoring case
+        let found = load_page(&mut tx, &UserQuery::new().search("USER1@"))
+            .await
+            .unwrap();
+        assert_eq!(user_names(&found), vec!["user1"]);
+        let found = load_page(&mut tx, &UserQuery::new().search("user 1"))
+            .await
+            .unwrap();
+        assert_eq!(found.total, 3);
+        assert_eq!(user_names(&found), vec!["user1", "user10", "user11"]);
+
+        // wildcards are matched literally
+        let none = load_page(&mut tx, &UserQuery::new().search("user_"))
+            .await
+            .unwrap();
+        assert_eq!(none.total, 0);
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn load_all_sorted_multi_column(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        for (user_name, email) in [
+            ("c", "b@test.com"),
+            ("a", "a@test.com"),
+            ("b", "b@test.com"),
+        ] {
+            insert(
+                &mut tx,
+                user_name,
+                "password",
+                user_name,
+                false,
+                email,
+                "01234567891",
+                &TEST_USER_RULES,
+                &TEST_PASSWORD_POLICY,
+            )
+            .await
+            .unwrap();
+        }
+
+        let order = UserOrder::new(UserSort::Email, SortDirection::Desc)
+            .then(UserSort::UserName, SortDirection::Asc);
+        let users = load_all_sorted(&mut tx, order, Deleted::Exclude)
+            .await
+            .unwrap();
+        let names: Vec<&str> = users.iter().map(|u| u.user_name.as_str()).collect();
+        assert_eq!(names, vec!["b", "c", "a"]);
+
+        let users = load_all_sorted(
+            &mut tx,
+            (UserSort::CreatedAt, SortDirection::Asc),
+            Deleted::Exclude,
+        )
+        .await
+        .unwrap();
+        assert!(users.windows(2).all(|w| w[0].created_at <= w[1].created_at));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn load_page_cursor_on_non_unique_sort(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let ids = insert_users(&mut tx, 5).await;
+
+        // created_at can tie, user_id keeps the pages from overlapping
+        let q = UserQuery::new()
+            .order(
+                UserOrder::new(UserSort::CreatedAt, SortDirection::Desc)
+                    .then(UserSort::Email, SortDirection::Asc),
+            )
+            .limit(2);
+        let mut seen = Vec::new();
+        let mut page = load_page(&mut tx, &q).await.unwrap();
+        loop {
+            seen.extend(page.items.iter().map(|u| u.user_id));
+            match page.next_cursor {
+                Some(cursor) => page = load_page(&mut tx, &q.clone().after(&cursor)).await.unwrap(),
+                None => break,
+            }
+        }
+        assert_eq!(seen.len(), 5);
+        for id in ids {
+            assert!(seen.contains(&id));
+        }
+
+        Ok(())
+    }
+
+    /// The backend's plan for the `load_page` query.
+    async fn explain_page(tx: &mut DbTransaction<'_>, q: &UserQuery) -> Vec<String> {
+        match tx {
+            #[cfg(feature = "sqlite")]
+            DbTransaction::Sqlite(tx) => {
+                axum_tenancy_sqlite::admin_sqlite::user_sqlite::explain_page(tx, q).await
+            }
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(tx) => {
+                axum_tenancy_postgres::admin_postgres::user_postgres::explain_page(tx, q).await
+            }
+        }
+        .unwrap()
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn load_page_sorts_with_an_index(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool().await;
+        let mut tx: DbTransaction = pool.begin().await?;
+        insert_users(&mut tx, 3).await;
+        #[cfg(feature = "postgres")]
+        #[allow(irrefutable_let_patterns)]
+        if let DbTransaction::Postgres(tx) = &mut tx {
+            // the test table is tiny and may have no statistics yet, so make
+            // a seq scan or a sort look expensive; a sort is still used when
+            // no index can provide the order
+            for setting in ["enable_seqscan", "enable_sort"] {
+                sqlx::query(&format!("SET LOCAL {} = off", setting))
+                    .execute(&mut **tx)
+                    .await?;
+            }
+        }
+
+        let cases = [
+            (
+                UserSort::UserName,
+                "sqlite_autoindex_user_",
+                "user_user_name_key",
+            ),
+            (
+                UserSort::DisplayName,
+                "sqlite_autoindex_user_",
+                "user_display_name_key",
+            ),
+            (UserSort::Email, "user_email", "user_email"),
+            (UserSort::CreatedAt, "user_created_at", "user_created_at"),
+            (UserSort::UpdatedAt, "user_updated_at", "user_updated_at"),
+            (
+                UserSort::LastLoginAt,
+                "user_last_login_at",
+                "user_last_login_at",
+            ),
+        ];
+        for (sort, sqlite_index, postgres_index) in cases {
+            for direction in [SortDirection::Asc, SortDirection::Desc] {
+                let q = UserQuery::new().sort(sort, direction);
+                let first = load_page(&mut tx, &q.clone().limit(1)).await.unwrap();
+                let cursor = first.next_cursor.unwrap();
+                for q in [q.clone(), q.after(&cursor)] {
+                    let plan = explain_page(&mut tx, &q).await.join("\n");
+                    let index = match pool.active_db() {
+                        ActiveDb::Sqlite => {
+                            assert!(!plan.contains("TEMP B-TREE"), "{}", plan);
+                            sqlite_index
+                        }
+                        _ => {
+                            assert!(!plan.contains("Sort"), "{}", plan);
+                            postgres_index
+                        }
+                    };
+                    assert!(
+                        plan.contains(&format!("INDEX {}", index))
+                            || plan.contains(&format!("using {}", index)),
+                        "{:?} {:?} does not use {}: {}",
+                        sort,
+                        direction,
+                        index,
+                        plan
+                    );
+                }
+            }
+        }
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn audit_timestamps(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let user_id = insert_users(&mut tx, 1).await[0];
+        let inserted = load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert_eq!(inserted.updated_at, inserted.created_at);
+        assert!(inserted.last_login_at.is_none());
+
+        update(
+            &mut tx,
+            &user_id,
+            inserted.version,
+            "user0",
+            "User 0",
+            false,
+            "user0@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+        )
+        .await
+        .unwrap();
+        let updated = load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert_eq!(updated.created_at, inserted.created_at);
+        assert!(updated.updated_at > inserted.updated_at);
+
+        // a login is not an edit
+        assert_eq!(record_login(&mut tx, &user_id).await.unwrap(), 1);
+        let logged_in = load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert!(logged_in.last_login_at.unwrap() >= updated.updated_at);
+        assert_eq!(logged_in.updated_at, updated.updated_at);
+
+        set_password(&mut tx, &user_id, "new password", &TEST_PASSWORD_POLICY)
+            .await
+            .unwrap();
+        let changed = load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert!(changed.updated_at > logged_in.updated_at);
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stale_accounts_first(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let ids = insert_users(&mut tx, 4).await;
+        record_login(&mut tx, &ids[2]).await.unwrap();
+        record_login(&mut tx, &ids[0]).await.unwrap();
+
+        let users = load_all_sorted(
+            &mut tx,
+            (UserSort::LastLoginAt, SortDirection::Asc),
+            Deleted::Exclude,
+        )
+        .await
+        .unwrap();
+        let order: Vec<Uuid> = users.iter().map(|u| u.user_id).collect();
+        assert!(order[..2].contains(&ids[1]) && order[..2].contains(&ids[3]));
+        assert_eq!(order[2..], [ids[2], ids[0]]);
+
+        // paging through the nulls in both directions
+        for direction in [SortDirection::Asc, SortDirection::Desc] {
+            let q = UserQuery::new()
+                .sort(UserSort::LastLoginAt, direction)
+                .limit(1);
+            let mut seen = Vec::new();
+            let mut page = load_page(&mut tx, &q).await.unwrap();
+            loop {
+                seen.extend(page.items.iter().map(|u| u.user_id));
+                match page.next_cursor {
+                    Some(cursor) => {
+                        page = load_page(&mut tx, &q.clone().after(&cursor)).await.unwrap()
+                    }
+                    None => break,
+                }
+            }
+            if direction == SortDirection::Desc {
+                seen.reverse();
+            }
+            assert_eq!(seen, order);
+        }
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn stale_update_is_a_conflict(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let user_id = insert_users(&mut tx, 1).await[0];
+        let loaded = load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert_eq!(loaded.version, 1);
+
+        // two admins edit the same user
+        let first = update(
+            &mut tx,
+            &user_id,
+            loaded.version,
+            "first",
+            "First",
+            false,
+            "",
+            "",
+            &TEST_USER_RULES,
+        )
+        .await
+        .unwrap();
+        assert_eq!(first, 2);
+        let second = update(
+            &mut tx,
+            &user_id,
+            loaded.version,
+            "second",
+            "Second",
+            false,
+            "",
+            "",
+            &TEST_USER_RULES,
+        )
+        .await;
+        assert!(matches!(second, Err(TenancyError::Conflict)));
+        let kept = load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert_eq!(kept.user_name, "first");
+        assert_eq!(kept.version, 2);
+
+        let missing = update(
+            &mut tx,
+            &Uuid::new_v4(),
+            1,
+            "xyz",
+            "X",
+            false,
+            "",
+            "",
+            &TEST_USER_RULES,
+        )
+        .await;
+        assert!(matches!(missing, Err(TenancyError::NotFound)));
+
+        // other edits count too, logging in does not
+        set_password(&mut tx, &user_id, "new password", &TEST_PASSWORD_POLICY)
+            .await
+            .unwrap();
+        deactivate(&mut tx, &user_id).await.unwrap();
+        restore(&mut tx, &user_id).await.unwrap();
+        record_login(&mut tx, &user_id).await.unwrap();
+        let edited = load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert_eq!(edited.version, 5);
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn short_passwords_are_rejected(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let policy = PasswordPolicy {
+            min_length: 12,
+            hash: TEST_PASSWORD_PARAMS,
+        };
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let bad = insert(
+            &mut tx,
+            "Dave",
+            "password",
+            "",
+            false,
+            "dwarnock@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+            &policy,
+        )
+        .await;
+        let Err(TenancyError::InvalidFields(errors)) = bad else {
+            panic!("expected InvalidFields, got {:?}", bad);
+        };
+        let fields: Vec<&str> = errors.iter().map(|e| e.field).collect();
+        assert_eq!(fields, ["display_name", "password"]);
+        assert_eq!(
+            errors.get("password"),
+            Some("must be at least 12 characters")
+        );
+
+        let user_id = insert(
+            &mut tx,
+            "Dave",
+            "correct horse battery",
+            "Dave Warnock",
+            false,
+            "dwarnock@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+            &policy,
+        )
+        .await
+        .unwrap();
+        let bad = set_password(&mut tx, &user_id, "short", &policy).await;
+        let Err(TenancyError::InvalidFields(errors)) = bad else {
+            panic!("expected InvalidFields, got {:?}", bad);
+        };
+        assert!(errors.get("password").is_some());
+        let verified = verify_password(
+            &mut tx,
+            "Dave",
+            "correct horse battery",
+            &TEST_PASSWORD_PARAMS,
+        )
+        .await
+        .unwrap();
+        assert!(matches!(verified, PasswordVerification::Verified(_)));
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn bad_fields_are_rejected(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let bad = insert(
+            &mut tx,
+            "not Dave",
+            "password",
+            "",
+            false,
+            "not dwarnock@test.com",
+            "01234567891",
+            &UserRules::default(),
+            &TEST_PASSWORD_POLICY,
+        )
+        .await;
+        let Err(TenancyError::InvalidFields(errors)) = bad else {
+            panic!("expected InvalidFields, got {:?}", bad);
+        };
+        let fields: Vec<&str> = errors.iter().map(|e| e.field).collect();
+        // no default country code, so the local number is rejected too
+        assert_eq!(
+            fields,
+            ["user_name", "display_name", "email", "mobile_phone"]
+        );
+
+        let user_id = insert(
+            &mut tx,
+            " Dave ",
+            "password",
+            " Dave Warnock ",
+            false,
+            "DWarnock@Test.com",
+            "0044 1234 567891",
+            &UserRules::default(),
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        let u = load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert_eq!(u.user_name, "Dave");
+        assert_eq!(u.display_name, "Dave Warnock");
+        assert_eq!(u.email, "DWarnock@test.com");
+        assert_eq!(u.mobile_phone, "+441234567891");
+
+        let bad = update(
+            &mut tx,
+            &user_id,
+            u.version,
+            "Dave",
+            "Dave Warnock",
+            false,
+            "dwarnock@",
+            "",
+            &UserRules::default(),
+        )
+        .await;
+        let Err(TenancyError::InvalidFields(errors)) = bad else {
+            panic!("expected InvalidFields, got {:?}", bad);
+        };
+        assert_eq!(errors.get("email"), Some("is not a valid email address"));
+        assert_eq!(errors.get("mobile_phone"), None);
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn user_names_are_unique_ignoring_case(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let mut results = Vec::new();
+        for (user_name, display_name) in [
+            ("Dave", "Dave Warnock"),
+            ("dave", "Dave W"),
+            ("ＤＡＶＥ", "Fullwidth Dave"),
+        ] {
+            let mut savepoint = tx.begin().await?;
+            let r = insert(
+                &mut savepoint,
+                user_name,
+                "correct horse",
+                display_name,
+                false,
+                "",
+                "",
+                &TEST_USER_RULES,
+                &TEST_PASSWORD_POLICY,
+            )
+            .await;
+            match r {
+                Ok(_) => savepoint.commit().await?,
+                Err(_) => savepoint.rollback().await?,
+            }
+            results.push(r);
+        }
+        let user_id = *results[0].as_ref().unwrap();
+        assert!(matches!(results[1], Err(TenancyError::DuplicateUserName)));
+        assert!(matches!(results[2], Err(TenancyError::DuplicateUserName)));
+
+        // the display form is kept, logins match any case
+        let u = load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert_eq!(u.user_name, "Dave");
+        let verified = verify_password(&mut tx, "DAVE", "correct horse", &TEST_PASSWORD_PARAMS)
+            .await
+            .unwrap();
+        assert_eq!(verified, PasswordVerification::Verified(user_id));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn recanonicalize_fixes_stale_forms(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let unique = UserRules {
+            unique_email: true,
+            ..TEST_USER_RULES
+        };
+        let user_id = insert(
+            &mut tx,
+            "Dave",
+            "correct horse",
+            "Dave",
+            false,
+            "Dave@Test.com",
+            "",
+            &unique,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        // as the migration left them, names only approximated, no emails
+        let stale = r#"UPDATE "user" SET canonical_user_name = user_name || '-stale', canonical_email = NULL"#;
+        match &mut tx {
+            #[cfg(feature = "sqlite")]
+            DbTransaction::Sqlite(tx) => {
+                sqlx::query(stale).execute(&mut **tx).await?;
+            }
+            #[cfg(feature = "postgres")]
+            DbTransaction::Postgres(tx) => {
+                sqlx::query(stale).execute(&mut **tx).await?;
+            }
+        }
+        let before = verify_password(&mut tx, "dave", "correct horse", &TEST_PASSWORD_PARAMS)
+            .await
+            .unwrap();
+        assert_eq!(before, PasswordVerification::Rejected);
+
+        assert!(recanonicalize(&mut tx, &unique).await.unwrap() >= 1);
+        assert_eq!(recanonicalize(&mut tx, &unique).await.unwrap(), 0);
+        let after = verify_password(&mut tx, "dave", "correct horse", &TEST_PASSWORD_PARAMS)
+            .await
+            .unwrap();
+        assert_eq!(after, PasswordVerification::Verified(user_id));
+        let clash = insert(
+            &mut tx,
+            "other",
+            "password",
+            "other",
+            false,
+            "dave@test.com",
+            "",
+            &unique,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await;
+        assert!(
+            matches!(clash, Err(TenancyError::DuplicateEmail)),
+            "{:?}",
+            clash
+        );
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn unique_email_is_optional(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let unique = UserRules {
+            unique_email: true,
+            ..TEST_USER_RULES
+        };
+        // only emails saved with the rule on are compared, empty ones never
+        let cases = [
+            ("a", "dave@test.com", unique, true),
+            ("b", "Dave@TEST.com", unique, false),
+            ("c", "dave@test.com", TEST_USER_RULES, true),
+            ("d", "", unique, true),
+            ("e", "", unique, true),
+        ];
+        let mut ids = Vec::new();
+        for (user_name, email, rules, ok) in cases {
+            let mut savepoint = tx.begin().await?;
+            let r = insert(
+                &mut savepoint,
+                user_name,
+                "password",
+                user_name,
+                false,
+                email,
+                "",
+                &rules,
+                &TEST_PASSWORD_POLICY,
+            )
+            .await;
+            if ok {
+                ids.push(r.unwrap());
+                savepoint.commit().await?;
+            } else {
+                assert!(matches!(r, Err(TenancyError::DuplicateEmail)), "{:?}", r);
+                savepoint.rollback().await?;
+            }
+        }
+
+        let clash = update(
+            &mut tx,
+            &ids[2],
+            1,
+            "d",
+            "d",
+            false,
+            "DAVE@test.com",
+            "",
+            &unique,
+        )
+        .await;
+        assert!(
+            matches!(clash, Err(TenancyError::DuplicateEmail)),
+            "{:?}",
+            clash
+        );
 
         Ok(())
     }
@@ -0,0 +1,302 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+use axum_tenancy_core::{
+    admin_core::{
+        role_core::Role,
+        user_core::SortDirection,
+        user_tenant_core::{UserTenant, UserTenantSort},
+    },
+    error::TenancyError,
+    store::UserTenantStore,
+};
+use uuid::Uuid;
+
+use super::DbTransaction;
+
+pub async fn insert(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+    tenant_id: &Uuid,
+    role: &Role,
+) -> Result<uuid::Uuid, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserTenantStore>::insert(
+        tx, user_id, tenant_id, role
+    )
+    .await)
+}
+
+pub async fn load_by_id(
+    tx: &mut DbTransaction<'_>,
+    user_tenant_id: Uuid,
+) -> Result<UserTenant, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserTenantStore>::load_by_id(
+        tx,
+        user_tenant_id
+    )
+    .await)
+}
+
+pub async fn load_by_user_and_tenant(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+    tenant_id: &Uuid,
+) -> Result<UserTenant, TenancyError> {
+    with_store!(tx, |tx, Store| {
+        <Store as UserTenantStore>::load_by_user_and_tenant(tx, user_id, tenant_id).await
+    })
+}
+
+pub async fn load_by_user(
+    tx: &mut DbTransaction<'_>,
+    user_id: &Uuid,
+) -> Result<Vec<UserTenant>, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserTenantStore>::load_by_user(
+        tx, user_id
+    )
+    .await)
+}
+
+pub async fn load_all_sorted(
+    tx: &mut DbTransaction<'_>,
+    sort: UserTenantSort,
+    direction: SortDirection,
+) -> Result<Vec<UserTenant>, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserTenantStore>::load_all_sorted(
+        tx, sort, direction
+    )
+    .await)
+}
+
+pub async fn update(
+    tx: &mut DbTransaction<'_>,
+    user_tenant_id: &Uuid,
+    user_id: &Uuid,
+    tenant_id: &Uuid,
+    role: &Role,
+) -> Result<u64, TenancyError> {
+    let ut = UserTenant {
+        user_tenant_id: *user_tenant_id,
+        user_id: *user_id,
+        tenant_id: *tenant_id,
+        role: role.clone(),
+    };
+    with_store!(tx, |tx, Store| <Store as UserTenantStore>::update(tx, &ut)
+        .await)
+}
+
+pub async fn delete(
+    tx: &mut DbTransaction<'_>,
+    user_tenant_id: &Uuid,
+) -> Result<u64, TenancyError> {
+    with_store!(tx, |tx, Store| <Store as UserTenantStore>::delete(
+        tx,
+        user_tenant_id
+    )
+    .await)
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use test_context::test_context;
+
+    use super::*;
+    use crate::admin::{tenant, test_support::*, user};
+
+    async fn insert_user(tx: &mut DbTransaction<'_>, user_name: &str) -> Uuid {
+        user::insert(
+            tx,
+            user_name,
+            "password",
+            &format!("{} Warnock", user_name),
+            false,
+            "dwarnock@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap()
+    }
+
+    async fn insert_tenant(tx: &mut DbTransaction<'_>, tenant_name: &str) -> Uuid {
+        tenant::insert(tx, tenant_name, &format!("{} Church", tenant_name))
+            .await
+            .unwrap()
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_then_check_load_user_tenant(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let user_id = insert_user(&mut tx, "Dave").await;
+        let tenant_id = insert_tenant(&mut tx, "Hannah").await;
+
+        let insert_result = insert(&mut tx, &user_id, &tenant_id, &Role::Owner).await;
+        assert_eq!(&insert_result.is_ok(), &true);
+        let inserted_uuid = insert_result.unwrap_or_default();
+
+        let load_result = load_by_id(&mut tx, inserted_uuid).await;
+        assert_eq!(&load_result.is_ok(), &true);
+        let loaded = load_result.unwrap_or_default();
+        assert_eq!(&loaded.user_tenant_id, &inserted_uuid);
+        assert_eq!(&loaded.user_id, &user_id);
+        assert_eq!(&loaded.tenant_id, &tenant_id);
+        assert_eq!(&loaded.role, &Role::Owner);
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_user_tenant_no_dup(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let user_id = insert_user(&mut tx, "Dave").await;
+        let tenant_id = insert_tenant(&mut tx, "Hannah").await;
+
+        assert_eq!(
+            &insert(&mut tx, &user_id, &tenant_id, &Role::Member)
+                .await
+                .is_ok(),
+            &true
+        );
+        assert!(matches!(
+            insert(&mut tx, &user_id, &tenant_id, &Role::Member).await,
+            Err(TenancyError::DuplicateUserTenant)
+        ));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_two_check_load_all(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let user_id = insert_user(&mut tx, "Dave").await;
+        let tenant_id1 = insert_tenant(&mut tx, "zHannah").await;
+        let tenant_id2 = insert_tenant(&mut tx, "Samuel").await;
+        let inserted_uuid1 = insert(&mut tx, &user_id, &tenant_id1, &Role::Member)
+            .await
+            .unwrap();
+        let inserted_uuid2 = insert(&mut tx, &user_id, &tenant_id2, &Role::Member)
+            .await
+            .unwrap();
+
+        let load_result =
+            load_all_sorted(&mut tx, UserTenantSort::TenantName, SortDirection::Asc).await;
+        assert_eq!(&load_result.is_ok(), &true);
+        let vec_user_tenants = &load_result.unwrap();
+        assert_eq!(&vec_user_tenants.len(), &2usize);
+        assert_eq!(&vec_user_tenants[0].user_tenant_id, &inserted_uuid2);
+        assert_eq!(&vec_user_tenants[1].user_tenant_id, &inserted_uuid1);
+
+        let load_result =
+            load_all_sorted(&mut tx, UserTenantSort::TenantName, SortDirection::Desc).await;
+        let vec_user_tenants = &load_result.unwrap();
+        assert_eq!(&vec_user_tenants[0].user_tenant_id, &inserted_uuid1);
+        assert_eq!(&vec_user_tenants[1].user_tenant_id, &inserted_uuid2);
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_update_then_check(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let user_id = insert_user(&mut tx, "Dave").await;
+        let tenant_id1 = insert_tenant(&mut tx, "Hannah").await;
+        let tenant_id2 = insert_tenant(&mut tx, "Samuel").await;
+        let inserted_uuid = insert(&mut tx, &user_id, &tenant_id1, &Role::Member)
+            .await
+            .unwrap();
+
+        let accountant = Role::from("accountant");
+        let update_result =
+            update(&mut tx, &inserted_uuid, &user_id, &tenant_id2, &accountant).await;
+        assert_eq!(update_result.unwrap(), 1);
+
+        let loaded = load_by_id(&mut tx, inserted_uuid).await.unwrap_or_default();
+        assert_eq!(&loaded.user_id, &user_id);
+        assert_eq!(&loaded.tenant_id, &tenant_id2);
+        assert_eq!(&loaded.role, &accountant);
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn insert_then_delete(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let user_id = insert_user(&mut tx, "Dave").await;
+        let tenant_id = insert_tenant(&mut tx, "Hannah").await;
+        let inserted_uuid = insert(&mut tx, &user_id, &tenant_id, &Role::Member)
+            .await
+            .unwrap();
+
+        assert_eq!(delete(&mut tx, &inserted_uuid).await.unwrap(), 1);
+        assert!(matches!(
+            load_by_id(&mut tx, inserted_uuid).await,
+            Err(TenancyError::NotFound)
+        ));
+
+        Ok(())
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn delete_tenant_removes_user_tenant(
+        _tenancy_context: &mut TenancyTestContext,
+    ) -> sqlx::Result<(), sqlx::Error> {
+        let pool = get_test_db_pool();
+        let mut tx: DbTransaction = pool.await.begin().await?;
+        let user_id = insert_user(&mut tx, "Dave").await;
+        let tenant_id = insert_tenant(&mut tx, "Hannah").await;
+        let inserted_uuid = insert(&mut tx, &user_id, &tenant_id, &Role::Member)
+            .await
+            .unwrap();
+
+        assert_eq!(tenant::delete(&mut tx, &tenant_id).await.unwrap(), 1);
+        assert!(matches!(
+            load_by_id(&mut tx, inserted_uuid).await,
+            Err(TenancyError::NotFound)
+        ));
+
+        Ok(())
+    }
+}
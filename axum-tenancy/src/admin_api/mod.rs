@@ -0,0 +1,461 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! A JSON API over the admin functions, for single page apps and
+//! automation.
+//!
+//! Like [`admin_ui`](crate::admin_ui) it can be nested under any prefix and
+//! every route needs an admin:
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .nest("/api/admin", tenancy.api_router())
+//!     .layer(axum::middleware::from_fn(user_to_extensions))
+//!     .layer(tenancy.auth_layer(session_store));
+//! ```
+//!
+//! | Method | Path | |
+//! |---|---|---|
+//! | GET | `/users` | a page of users, `?sort=&dir=&search=&is_admin=&after=&offset=&limit=` |
+//! | POST | `/users` | create a user, 201 |
+//! | GET | `/users/:user_id` | |
+//! | PATCH | `/users/:user_id` | change the fields sent, `version` is required |
+//! | DELETE | `/users/:user_id` | 204 |
+//! | GET | `/users/:user_id/tenants` | the user's memberships |
+//! | PUT | `/users/:user_id/tenants/:tenant_id` | give the user access, 201, or change their role, 200, with an optional `{"role": "viewer"}` |
+//! | DELETE | `/users/:user_id/tenants/:tenant_id` | take it away, 204 |
+//! | GET | `/tenants` | all tenants, `?sort=&dir=` |
+//! | POST | `/tenants` | create a tenant, 201 |
+//! | GET | `/tenants/:tenant_id` | |
+//! | PATCH | `/tenants/:tenant_id` | change the fields sent |
+//! | DELETE | `/tenants/:tenant_id` | 204 |
+//!
+//! Lists are returned as a [`Page`](axum_tenancy_core::admin_core::page_core::Page).
+//! Every error is an [`ApiError`] body.
+
+use axum::{
+    async_trait,
+    extract::{
+        rejection::{JsonRejection, QueryRejection},
+        FromRequest, FromRequestParts, Json, Query, Request,
+    },
+    http::{request::Parts, StatusCode},
+    middleware,
+    response::{IntoResponse, Response},
+    routing::{get, put},
+    Router,
+};
+use axum_tenancy_core::{admin_core::validation_core::ValidationErrors, error::TenancyError};
+use serde::{de::DeserializeOwned, Serialize};
+
+use crate::{extract::RequireAdmin, Tenancy};
+
+mod tenant;
+mod user;
+
+/// The JSON API, see the [module docs](self).
+pub fn api_router(tenancy: Tenancy) -> Router {
+    Router::new()
+        .route("/users", get(user::list).post(user::create))
+        .route(
+            "/users/:user_id",
+            get(user::get).patch(user::patch).delete(user::delete),
+        )
+        .route("/users/:user_id/tenants", get(tenant::memberships))
+        .route(
+            "/users/:user_id/tenants/:tenant_id",
+            put(tenant::join).delete(tenant::leave),
+        )
+        .route("/tenants", get(tenant::list).post(tenant::create))
+        .route(
+            "/tenants/:tenant_id",
+            get(tenant::get).patch(tenant::patch).delete(tenant::delete),
+        )
+        .route_layer(middleware::from_extractor::<ApiAdmin>())
+        .with_state(tenancy)
+}
+
+/// The body of every error response:
+///
+/// ```json
+/// {"error": "invalid fields", "fields": [{"field": "email", "message": "is not a valid email address"}]}
+/// ```
+///
+/// `fields` is only sent when there are field errors. Server errors are
+/// logged with `tracing` and only give the status's reason.
+#[derive(Debug, Serialize)]
+pub struct ApiError {
+    #[serde(skip)]
+    pub status: StatusCode,
+    pub error: String,
+    #[serde(skip_serializing_if = "ValidationErrors::is_empty")]
+    pub fields: ValidationErrors,
+}
+
+impl ApiError {
+    fn new(status: StatusCode, error: impl Into<String>) -> ApiError {
+        ApiError {
+            status,
+            error: error.into(),
+            fields: ValidationErrors::new(),
+        }
+    }
+}
+
+impl From<TenancyError> for ApiError {
+    fn from(e: TenancyError) -> ApiError {
+        let status = e.status();
+        match e {
+            TenancyError::InvalidFields(fields) => ApiError {
+                status,
+                error: "invalid fields".to_string(),
+                fields,
+            },
+            e if status.is_server_error() => {
+                tracing::error!(error = %e, "axum-tenancy api request failed");
+                ApiError::new(status, status.canonical_reason().unwrap_or_default())
+            }
+            e => ApiError::new(status, e.to_string()),
+        }
+    }
+}
+
+impl From<sqlx::Error> for ApiError {
+    fn from(e: sqlx::Error) -> ApiError {
+        TenancyError::from(e).into()
+    }
+}
+
+impl From<JsonRejection> for ApiError {
+    fn from(e: JsonRejection) -> ApiError {
+        ApiError::new(e.status(), e.body_text())
+    }
+}
+
+impl From<QueryRejection> for ApiError {
+    fn from(e: QueryRejection) -> ApiError {
+        ApiError::new(e.status(), e.body_text())
+    }
+}
+
+impl IntoResponse for ApiError {
+    fn into_response(self) -> Response {
+        (self.status, Json(self)).into_response()
+    }
+}
+
+/// [`RequireAdmin`] answering with an [`ApiError`].
+struct ApiAdmin;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for ApiAdmin {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        match RequireAdmin::from_request_parts(parts, state).await {
+            Ok(_) => Ok(ApiAdmin),
+            Err(StatusCode::UNAUTHORIZED) => {
+                Err(ApiError::new(StatusCode::UNAUTHORIZED, "login required"))
+            }
+            Err(status) => Err(ApiError::new(status, "admin only")),
+        }
+    }
+}
+
+/// `Json` with its rejections as an [`ApiError`].
+struct ApiJson<T>(T);
+
+#[async_trait]
+impl<S: Send + Sync, T: DeserializeOwned> FromRequest<S> for ApiJson<T> {
+    type Rejection = ApiError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        let Json(value) = Json::<T>::from_request(req, state).await?;
+        Ok(ApiJson(value))
+    }
+}
+
+/// `Query` with its rejections as an [`ApiError`].
+struct ApiQuery<T>(T);
+
+#[async_trait]
+impl<S: Send + Sync, T: DeserializeOwned> FromRequestParts<S> for ApiQuery<T> {
+    type Rejection = ApiError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let Query(value) = Query::<T>::from_request_parts(parts, state).await?;
+        Ok(ApiQuery(value))
+    }
+}
+
+type ApiResult<T> = Result<T, ApiError>;
+
+#[cfg(test)]
+mod tests_tokio {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request},
+        Extension,
+    };
+    use axum_tenancy_core::admin_core::{password_core::PasswordPolicy, user_core::User};
+    use serde_json::{json, Value};
+    use test_context::test_context;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{admin::test_support::*, initialize, TenancyConfig};
+
+    async fn app(user: Option<User>) -> Router {
+        let config = TenancyConfig::default()
+            .run_migrations(false)
+            .user(TEST_USER_RULES)
+            .password(PasswordPolicy {
+                min_length: 8,
+                hash: TEST_PASSWORD_PARAMS,
+            });
+        let tenancy = initialize(get_committed_test_db_pool().await, config)
+            .await
+            .unwrap();
+        let router = Router::new().nest("/api", tenancy.api_router());
+        match user {
+            Some(u) => router.layer(Extension(u)),
+            None => router,
+        }
+    }
+
+    fn admin() -> User {
+        User {
+            user_name: "admin".to_string(),
+            is_admin: true,
+            ..Default::default()
+        }
+    }
+
+    async fn call(
+        app: &Router,
+        method: &str,
+        uri: &str,
+        body: Option<Value>,
+    ) -> (StatusCode, Value, Option<String>) {
+        let request = Request::builder().method(method).uri(uri);
+        let request = match body {
+            Some(body) => request
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(body.to_string())),
+            None => request.body(Body::empty()),
+        };
+        let response = app.clone().oneshot(request.unwrap()).await.unwrap();
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        let body = if body.is_empty() {
+            Value::Null
+        } else {
+            serde_json::from_slice(&body).unwrap()
+        };
+        (status, body, location)
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn errors_are_json(_tenancy_context: &mut TenancyTestContext) {
+        let (status, body, _) = call(&app(None).await, "GET", "/api/users", None).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        assert_eq!(body, json!({"error": "login required"}));
+        let not_admin = User {
+            is_admin: false,
+            ..admin()
+        };
+        let (status, body, _) = call(&app(Some(not_admin)).await, "GET", "/api/users", None).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        assert_eq!(body, json!({"error": "admin only"}));
+
+        let app = app(Some(admin())).await;
+        let (status, body, _) = call(&app, "GET", "/api/users?sort=hash_password", None).await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+        assert!(body["error"]
+            .as_str()
+            .unwrap()
+            .contains("unknown variant `hash_password`"));
+        let uri = format!("/api/users/{}", Uuid::new_v4());
+        let (status, body, _) = call(&app, "GET", &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        assert_eq!(body, json!({"error": "not found"}));
+        let (status, body, _) =
+            call(&app, "POST", "/api/users", Some(json!({"user_name": "x"}))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body["error"].as_str().unwrap().contains("missing field"));
+
+        let new = json!({
+            "user_name": "",
+            "password": "short",
+            "display_name": "Dave",
+            "email": "not an email",
+        });
+        let (status, body, _) = call(&app, "POST", "/api/users", Some(new)).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(body["error"], "invalid fields");
+        let fields: Vec<&str> = body["fields"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .map(|f| f["field"].as_str().unwrap())
+            .collect();
+        assert_eq!(fields, ["user_name", "email", "password"]);
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn users(_tenancy_context: &mut TenancyTestContext) {
+        let app = app(Some(admin())).await;
+        let name = format!("api-{}", Uuid::new_v4());
+        let new = json!({
+            "user_name": name,
+            "password": "password",
+            "display_name": name,
+            "mobile_phone": "01234567891",
+        });
+        let (status, created, location) = call(&app, "POST", "/api/users", Some(new.clone())).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(created["user_name"], name.as_str());
+        assert_eq!(created["mobile_phone"], "+441234567891");
+        assert!(created.get("hash_password").is_none());
+        let id = created["user_id"].as_str().unwrap();
+        let uri = format!("/api/users/{}", id);
+        assert_eq!(location, Some(uri.clone()));
+        let (status, _, _) = call(&app, "POST", "/api/users", Some(new)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+
+        let (status, got, _) = call(&app, "GET", &uri, None).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(got, created);
+
+        let (status, page, _) = call(
+            &app,
+            "GET",
+            &format!("/api/users?search={}&limit=5", name),
+            None,
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(page["total"], 1);
+        assert_eq!(page["items"][0]["user_id"], id);
+        assert_eq!(page["next_cursor"], Value::Null);
+        let (_, page, _) = call(
+            &app,
+            "GET",
+            &format!("/api/users?search={}&offset=1", name),
+            None,
+        )
+        .await;
+        assert_eq!(page["total"], 1);
+        assert_eq!(page["items"], json!([]));
+
+        let patch = json!({"version": 1, "display_name": "Patched", "is_admin": true});
+        let (status, patched, _) = call(&app, "PATCH", &uri, Some(patch.clone())).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(patched["display_name"], "Patched");
+        assert_eq!(patched["user_name"], name.as_str());
+        assert_eq!(patched["is_admin"], true);
+        assert_eq!(patched["version"], 2);
+        let (status, body, _) = call(&app, "PATCH", &uri, Some(patch)).await;
+        assert_eq!(status, StatusCode::CONFLICT);
+        assert_eq!(
+            body["error"],
+            "changed by someone else, reload and try again"
+        );
+
+        let (status, _, _) = call(&app, "DELETE", &uri, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _, _) = call(&app, "DELETE", &uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tenants_and_memberships(_tenancy_context: &mut TenancyTestContext) {
+        let app = app(Some(admin())).await;
+        let name = format!("api-{}", Uuid::new_v4());
+        let new = json!({"tenant_name": name, "display_name": name});
+        let (status, tenant, location) = call(&app, "POST", "/api/tenants", Some(new)).await;
+        assert_eq!(status, StatusCode::CREATED);
+        let tenant_id = tenant["tenant_id"].as_str().unwrap();
+        let tenant_uri = format!("/api/tenants/{}", tenant_id);
+        assert_eq!(location, Some(tenant_uri.clone()));
+
+        let (status, patched, _) = call(
+            &app,
+            "PATCH",
+            &tenant_uri,
+            Some(json!({"display_name": "Patched"})),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(patched["tenant_name"], name.as_str());
+        assert_eq!(patched["display_name"], "Patched");
+        let (_, page, _) = call(&app, "GET", "/api/tenants?sort=display_name&dir=desc", None).await;
+        assert!(page["items"]
+            .as_array()
+            .unwrap()
+            .iter()
+            .any(|t| t["tenant_id"] == tenant_id));
+
+        let new = json!({"user_name": name, "password": "password", "display_name": name});
+        let (_, user, _) = call(&app, "POST", "/api/users", Some(new)).await;
+        let user_id = user["user_id"].as_str().unwrap();
+        let membership = format!("/api/users/{}/tenants/{}", user_id, tenant_id);
+        let (status, ut, _) = call(&app, "PUT", &membership, None).await;
+        assert_eq!(status, StatusCode::CREATED);
+        assert_eq!(ut["tenant_id"], tenant_id);
+        assert_eq!(ut["role"], "member");
+        let role = Some(json!({"role": "accountant"}));
+        let (status, ut, _) = call(&app, "PUT", &membership, role).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(ut["role"], "accountant");
+        let typo = Some(json!({"rolle": "owner"}));
+        let (status, _, _) = call(&app, "PUT", &membership, typo).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        let (_, list, _) = call(
+            &app,
+            "GET",
+            &format!("/api/users/{}/tenants", user_id),
+            None,
+        )
+        .await;
+        assert_eq!(list, json!([ut]));
+
+        let (status, _, _) = call(&app, "DELETE", &membership, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _, _) = call(&app, "DELETE", &membership, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+        let (status, _, _) = call(&app, "DELETE", &tenant_uri, None).await;
+        assert_eq!(status, StatusCode::NO_CONTENT);
+        let (status, _, _) = call(&app, "GET", &tenant_uri, None).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
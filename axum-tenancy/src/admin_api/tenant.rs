@@ -0,0 +1,209 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! `/tenants` and the `/users/:user_id/tenants` memberships.
+
+use axum::{
+    body::Bytes,
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_tenancy_core::{
+    admin_core::{
+        page_core::Page,
+        role_core::Role,
+        tenant_core::{Tenant, TenantSort},
+        user_core::{Deleted, SortDirection},
+        user_tenant_core::UserTenant,
+    },
+    error::TenancyError,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{ApiJson, ApiQuery, ApiResult};
+use crate::{
+    admin::{tenant, user, user_tenant},
+    admin_ui::Base,
+    Tenancy,
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct ListParams {
+    sort: Option<TenantSort>,
+    dir: Option<SortDirection>,
+}
+
+/// Every tenant on one page, there are few enough not to need cursors.
+pub(super) async fn list(
+    State(tenancy): State<Tenancy>,
+    ApiQuery(params): ApiQuery<ListParams>,
+) -> ApiResult<Json<Page<Tenant>>> {
+    let mut tx = tenancy.pool().begin().await?;
+    let items = tenant::load_all_sorted(
+        &mut tx,
+        params.sort.unwrap_or(TenantSort::TenantName),
+        params.dir.unwrap_or(SortDirection::Asc),
+    )
+    .await?;
+    Ok(Json(Page {
+        total: items.len() as i64,
+        items,
+        next_cursor: None,
+    }))
+}
+
+pub(super) async fn get(
+    State(tenancy): State<Tenancy>,
+    Path(tenant_id): Path<Uuid>,
+) -> ApiResult<Json<Tenant>> {
+    let mut tx = tenancy.pool().begin().await?;
+    Ok(Json(tenant::load_by_id(&mut tx, tenant_id).await?))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct NewTenant {
+    tenant_name: String,
+    display_name: String,
+}
+
+/// 201 with the new tenant and its url in `Location`.
+pub(super) async fn create(
+    State(tenancy): State<Tenancy>,
+    base: Base,
+    ApiJson(new): ApiJson<NewTenant>,
+) -> ApiResult<Response> {
+    let tx = tenancy.pool().begin().await?;
+    let tenant_id = tenancy
+        .insert_tenant(tx, &new.tenant_name, &new.display_name)
+        .await?;
+    let mut tx = tenancy.pool().begin().await?;
+    let t = tenant::load_by_id(&mut tx, tenant_id).await?;
+    let location = format!("{}/tenants/{}", base.0, tenant_id);
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], Json(t)).into_response())
+}
+
+/// Only the fields sent are changed.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct TenantPatch {
+    tenant_name: Option<String>,
+    display_name: Option<String>,
+}
+
+pub(super) async fn patch(
+    State(tenancy): State<Tenancy>,
+    Path(tenant_id): Path<Uuid>,
+    ApiJson(patch): ApiJson<TenantPatch>,
+) -> ApiResult<Json<Tenant>> {
+    let mut tx = tenancy.pool().begin().await?;
+    let t = tenant::load_by_id(&mut tx, tenant_id).await?;
+    tenant::update(
+        &mut tx,
+        &tenant_id,
+        patch.tenant_name.as_deref().unwrap_or(&t.tenant_name),
+        patch.display_name.as_deref().unwrap_or(&t.display_name),
+    )
+    .await?;
+    let t = tenant::load_by_id(&mut tx, tenant_id).await?;
+    tx.commit().await?;
+    Ok(Json(t))
+}
+
+pub(super) async fn delete(
+    State(tenancy): State<Tenancy>,
+    Path(tenant_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let tx = tenancy.pool().begin().await?;
+    if tenancy.delete_tenant(tx, &tenant_id).await? == 0 {
+        return Err(TenancyError::NotFound.into());
+    }
+    Ok(StatusCode::NO_CONTENT)
+}
+
+pub(super) async fn memberships(
+    State(tenancy): State<Tenancy>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<Json<Vec<UserTenant>>> {
+    let mut tx = tenancy.pool().begin().await?;
+    // 404 for an unknown user rather than an empty list
+    user::load_by_id(&mut tx, user_id, Deleted::Exclude).await?;
+    Ok(Json(user_tenant::load_by_user(&mut tx, &user_id).await?))
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct Membership {
+    #[serde(default)]
+    role: Role,
+}
+
+/// Gives the user access with the role in the body, `member` if there is no
+/// body, 201 with the membership. If the user already has access their role
+/// is changed instead, 200.
+pub(super) async fn join(
+    State(tenancy): State<Tenancy>,
+    Path((user_id, tenant_id)): Path<(Uuid, Uuid)>,
+    body: Bytes,
+) -> ApiResult<Response> {
+    let Membership { role } = if body.is_empty() {
+        Membership::default()
+    } else {
+        Json::<Membership>::from_bytes(&body)?.0
+    };
+    let mut tx = tenancy.pool().begin().await?;
+    user::load_by_id(&mut tx, user_id, Deleted::Exclude).await?;
+    tenant::load_by_id(&mut tx, tenant_id).await?;
+    let (status, user_tenant_id) =
+        match user_tenant::load_by_user_and_tenant(&mut tx, &user_id, &tenant_id).await {
+            Ok(ut) => {
+                user_tenant::update(&mut tx, &ut.user_tenant_id, &user_id, &tenant_id, &role)
+                    .await?;
+                (StatusCode::OK, ut.user_tenant_id)
+            }
+            Err(TenancyError::NotFound) => (
+                StatusCode::CREATED,
+                user_tenant::insert(&mut tx, &user_id, &tenant_id, &role).await?,
+            ),
+            Err(e) => return Err(e.into()),
+        };
+    let ut = user_tenant::load_by_id(&mut tx, user_tenant_id).await?;
+    tx.commit().await?;
+    Ok((status, Json(ut)).into_response())
+}
+
+pub(super) async fn leave(
+    State(tenancy): State<Tenancy>,
+    Path((user_id, tenant_id)): Path<(Uuid, Uuid)>,
+) -> ApiResult<StatusCode> {
+    let mut tx = tenancy.pool().begin().await?;
+    let ut = user_tenant::load_by_user_and_tenant(&mut tx, &user_id, &tenant_id).await?;
+    user_tenant::delete(&mut tx, &ut.user_tenant_id).await?;
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
@@ -0,0 +1,193 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! `/users`
+
+use axum::{
+    extract::{Path, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use axum_tenancy_core::{
+    admin_core::{
+        page_core::Page,
+        user_core::{Deleted, SortDirection, User, UserQuery, UserSort},
+    },
+    error::TenancyError,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{ApiJson, ApiQuery, ApiResult};
+use crate::{admin::user, admin_ui::Base, Tenancy};
+
+const DEFAULT_LIMIT: u32 = 25;
+const MAX_LIMIT: u32 = 100;
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct ListParams {
+    sort: Option<UserSort>,
+    dir: Option<SortDirection>,
+    search: Option<String>,
+    is_admin: Option<bool>,
+    after: Option<String>,
+    /// Rows skipped, after `after` when both are given.
+    offset: Option<u32>,
+    /// At most 100.
+    limit: Option<u32>,
+}
+
+pub(super) async fn list(
+    State(tenancy): State<Tenancy>,
+    ApiQuery(params): ApiQuery<ListParams>,
+) -> ApiResult<Json<Page<User>>> {
+    let mut q = UserQuery::new()
+        .sort(
+            params.sort.unwrap_or(UserSort::UserName),
+            params.dir.unwrap_or(SortDirection::Asc),
+        )
+        .limit(params.limit.unwrap_or(DEFAULT_LIMIT).clamp(1, MAX_LIMIT));
+    if let Some(search) = params.search.as_deref().map(str::trim) {
+        if !search.is_empty() {
+            q = q.search(search);
+        }
+    }
+    if let Some(is_admin) = params.is_admin {
+        q = q.is_admin(is_admin);
+    }
+    if let Some(after) = &params.after {
+        q = q.after(after);
+    }
+    if let Some(offset) = params.offset {
+        q = q.offset(offset);
+    }
+    let mut tx = tenancy.pool().begin().await?;
+    Ok(Json(user::load_page(&mut tx, &q).await?))
+}
+
+pub(super) async fn get(
+    State(tenancy): State<Tenancy>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<Json<User>> {
+    let mut tx = tenancy.pool().begin().await?;
+    Ok(Json(
+        user::load_by_id(&mut tx, user_id, Deleted::Exclude).await?,
+    ))
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct NewUser {
+    user_name: String,
+    password: String,
+    display_name: String,
+    #[serde(default)]
+    is_admin: bool,
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    mobile_phone: String,
+}
+
+/// 201 with the new user and its url in `Location`.
+pub(super) async fn create(
+    State(tenancy): State<Tenancy>,
+    base: Base,
+    ApiJson(new): ApiJson<NewUser>,
+) -> ApiResult<Response> {
+    let config = tenancy.config();
+    let mut tx = tenancy.pool().begin().await?;
+    let user_id = user::insert(
+        &mut tx,
+        &new.user_name,
+        &new.password,
+        &new.display_name,
+        new.is_admin,
+        &new.email,
+        &new.mobile_phone,
+        &config.user,
+        &config.password,
+    )
+    .await?;
+    let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude).await?;
+    tx.commit().await?;
+    let location = format!("{}/users/{}", base.0, user_id);
+    Ok((StatusCode::CREATED, [(header::LOCATION, location)], Json(u)).into_response())
+}
+
+/// Only the fields sent are changed. `version` is the `User::version` the
+/// change was made from, a stale one is a 409.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub(super) struct UserPatch {
+    version: i64,
+    user_name: Option<String>,
+    display_name: Option<String>,
+    is_admin: Option<bool>,
+    email: Option<String>,
+    mobile_phone: Option<String>,
+    password: Option<String>,
+}
+
+pub(super) async fn patch(
+    State(tenancy): State<Tenancy>,
+    Path(user_id): Path<Uuid>,
+    ApiJson(patch): ApiJson<UserPatch>,
+) -> ApiResult<Json<User>> {
+    let config = tenancy.config();
+    let mut tx = tenancy.pool().begin().await?;
+    let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude).await?;
+    user::update(
+        &mut tx,
+        &user_id,
+        patch.version,
+        patch.user_name.as_deref().unwrap_or(&u.user_name),
+        patch.display_name.as_deref().unwrap_or(&u.display_name),
+        patch.is_admin.unwrap_or(u.is_admin),
+        patch.email.as_deref().unwrap_or(&u.email),
+        patch.mobile_phone.as_deref().unwrap_or(&u.mobile_phone),
+        &config.user,
+    )
+    .await?;
+    if let Some(password) = &patch.password {
+        user::set_password(&mut tx, &user_id, password, &config.password).await?;
+    }
+    let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude).await?;
+    tx.commit().await?;
+    Ok(Json(u))
+}
+
+pub(super) async fn delete(
+    State(tenancy): State<Tenancy>,
+    Path(user_id): Path<Uuid>,
+) -> ApiResult<StatusCode> {
+    let mut tx = tenancy.pool().begin().await?;
+    if user::delete(&mut tx, &user_id).await? == 0 {
+        return Err(TenancyError::NotFound.into());
+    }
+    tx.commit().await?;
+    Ok(StatusCode::NO_CONTENT)
+}
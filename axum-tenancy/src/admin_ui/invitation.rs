@@ -0,0 +1,791 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Sending invitations to a tenant and the pages their links lead to.
+
+use askama::Template;
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Form,
+};
+use axum_tenancy_core::{
+    admin_core::{
+        invitation_core::{Invitation, InvitationStatus},
+        role_core::Role,
+        tenant_core::Tenant,
+        user_core::User,
+        validation_core::{UserFields, ValidationErrors},
+    },
+    error::TenancyError,
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{see_other, user::field_errors, AdminUi, Base, Htmx};
+use crate::{
+    admin::{invitation, tenant},
+    extract::{CurrentTenant, CurrentUser, TenantPermissions},
+};
+
+/// What the invite form posts.
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct InviteForm {
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    role: String,
+}
+
+/// A new invitation's link, only available right after sending it.
+#[derive(Debug, Clone)]
+pub struct SentInvitation {
+    pub email: String,
+    pub url: String,
+}
+
+/// A tenant's invitations, newest first, and the invite form.
+#[derive(Template)]
+#[template(path = "admin/invitation_list.html")]
+pub struct InvitationListView {
+    pub base: String,
+    pub tenant: Tenant,
+    pub invitations: Vec<Invitation>,
+    /// The roles the user may invite with.
+    pub roles: Vec<String>,
+    /// The form as typed when redisplaying with errors.
+    pub email: String,
+    pub role: String,
+    pub errors: ValidationErrors,
+    pub sent: Option<SentInvitation>,
+    pub now: DateTime<Utc>,
+}
+
+impl InvitationListView {
+    /// The status, with pending invitations past their expiry shown as
+    /// expired.
+    pub fn status(&self, i: &Invitation) -> &'static str {
+        if i.status == InvitationStatus::Pending && !i.is_open(self.now) {
+            "expired"
+        } else {
+            i.status.as_str()
+        }
+    }
+
+    pub fn can_revoke(&self, i: &Invitation) -> bool {
+        i.status == InvitationStatus::Pending
+    }
+
+    pub fn is_selected(&self, role: &str) -> bool {
+        self.role == role
+    }
+}
+
+/// The roles `perms` may hand out, by name.
+fn grantable_roles(perms: &TenantPermissions) -> Vec<String> {
+    let roles = perms.roles();
+    roles
+        .names()
+        .filter(|name| roles.can_grant(perms.role(), &Role::from(*name)))
+        .map(str::to_string)
+        .collect()
+}
+
+async fn list_view(
+    ui: &AdminUi,
+    base: &Base,
+    tenant: Tenant,
+    perms: &TenantPermissions,
+) -> Result<InvitationListView, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let invitations = invitation::load_by_tenant(&mut tx, &tenant.tenant_id).await?;
+    Ok(InvitationListView {
+        base: base.0.clone(),
+        tenant,
+        invitations,
+        roles: grantable_roles(perms),
+        email: String::new(),
+        role: Role::default().to_string(),
+        errors: ValidationErrors::new(),
+        sent: None,
+        now: Utc::now(),
+    })
+}
+
+fn list_page(
+    ui: &AdminUi,
+    htmx: Htmx,
+    base: &Base,
+    view: InvitationListView,
+) -> Result<Response, TenancyError> {
+    let title = format!("Invitations to {}", view.tenant.display_name);
+    let body = ui.templates.invitation_list(&view)?;
+    ui.page_with(htmx, base, &title, body, false)
+}
+
+pub(super) async fn list(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    CurrentTenant(tenant): CurrentTenant,
+    perms: TenantPermissions,
+) -> Result<Response, TenancyError> {
+    let view = list_view(&ui, &base, tenant, &perms).await?;
+    list_page(&ui, htmx, &base, view)
+}
+
+pub(super) async fn create(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    CurrentTenant(tenant): CurrentTenant,
+    perms: TenantPermissions,
+    Form(form): Form<InviteForm>,
+) -> Result<Response, TenancyError> {
+    let role = Role::from(form.role.trim());
+    let result = if grantable_roles(&perms).iter().any(|r| r == role.as_str()) {
+        let mut tx = ui.tenancy.pool().begin().await?;
+        let result = invitation::invite(
+            &mut tx,
+            &tenant.tenant_id,
+            &form.email,
+            &role,
+            Some(&perms.membership.user_id),
+            ui.tenancy.config().invitation.valid_for(),
+        )
+        .await;
+        if result.is_ok() {
+            tx.commit().await?;
+        }
+        result
+    } else {
+        let mut errors = ValidationErrors::new();
+        errors.add("role", "is not one you can invite with");
+        Err(errors.into())
+    };
+    let mut view = list_view(&ui, &base, tenant, &perms).await?;
+    match result {
+        Ok((invitation, token)) => {
+            let url = format!("{}/{}", ui.invite_url, token);
+            if let Some(on_invite) = &ui.on_invite {
+                on_invite(&invitation, &url);
+            }
+            view.sent = Some(SentInvitation {
+                email: invitation.email,
+                url,
+            });
+            list_page(&ui, htmx, &base, view)
+        }
+        Err(e) => {
+            view.errors = field_errors(e)?;
+            view.email = form.email;
+            view.role = form.role;
+            let response = list_page(&ui, htmx, &base, view)?;
+            // htmx only swaps successful responses
+            if htmx.request {
+                Ok(response)
+            } else {
+                Ok((StatusCode::UNPROCESSABLE_ENTITY, response).into_response())
+            }
+        }
+    }
+}
+
+pub(super) async fn revoke(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    CurrentTenant(tenant): CurrentTenant,
+    Path(invitation_id): Path<Uuid>,
+) -> Result<Response, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    invitation::revoke(&mut tx, &tenant.tenant_id, &invitation_id).await?;
+    tx.commit().await?;
+    Ok(see_other(htmx, &format!("{}/invitations", base.0)))
+}
+
+/// What the register form posts.
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct RegisterForm {
+    user_name: String,
+    display_name: String,
+    #[serde(default)]
+    mobile_phone: String,
+    #[serde(default)]
+    password: String,
+}
+
+/// An open invitation, with an accept button for a logged in user and a
+/// form for creating a user otherwise.
+#[derive(Template)]
+#[template(path = "admin/invitation_accept.html")]
+pub struct InvitationAcceptView {
+    pub base: String,
+    pub token: String,
+    pub invitation: Invitation,
+    pub tenant: Tenant,
+    pub user: Option<User>,
+    /// The register form as typed, the email is the invitation's.
+    pub fields: UserFields,
+    pub errors: ValidationErrors,
+}
+
+/// The outcome of answering an invitation.
+#[derive(Template)]
+#[template(path = "admin/invitation_done.html")]
+pub struct InvitationDoneView {
+    pub base: String,
+    pub title: String,
+    pub message: String,
+}
+
+fn done(
+    ui: &AdminUi,
+    htmx: Htmx,
+    base: &Base,
+    title: &str,
+    message: String,
+) -> Result<Response, TenancyError> {
+    let body = ui.templates.invitation_done(&InvitationDoneView {
+        base: base.0.clone(),
+        title: title.to_string(),
+        message,
+    })?;
+    ui.page_with(htmx, base, title, body, false)
+}
+
+/// A page saying why the link can't be used, anything else is passed on.
+fn closed(
+    ui: &AdminUi,
+    htmx: Htmx,
+    base: &Base,
+    e: TenancyError,
+) -> Result<Response, TenancyError> {
+    match e {
+        TenancyError::InvitationExpired | TenancyError::InvitationUsed => {
+            let status = e.status();
+            let response = done(ui, htmx, base, "Invitation", format!("This {}.", e))?;
+            Ok((status, response).into_response())
+        }
+        e => Err(e),
+    }
+}
+
+async fn accept_page(
+    ui: &AdminUi,
+    htmx: Htmx,
+    base: &Base,
+    token: String,
+    user: Option<User>,
+    fields: UserFields,
+    errors: ValidationErrors,
+) -> Result<Response, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let invitation = match invitation::load_open(&mut tx, &token).await {
+        Ok(invitation) => invitation,
+        Err(e) => return closed(ui, htmx, base, e),
+    };
+    let tenant = tenant::load_by_id(&mut tx, invitation.tenant_id).await?;
+    let title = format!("Join {}", tenant.display_name);
+    let body = ui.templates.invitation_accept(&InvitationAcceptView {
+        base: base.0.clone(),
+        token,
+        invitation,
+        tenant,
+        user,
+        fields,
+        errors,
+    })?;
+    ui.page_with(htmx, base, &title, body, false)
+}
+
+pub(super) async fn show(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    user: Option<CurrentUser>,
+    Path(token): Path<String>,
+) -> Result<Response, TenancyError> {
+    let user = user.map(|u| u.0);
+    accept_page(
+        &ui,
+        htmx,
+        &base,
+        token,
+        user,
+        UserFields::default(),
+        ValidationErrors::new(),
+    )
+    .await
+}
+
+pub(super) async fn accept(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    CurrentUser(user): CurrentUser,
+    Path(token): Path<String>,
+) -> Result<Response, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let invitation = match invitation::load_open(&mut tx, &token).await {
+        Ok(invitation) => invitation,
+        Err(e) => return closed(&ui, htmx, &base, e),
+    };
+    invitation::accept(&mut tx, &token, &user.user_id).await?;
+    let tenant = tenant::load_by_id(&mut tx, invitation.tenant_id).await?;
+    tx.commit().await?;
+    done(
+        &ui,
+        htmx,
+        &base,
+        "Invitation accepted",
+        format!("You have joined {}.", tenant.display_name),
+    )
+}
+
+pub(super) async fn register(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Path(token): Path<String>,
+    Form(form): Form<RegisterForm>,
+) -> Result<Response, TenancyError> {
+    let config = ui.tenancy.config();
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let invitation = match invitation::load_open(&mut tx, &token).await {
+        Ok(invitation) => invitation,
+        Err(e) => return closed(&ui, htmx, &base, e),
+    };
+    let fields = UserFields::new(
+        &form.user_name,
+        &form.display_name,
+        &invitation.email,
+        &form.mobile_phone,
+    );
+    let result = invitation::accept_new_user(
+        &mut tx,
+        &token,
+        &form.user_name,
+        &form.password,
+        &form.display_name,
+        &form.mobile_phone,
+        &config.user,
+        &config.password,
+    )
+    .await;
+    match result {
+        Ok(_) => {
+            let tenant = tenant::load_by_id(&mut tx, invitation.tenant_id).await?;
+            tx.commit().await?;
+            done(
+                &ui,
+                htmx,
+                &base,
+                "Invitation accepted",
+                format!(
+                    "You have joined {}, log in as {} to start.",
+                    tenant.display_name, form.user_name
+                ),
+            )
+        }
+        Err(e) => {
+            let errors = field_errors(e)?;
+            drop(tx);
+            let response = accept_page(&ui, htmx, &base, token, None, fields, errors).await?;
+            if htmx.request {
+                Ok(response)
+            } else {
+                Ok((StatusCode::UNPROCESSABLE_ENTITY, response).into_response())
+            }
+        }
+    }
+}
+
+pub(super) async fn decline(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Path(token): Path<String>,
+) -> Result<Response, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    if let Err(e) = invitation::decline(&mut tx, &token).await {
+        return closed(&ui, htmx, &base, e);
+    }
+    tx.commit().await?;
+    done(
+        &ui,
+        htmx,
+        &base,
+        "Invitation declined",
+        "The invitation has been declined.".to_string(),
+    )
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use std::sync::{Arc, Mutex};
+
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request},
+        Extension, Router,
+    };
+    use axum_tenancy_core::admin_core::{password_core::PasswordPolicy, user_core::Deleted};
+    use test_context::test_context;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        admin::{test_support::*, user, user_tenant},
+        initialize, Tenancy, TenancyConfig,
+    };
+
+    async fn tenancy() -> Tenancy {
+        let config = TenancyConfig::default()
+            .run_migrations(false)
+            .user(TEST_USER_RULES)
+            .password(PasswordPolicy {
+                min_length: 8,
+                hash: TEST_PASSWORD_PARAMS,
+            });
+        initialize(get_committed_test_db_pool().await, config)
+            .await
+            .unwrap()
+    }
+
+    /// Commits a tenant and a user with `role` in it, returns both.
+    async fn setup(tenancy: &Tenancy, role: Role) -> (Tenant, User) {
+        let suffix = Uuid::new_v4().simple().to_string();
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let user_id = user::insert(
+            &mut tx,
+            &format!("inv{}", suffix),
+            "password",
+            &format!("Inviter {}", suffix),
+            false,
+            "",
+            "",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        let name = format!("inv{}", suffix);
+        let tenant_id = tenant::insert(&mut tx, &name, &format!("Church {}", suffix))
+            .await
+            .unwrap();
+        user_tenant::insert(&mut tx, &user_id, &tenant_id, &role)
+            .await
+            .unwrap();
+        let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        let t = tenant::load_by_id(&mut tx, tenant_id).await.unwrap();
+        tx.commit().await.unwrap();
+        (t, u)
+    }
+
+    /// The invitations router for the tenant named in `x-tenant`.
+    fn members_app(tenancy: &Tenancy, ui: AdminUi, user: User) -> Router {
+        let layer = tenancy.layer(|parts: &axum::http::request::Parts| {
+            parts
+                .headers
+                .get("x-tenant")
+                .and_then(|v| v.to_str().ok())
+                .map(|v| v.to_string())
+        });
+        Router::new()
+            .nest("/members", ui.invitations_router())
+            .route_layer(layer)
+            .layer(Extension(user))
+    }
+
+    fn accept_app(tenancy: &Tenancy, user: Option<User>) -> Router {
+        let router = Router::new().nest("/invite", tenancy.admin_ui().accept_router());
+        match user {
+            Some(u) => router.layer(Extension(u)),
+            None => router,
+        }
+    }
+
+    async fn send(router: Router, request: Request<Body>) -> (StatusCode, String) {
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn request(
+        method: &str,
+        uri: &str,
+        tenant: &str,
+        form: &impl serde::Serialize,
+    ) -> Request<Body> {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("x-tenant", tenant)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(serde_urlencoded::to_string(form).unwrap()))
+            .unwrap()
+    }
+
+    async fn invite(tenancy: &Tenancy, tenant: &Tenant, role: Role) -> String {
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let (_, token) = invitation::invite(
+            &mut tx,
+            &tenant.tenant_id,
+            "anna@example.com",
+            &role,
+            None,
+            chrono::Duration::days(1),
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+        token
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn members_send_and_revoke(_tenancy_context: &mut TenancyTestContext) {
+        let tenancy = tenancy().await;
+        let (t, admin) = setup(&tenancy, Role::Admin).await;
+        let sent = Arc::new(Mutex::new(Vec::new()));
+        let on_invite = sent.clone();
+        let ui = tenancy
+            .admin_ui()
+            .invite_url("https://example.com/join/")
+            .on_invite(move |i, url| {
+                on_invite
+                    .lock()
+                    .unwrap()
+                    .push((i.email.clone(), url.to_string()))
+            });
+        let app = members_app(&tenancy, ui, admin);
+        let empty: [(&str, &str); 0] = [];
+
+        let (status, body) = send(
+            app.clone(),
+            request("GET", "/members/invitations", &t.tenant_name, &empty),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(&format!("Invitations to {}", t.display_name)));
+        // admins can't make owners
+        assert!(body.contains("<option value=\"viewer\""));
+        assert!(!body.contains("<option value=\"owner\""));
+
+        let (status, body) = send(
+            app.clone(),
+            request(
+                "POST",
+                "/members/invitations",
+                &t.tenant_name,
+                &[("email", "anna@example.com"), ("role", "owner")],
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.contains("Role is not one you can invite with"));
+
+        let (status, body) = send(
+            app.clone(),
+            request(
+                "POST",
+                "/members/invitations",
+                &t.tenant_name,
+                &[("email", "anna@example.com"), ("role", "viewer")],
+            ),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        let sent = sent.lock().unwrap().clone();
+        assert_eq!(sent.len(), 1);
+        assert_eq!(sent[0].0, "anna@example.com");
+        assert!(sent[0].1.starts_with("https://example.com/join/"));
+        assert!(body.contains(&sent[0].1));
+
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let invitations = invitation::load_by_tenant(&mut tx, &t.tenant_id)
+            .await
+            .unwrap();
+        drop(tx);
+        assert_eq!(invitations.len(), 1);
+        assert_eq!(invitations[0].role, Role::Viewer);
+        let revoke = format!(
+            "/members/invitations/{}/revoke",
+            invitations[0].invitation_id
+        );
+        let (status, _) = send(
+            app.clone(),
+            request("POST", &revoke, &t.tenant_name, &empty),
+        )
+        .await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        let (_, body) = send(
+            app,
+            request("GET", "/members/invitations", &t.tenant_name, &empty),
+        )
+        .await;
+        assert!(body.contains("<td>revoked</td>"));
+        assert!(!body.contains("Revoke</button>"));
+
+        // members can't invite
+        let (t, member) = setup(&tenancy, Role::Member).await;
+        let (status, _) = send(
+            members_app(&tenancy, tenancy.admin_ui(), member),
+            request("GET", "/members/invitations", &t.tenant_name, &empty),
+        )
+        .await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn register_from_the_link(_tenancy_context: &mut TenancyTestContext) {
+        let tenancy = tenancy().await;
+        let (t, _) = setup(&tenancy, Role::Owner).await;
+        let token = invite(&tenancy, &t, Role::Viewer).await;
+        let link = format!("/invite/{}", token);
+        let register = format!("{}/register", link);
+        let user_name = format!("anna{}", Uuid::new_v4().simple());
+        let empty: [(&str, &str); 0] = [];
+
+        let (status, body) = send(
+            accept_app(&tenancy, None),
+            request("GET", &link, "", &empty),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(&format!("Join {}", t.display_name)));
+        assert!(body.contains("anna@example.com"));
+        assert!(body.contains("Create account and join"));
+
+        let form = |password| {
+            [
+                ("user_name", user_name.clone()),
+                ("display_name", user_name.clone()),
+                ("password", password),
+            ]
+        };
+        let (status, body) = send(
+            accept_app(&tenancy, None),
+            request("POST", &register, "", &form("short".to_string())),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.contains("Password must be at least 8 characters"));
+
+        let (status, body) = send(
+            accept_app(&tenancy, None),
+            request("POST", &register, "", &form("long enough".to_string())),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(&format!("You have joined {}", t.display_name)));
+
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let invitation = invitation::load_by_token(&mut tx, &token).await.unwrap();
+        let anna = user::load_by_id(&mut tx, invitation.accepted_by.unwrap(), Deleted::Exclude)
+            .await
+            .unwrap();
+        assert_eq!(anna.user_name, user_name);
+        assert_eq!(anna.email, "anna@example.com");
+        let ut = user_tenant::load_by_user_and_tenant(&mut tx, &anna.user_id, &t.tenant_id)
+            .await
+            .unwrap();
+        assert_eq!(ut.role, Role::Viewer);
+        drop(tx);
+
+        let (status, body) = send(
+            accept_app(&tenancy, None),
+            request("GET", &link, "", &empty),
+        )
+        .await;
+        assert_eq!(status, StatusCode::GONE);
+        assert!(body.contains("This invitation is no longer valid."));
+        let (status, _) = send(
+            accept_app(&tenancy, None),
+            request("GET", "/invite/nonsense", "", &empty),
+        )
+        .await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn accept_or_decline(_tenancy_context: &mut TenancyTestContext) {
+        let tenancy = tenancy().await;
+        let (t, _) = setup(&tenancy, Role::Owner).await;
+        let (_, existing) = setup(&tenancy, Role::Member).await;
+        let empty: [(&str, &str); 0] = [];
+
+        let token = invite(&tenancy, &t, Role::Admin).await;
+        let link = format!("/invite/{}", token);
+        let (status, _) = send(
+            accept_app(&tenancy, None),
+            request("POST", &format!("{}/accept", link), "", &empty),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let (_, body) = send(
+            accept_app(&tenancy, Some(existing.clone())),
+            request("GET", &link, "", &empty),
+        )
+        .await;
+        assert!(body.contains(&format!("Accept as {}", existing.display_name)));
+        let (status, body) = send(
+            accept_app(&tenancy, Some(existing.clone())),
+            request("POST", &format!("{}/accept", link), "", &empty),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("You have joined"));
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let ut = user_tenant::load_by_user_and_tenant(&mut tx, &existing.user_id, &t.tenant_id)
+            .await
+            .unwrap();
+        assert_eq!(ut.role, Role::Admin);
+        drop(tx);
+
+        let token = invite(&tenancy, &t, Role::Member).await;
+        let decline = format!("/invite/{}/decline", token);
+        let (status, body) = send(
+            accept_app(&tenancy, None),
+            request("POST", &decline, "", &empty),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("declined"));
+        let (status, _) = send(
+            accept_app(&tenancy, None),
+            request("POST", &decline, "", &empty),
+        )
+        .await;
+        assert_eq!(status, StatusCode::GONE);
+    }
+}
@@ -0,0 +1,322 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Server rendered admin pages, built with askama and enhanced with htmx.
+//!
+//! [`admin_router`] only works out its own links from the request, so it can
+//! be nested under any prefix. It must sit behind the authentication layer
+//! and [`user_to_extensions`](crate::auth::user_to_extensions), every page
+//! needs an admin:
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .nest("/admin", tenancy.admin_router())
+//!     .layer(axum::middleware::from_fn(user_to_extensions))
+//!     .layer(tenancy.auth_layer(session_store));
+//! ```
+//!
+//! Links and forms work without JavaScript. With htmx, sorting, paging and
+//! searching swap just the user or tenant table and forms are re-rendered
+//! in place with their errors.
+//!
+//! The html comes from a [`TemplateProvider`], see [`templates`] for using
+//! your own. To embed the pages in an application's own pages, turn on
+//! [`AdminUi::fragments_only`] and load them with htmx:
+//!
+//! ```ignore
+//! let app = Router::new()
+//!     .nest("/admin", tenancy.admin_ui().fragments_only(true).router());
+//! // in the application's page
+//! // <div hx-get="/admin/users" hx-trigger="load"></div>
+//! ```
+//!
+//! Tenant invitations have two more routers. [`AdminUi::invitations_router`]
+//! lets the members of a tenant who have `members:invite` send and revoke
+//! invitations, so it goes behind the [`TenancyLayer`](crate::TenancyLayer).
+//! [`AdminUi::accept_router`] is where the links sent lead, it must be
+//! mounted at [`AdminUi::invite_url`]:
+//!
+//! ```ignore
+//! let admin = tenancy.admin_ui().on_invite(|invitation, url| send_email(invitation, url));
+//! // the resolver sees `/t/acme/members/...`, so nest inside the layer
+//! let tenant_routes = Router::new()
+//!     .nest("/t/:tenant/members", admin.clone().invitations_router())
+//!     .layer(tenancy.layer(PathPrefixResolver::new("t")));
+//! let app = Router::new()
+//!     .merge(tenant_routes)
+//!     .nest("/invite", admin.accept_router())
+//!     .layer(axum::middleware::from_fn(user_to_extensions))
+//!     .layer(tenancy.auth_layer(session_store));
+//! ```
+
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::{FromRequestParts, OriginalUri},
+    http::{request::Parts, HeaderMap, StatusCode, Uri},
+    middleware,
+    response::{Html, IntoResponse, Redirect, Response},
+    routing::{get, post},
+    Router,
+};
+use axum_tenancy_core::{admin_core::invitation_core::Invitation, error::TenancyError};
+
+use crate::{extract::RequireAdmin, permission::require_permission, Tenancy};
+
+mod invitation;
+pub mod templates;
+mod tenant;
+mod user;
+
+pub use templates::{AskamaTemplates, LayoutView, TemplateProvider};
+
+/// The admin pages with the built-in templates, see the [module docs](self).
+pub fn admin_router(tenancy: Tenancy) -> Router {
+    AdminUi::new(tenancy).router()
+}
+
+type OnInvite = Arc<dyn Fn(&Invitation, &str) + Send + Sync>;
+
+/// Builds the admin routers, shared with their handlers.
+#[derive(Clone)]
+pub struct AdminUi {
+    tenancy: Tenancy,
+    templates: Arc<dyn TemplateProvider>,
+    fragments_only: bool,
+    invite_url: String,
+    on_invite: Option<OnInvite>,
+}
+
+impl AdminUi {
+    pub fn new(tenancy: Tenancy) -> AdminUi {
+        AdminUi {
+            tenancy,
+            templates: Arc::new(AskamaTemplates),
+            fragments_only: false,
+            invite_url: "/invite".to_string(),
+            on_invite: None,
+        }
+    }
+
+    /// Replaces the built-in askama templates.
+    pub fn templates(mut self, templates: impl TemplateProvider) -> AdminUi {
+        self.templates = Arc::new(templates);
+        self
+    }
+
+    /// Never wrap pages in the layout, for embedding them in an
+    /// application's own pages.
+    pub fn fragments_only(mut self, fragments_only: bool) -> AdminUi {
+        self.fragments_only = fragments_only;
+        self
+    }
+
+    /// Where [`accept_router`](AdminUi::accept_router) is mounted, invitation
+    /// links are this followed by `/` and the token. `/invite` by default,
+    /// give the full url when the links are emailed.
+    pub fn invite_url(mut self, invite_url: &str) -> AdminUi {
+        self.invite_url = invite_url.trim_end_matches('/').to_string();
+        self
+    }
+
+    /// Called with each new invitation and its link, e.g. to email it. The
+    /// link is also shown to whoever sent the invitation.
+    pub fn on_invite(
+        mut self,
+        on_invite: impl Fn(&Invitation, &str) + Send + Sync + 'static,
+    ) -> AdminUi {
+        self.on_invite = Some(Arc::new(on_invite));
+        self
+    }
+
+    pub fn router(self) -> Router {
+        Router::new()
+            .route("/", get(user::home))
+            .route("/users", get(user::list).post(user::create))
+            .route("/users/new", get(user::new))
+            .route("/users/:user_id", get(user::edit).post(user::update))
+            .route(
+                "/users/:user_id/delete",
+                get(user::confirm_delete).post(user::delete),
+            )
+            .route("/tenants", get(tenant::list).post(tenant::create))
+            .route("/tenants/new", get(tenant::new))
+            .route(
+                "/tenants/:tenant_id",
+                get(tenant::edit).post(tenant::update),
+            )
+            .route(
+                "/tenants/:tenant_id/delete",
+                get(tenant::confirm_delete).post(tenant::delete),
+            )
+            .route_layer(middleware::from_extractor::<RequireAdmin>())
+            .with_state(self)
+    }
+
+    /// Sending and revoking invitations to the current tenant, for members
+    /// with the `members:invite` permission. Must be behind the
+    /// [`TenancyLayer`](crate::TenancyLayer).
+    pub fn invitations_router(self) -> Router {
+        Router::new()
+            .route(
+                "/invitations",
+                get(invitation::list).post(invitation::create),
+            )
+            .route(
+                "/invitations/:invitation_id/revoke",
+                post(invitation::revoke),
+            )
+            .route_layer(require_permission("members:invite"))
+            .with_state(self)
+    }
+
+    /// Where invitation links lead. Anyone with the link can see the
+    /// invitation, a logged in user can accept it and anyone else can
+    /// create a user to accept it with.
+    pub fn accept_router(self) -> Router {
+        Router::new()
+            .route("/:token", get(invitation::show))
+            .route("/:token/accept", post(invitation::accept))
+            .route("/:token/register", post(invitation::register))
+            .route("/:token/decline", post(invitation::decline))
+            .with_state(self)
+    }
+
+    /// `body` alone for fragment only and partial htmx requests, otherwise
+    /// wrapped in the layout.
+    fn page(
+        &self,
+        htmx: Htmx,
+        base: &Base,
+        title: &str,
+        body: String,
+    ) -> Result<Response, TenancyError> {
+        self.page_with(htmx, base, title, body, true)
+    }
+
+    /// As `page`, `nav` is off for pages outside the admin router.
+    fn page_with(
+        &self,
+        htmx: Htmx,
+        base: &Base,
+        title: &str,
+        body: String,
+        nav: bool,
+    ) -> Result<Response, TenancyError> {
+        if self.fragments_only || htmx.partial() {
+            return Ok(Html(body).into_response());
+        }
+        let page = self.templates.layout(&LayoutView {
+            base: &base.0,
+            title,
+            body: &body,
+            nav,
+        })?;
+        Ok(Html(page).into_response())
+    }
+}
+
+/// The path the router is mounted at, e.g. `/admin`, empty at the root.
+#[derive(Debug, Clone)]
+pub(crate) struct Base(pub String);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Base {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        // outside a nest there is no OriginalUri and nothing to strip
+        let original = parts
+            .extensions
+            .get::<OriginalUri>()
+            .map(|o| o.0.clone())
+            .unwrap_or_else(|| parts.uri.clone());
+        Ok(Base(base_path(&original, &parts.uri)))
+    }
+}
+
+fn base_path(original: &Uri, nested: &Uri) -> String {
+    let original = original.path();
+    let nested = nested.path().trim_end_matches('/');
+    original
+        .strip_suffix(nested)
+        .unwrap_or(original)
+        .trim_end_matches('/')
+        .to_string()
+}
+
+/// How htmx sent the request. Boosted requests swap the whole body so get
+/// full pages, other htmx requests only want the part being swapped.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Htmx {
+    pub request: bool,
+    pub boosted: bool,
+}
+
+impl Htmx {
+    pub fn partial(&self) -> bool {
+        self.request && !self.boosted
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for Htmx {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let is_set = |headers: &HeaderMap, name| {
+            headers.get(name).and_then(|v| v.to_str().ok()) == Some("true")
+        };
+        Ok(Htmx {
+            request: is_set(&parts.headers, "hx-request"),
+            boosted: is_set(&parts.headers, "hx-boosted"),
+        })
+    }
+}
+
+/// After a successful post: htmx is told to load `url`, browsers are
+/// redirected to it.
+fn see_other(htmx: Htmx, url: &str) -> Response {
+    if htmx.request {
+        [("hx-redirect", url)].into_response()
+    } else {
+        Redirect::to(url).into_response()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn base_path_is_the_nest_prefix() {
+        let uri = |s: &str| s.parse::<Uri>().unwrap();
+        assert_eq!(base_path(&uri("/admin/users"), &uri("/users")), "/admin");
+        assert_eq!(base_path(&uri("/admin"), &uri("/")), "/admin");
+        assert_eq!(base_path(&uri("/admin/"), &uri("/")), "/admin");
+        assert_eq!(base_path(&uri("/a/b/users/1"), &uri("/users/1")), "/a/b");
+        assert_eq!(base_path(&uri("/users"), &uri("/users")), "");
+    }
+}
@@ -0,0 +1,130 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! How the admin pages are turned into html.
+//!
+//! Every page is rendered through a [`TemplateProvider`]. Its methods default
+//! to the built-in askama templates, so an application only overrides the
+//! ones it wants, e.g. just [`layout`](TemplateProvider::layout) to put the
+//! pages in its own chrome:
+//!
+//! ```ignore
+//! struct MyTemplates;
+//!
+//! impl TemplateProvider for MyTemplates {
+//!     fn layout(&self, view: &LayoutView) -> Result<String, TenancyError> {
+//!         render(&MyLayout { title: view.title, main: view.body })
+//!     }
+//! }
+//!
+//! let admin = tenancy.admin_ui().templates(MyTemplates).router();
+//! ```
+//!
+//! The views passed in have public fields and the helper methods the
+//! built-in templates use, any template engine can be used with them.
+
+use askama::Template;
+use axum_tenancy_core::error::TenancyError;
+
+pub use super::{
+    invitation::{InvitationAcceptView, InvitationDoneView, InvitationListView},
+    tenant::{TenantDeleteView, TenantFormView, TenantListView},
+    user::{UserDeleteView, UserFormView, UserListView},
+};
+
+/// The page around every fragment, unless the request only wants the
+/// fragment.
+#[derive(Template)]
+#[template(path = "admin/layout.html")]
+pub struct LayoutView<'a> {
+    /// The path the admin router is mounted at, for links.
+    pub base: &'a str,
+    pub title: &'a str,
+    /// The rendered fragment, already escaped.
+    pub body: &'a str,
+    /// Show the admin links, off for the invitation pages.
+    pub nav: bool,
+}
+
+/// Renders the admin pages. Each method gets a view and returns html.
+pub trait TemplateProvider: Send + Sync + 'static {
+    fn layout(&self, view: &LayoutView) -> Result<String, TenancyError> {
+        render(view)
+    }
+
+    fn user_list(&self, view: &UserListView) -> Result<String, TenancyError> {
+        render(view)
+    }
+
+    /// Both the create and edit forms, `view.user_id` is `None` when
+    /// creating.
+    fn user_form(&self, view: &UserFormView) -> Result<String, TenancyError> {
+        render(view)
+    }
+
+    fn user_delete(&self, view: &UserDeleteView) -> Result<String, TenancyError> {
+        render(view)
+    }
+
+    fn tenant_list(&self, view: &TenantListView) -> Result<String, TenancyError> {
+        render(view)
+    }
+
+    /// Both the create and edit forms, `view.tenant_id` is `None` when
+    /// creating.
+    fn tenant_form(&self, view: &TenantFormView) -> Result<String, TenancyError> {
+        render(view)
+    }
+
+    fn tenant_delete(&self, view: &TenantDeleteView) -> Result<String, TenancyError> {
+        render(view)
+    }
+
+    /// A tenant's invitations with the form for sending another.
+    fn invitation_list(&self, view: &InvitationListView) -> Result<String, TenancyError> {
+        render(view)
+    }
+
+    /// What the invitation link shows.
+    fn invitation_accept(&self, view: &InvitationAcceptView) -> Result<String, TenancyError> {
+        render(view)
+    }
+
+    /// After answering, or for a link that can no longer be used.
+    fn invitation_done(&self, view: &InvitationDoneView) -> Result<String, TenancyError> {
+        render(view)
+    }
+}
+
+/// The built-in askama templates.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AskamaTemplates;
+
+impl TemplateProvider for AskamaTemplates {}
+
+/// Renders an askama template, for providers using their own.
+pub fn render(t: &impl Template) -> Result<String, TenancyError> {
+    t.render()
+        .map_err(|e| TenancyError::Template(e.to_string()))
+}
@@ -0,0 +1,452 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! The tenant list, create and edit forms and delete confirmation.
+
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Response},
+    Form,
+};
+use axum_tenancy_core::{
+    admin_core::{
+        tenant_core::{Tenant, TenantSort},
+        user_core::SortDirection,
+        validation_core::{normalize_display_name, ValidationErrors},
+    },
+    error::TenancyError,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{see_other, AdminUi, Base, Htmx};
+use crate::admin::tenant;
+
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct ListParams {
+    sort: Option<TenantSort>,
+    dir: Option<SortDirection>,
+}
+
+/// Every tenant, there are few enough not to need paging.
+#[derive(Template)]
+#[template(path = "admin/tenant_list.html")]
+pub struct TenantListView {
+    pub base: String,
+    pub tenants: Vec<Tenant>,
+    pub sort: TenantSort,
+    pub dir: SortDirection,
+}
+
+impl TenantListView {
+    /// Clicking the current sort column reverses it.
+    pub fn sort_href(&self, sort: &TenantSort) -> String {
+        let dir = if *sort == self.sort {
+            self.dir.reverse()
+        } else {
+            SortDirection::Asc
+        };
+        format!(
+            "{}/tenants?sort={}&dir={}",
+            self.base,
+            sort.as_str(),
+            dir.as_str().to_lowercase()
+        )
+    }
+
+    pub fn arrow(&self, sort: &TenantSort) -> &'static str {
+        match (*sort == self.sort, self.dir) {
+            (false, _) => "",
+            (true, SortDirection::Asc) => " ▲",
+            (true, SortDirection::Desc) => " ▼",
+        }
+    }
+
+    /// The sortable columns and their headings.
+    pub fn columns(&self) -> [(TenantSort, &'static str); 2] {
+        [
+            (TenantSort::TenantName, "Tenant name"),
+            (TenantSort::DisplayName, "Display name"),
+        ]
+    }
+}
+
+pub(super) async fn list(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Query(params): Query<ListParams>,
+) -> Result<Response, TenancyError> {
+    let sort = params.sort.unwrap_or(TenantSort::TenantName);
+    let dir = params.dir.unwrap_or(SortDirection::Asc);
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let tenants = tenant::load_all_sorted(&mut tx, sort, dir).await?;
+    let body = ui.templates.tenant_list(&TenantListView {
+        base: base.0.clone(),
+        tenants,
+        sort,
+        dir,
+    })?;
+    ui.page(htmx, &base, "Tenants", body)
+}
+
+/// What the create and edit forms post.
+#[derive(Debug, Deserialize)]
+pub(super) struct TenantForm {
+    tenant_name: String,
+    display_name: String,
+}
+
+impl TenantForm {
+    /// Both trimmed, or the errors to show.
+    fn normalize(&self) -> Result<(String, String), TenancyError> {
+        let mut errors = ValidationErrors::new();
+        let tenant_name = self.tenant_name.trim();
+        if tenant_name.is_empty() {
+            errors.add("tenant_name", "is required");
+        }
+        let display_name = match normalize_display_name(&self.display_name) {
+            Ok(display_name) => display_name,
+            Err(message) => {
+                errors.add("display_name", message);
+                String::new()
+            }
+        };
+        if errors.is_empty() {
+            Ok((tenant_name.to_string(), display_name))
+        } else {
+            Err(errors.into())
+        }
+    }
+}
+
+/// The create and edit forms.
+#[derive(Template)]
+#[template(path = "admin/tenant_form.html")]
+pub struct TenantFormView {
+    pub base: String,
+    /// `None` on the create form.
+    pub tenant_id: Option<Uuid>,
+    /// As loaded or as typed when redisplaying with errors.
+    pub tenant_name: String,
+    pub display_name: String,
+    /// By field name.
+    pub errors: ValidationErrors,
+}
+
+impl TenantFormView {
+    fn from_form(base: &Base, tenant_id: Option<Uuid>, form: TenantForm) -> TenantFormView {
+        TenantFormView {
+            base: base.0.clone(),
+            tenant_id,
+            tenant_name: form.tenant_name,
+            display_name: form.display_name,
+            errors: ValidationErrors::new(),
+        }
+    }
+
+    /// Where the form posts to.
+    pub fn action(&self) -> String {
+        match self.tenant_id {
+            Some(id) => format!("{}/tenants/{}", self.base, id),
+            None => format!("{}/tenants", self.base),
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self.tenant_id {
+            Some(_) => "Edit tenant",
+            None => "New tenant",
+        }
+    }
+}
+
+/// The form again with its errors, a 200 for htmx and 422 for browsers as
+/// for the user form.
+fn form_again(
+    ui: &AdminUi,
+    htmx: Htmx,
+    base: &Base,
+    mut view: TenantFormView,
+    e: TenancyError,
+) -> Result<Response, TenancyError> {
+    view.errors = match e {
+        TenancyError::InvalidFields(e) => e,
+        TenancyError::DuplicateTenantName => {
+            let mut errors = ValidationErrors::new();
+            errors.add("tenant_name", "is already in use");
+            errors
+        }
+        TenancyError::DuplicateTenantDisplayName => {
+            let mut errors = ValidationErrors::new();
+            errors.add("display_name", "is already in use");
+            errors
+        }
+        e => return Err(e),
+    };
+    let body = ui.templates.tenant_form(&view)?;
+    let response = ui.page(htmx, base, view.title(), body)?;
+    if htmx.request {
+        Ok(response)
+    } else {
+        Ok((StatusCode::UNPROCESSABLE_ENTITY, response).into_response())
+    }
+}
+
+pub(super) async fn new(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+) -> Result<Response, TenancyError> {
+    let body = ui.templates.tenant_form(&TenantFormView {
+        base: base.0.clone(),
+        tenant_id: None,
+        tenant_name: String::new(),
+        display_name: String::new(),
+        errors: ValidationErrors::new(),
+    })?;
+    ui.page(htmx, &base, "New tenant", body)
+}
+
+/// Goes through [`Tenancy::insert_tenant`](crate::Tenancy::insert_tenant),
+/// so the tenant gets its own schema or file when isolated.
+pub(super) async fn create(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Form(form): Form<TenantForm>,
+) -> Result<Response, TenancyError> {
+    let result = match form.normalize() {
+        Ok((tenant_name, display_name)) => {
+            let tx = ui.tenancy.pool().begin().await?;
+            ui.tenancy
+                .insert_tenant(tx, &tenant_name, &display_name)
+                .await
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(_) => Ok(see_other(htmx, &format!("{}/tenants", base.0))),
+        Err(e) => form_again(
+            &ui,
+            htmx,
+            &base,
+            TenantFormView::from_form(&base, None, form),
+            e,
+        ),
+    }
+}
+
+pub(super) async fn edit(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Path(tenant_id): Path<Uuid>,
+) -> Result<Response, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let t = tenant::load_by_id(&mut tx, tenant_id).await?;
+    let body = ui.templates.tenant_form(&TenantFormView {
+        base: base.0.clone(),
+        tenant_id: Some(tenant_id),
+        tenant_name: t.tenant_name,
+        display_name: t.display_name,
+        errors: ValidationErrors::new(),
+    })?;
+    ui.page(htmx, &base, "Edit tenant", body)
+}
+
+pub(super) async fn update(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Path(tenant_id): Path<Uuid>,
+    Form(form): Form<TenantForm>,
+) -> Result<Response, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    tenant::load_by_id(&mut tx, tenant_id).await?;
+    let result = match form.normalize() {
+        Ok((tenant_name, display_name)) => {
+            tenant::update(&mut tx, &tenant_id, &tenant_name, &display_name).await
+        }
+        Err(e) => Err(e),
+    };
+    match result {
+        Ok(_) => {
+            tx.commit().await?;
+            Ok(see_other(htmx, &format!("{}/tenants", base.0)))
+        }
+        Err(e) => form_again(
+            &ui,
+            htmx,
+            &base,
+            TenantFormView::from_form(&base, Some(tenant_id), form),
+            e,
+        ),
+    }
+}
+
+/// Asks before deleting a tenant.
+#[derive(Template)]
+#[template(path = "admin/tenant_delete.html")]
+pub struct TenantDeleteView {
+    pub base: String,
+    pub tenant: Tenant,
+}
+
+pub(super) async fn confirm_delete(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Path(tenant_id): Path<Uuid>,
+) -> Result<Response, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let t = tenant::load_by_id(&mut tx, tenant_id).await?;
+    let body = ui.templates.tenant_delete(&TenantDeleteView {
+        base: base.0.clone(),
+        tenant: t,
+    })?;
+    ui.page(htmx, &base, "Delete tenant", body)
+}
+
+/// Also removes the tenant's schema or file, see
+/// [`Tenancy::delete_tenant`](crate::Tenancy::delete_tenant).
+pub(super) async fn delete(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Path(tenant_id): Path<Uuid>,
+) -> Result<Response, TenancyError> {
+    let tx = ui.tenancy.pool().begin().await?;
+    if ui.tenancy.delete_tenant(tx, &tenant_id).await? == 0 {
+        return Err(TenancyError::NotFound);
+    }
+    Ok(see_other(htmx, &format!("{}/tenants", base.0)))
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request},
+        Extension, Router,
+    };
+    use axum_tenancy_core::admin_core::user_core::User;
+    use test_context::test_context;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{admin::test_support::*, initialize, Tenancy, TenancyConfig};
+
+    async fn tenancy() -> Tenancy {
+        let config = TenancyConfig::default().run_migrations(false);
+        initialize(get_committed_test_db_pool().await, config)
+            .await
+            .unwrap()
+    }
+
+    fn app(tenancy: &Tenancy) -> Router {
+        let admin = User {
+            user_name: "admin".to_string(),
+            is_admin: true,
+            ..Default::default()
+        };
+        Router::new()
+            .nest("/admin", tenancy.admin_router())
+            .layer(Extension(admin))
+    }
+
+    async fn send(tenancy: &Tenancy, request: Request<Body>) -> (StatusCode, String) {
+        let response = app(tenancy).oneshot(request).await.unwrap();
+        let status = response.status();
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap())
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    fn post(uri: &str, form: &impl serde::Serialize) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(serde_urlencoded::to_string(form).unwrap()))
+            .unwrap()
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn create_edit_and_delete(_tenancy_context: &mut TenancyTestContext) {
+        let tenancy = tenancy().await;
+        let name = format!("t{}", Uuid::new_v4().simple());
+        let form = |tenant_name: &str, display_name: &str| {
+            vec![
+                ("tenant_name", tenant_name.to_string()),
+                ("display_name", display_name.to_string()),
+            ]
+        };
+
+        let (status, body) = send(&tenancy, post("/admin/tenants", &form(&name, " "))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.contains("<p class=\"error\">Display name is required"));
+        let (status, _) = send(&tenancy, post("/admin/tenants", &form(&name, &name))).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        let (_, body) = send(&tenancy, post("/admin/tenants", &form(&name, "other"))).await;
+        assert!(body.contains("Tenant name is already in use"));
+
+        let (status, body) = send(&tenancy, get("/admin/tenants?sort=display_name")).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("<div id=\"tenant-list\">"));
+        assert!(body.contains(&name));
+        assert!(body.contains("sort=display_name&amp;dir=desc"));
+
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let tenant_id = tenant::load_by_name(&mut tx, &name)
+            .await
+            .unwrap()
+            .tenant_id;
+        drop(tx);
+        let uri = format!("/admin/tenants/{}", tenant_id);
+        let (_, body) = send(&tenancy, get(&uri)).await;
+        assert!(body.contains(&format!("value=\"{}\"", name)));
+        let edited = format!("{} edited", name);
+        let (status, _) = send(&tenancy, post(&uri, &form(&name, &edited))).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let t = tenant::load_by_id(&mut tx, tenant_id).await.unwrap();
+        assert_eq!(t.display_name, edited);
+        drop(tx);
+
+        let delete = format!("{}/delete", uri);
+        let (_, body) = send(&tenancy, get(&delete)).await;
+        assert!(body.contains(&edited));
+        let (status, _) = send(&tenancy, post(&delete, &())).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        let (status, _) = send(&tenancy, get(&uri)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+}
@@ -0,0 +1,732 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! The user list, create and edit forms and delete confirmation.
+
+use askama::Template;
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
+    Form,
+};
+use axum_tenancy_core::{
+    admin_core::{
+        page_core::Page,
+        user_core::{Deleted, SortDirection, User, UserQuery, UserSort},
+        validation_core::{UserFields, ValidationErrors},
+    },
+    error::TenancyError,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use super::{see_other, AdminUi, Base, Htmx};
+use crate::admin::user;
+
+const PAGE_SIZE: u32 = 25;
+
+pub(super) async fn home(base: Base) -> Redirect {
+    Redirect::to(&format!("{}/users", base.0))
+}
+
+#[derive(Debug, Default, Deserialize)]
+pub(super) struct ListParams {
+    sort: Option<UserSort>,
+    dir: Option<SortDirection>,
+    #[serde(default)]
+    search: String,
+    after: Option<String>,
+}
+
+/// A page of the user list.
+#[derive(Template)]
+#[template(path = "admin/user_list.html")]
+pub struct UserListView {
+    pub base: String,
+    pub page: Page<User>,
+    pub sort: UserSort,
+    pub dir: SortDirection,
+    /// Empty when not searching.
+    pub search: String,
+    /// The cursor this page started from, `None` on the first page.
+    pub after: Option<String>,
+}
+
+impl UserListView {
+    fn href(&self, sort: UserSort, dir: SortDirection, after: Option<&str>) -> String {
+        let mut params = vec![
+            ("sort", sort.as_str()),
+            (
+                "dir",
+                if dir == SortDirection::Asc {
+                    "asc"
+                } else {
+                    "desc"
+                },
+            ),
+        ];
+        if !self.search.is_empty() {
+            params.push(("search", &self.search));
+        }
+        if let Some(after) = after {
+            params.push(("after", after));
+        }
+        format!(
+            "{}/users?{}",
+            self.base,
+            serde_urlencoded::to_string(params).unwrap_or_default()
+        )
+    }
+
+    /// Clicking the current sort column reverses it.
+    pub fn sort_href(&self, sort: &UserSort) -> String {
+        let dir = if *sort == self.sort {
+            self.dir.reverse()
+        } else {
+            SortDirection::Asc
+        };
+        self.href(*sort, dir, None)
+    }
+
+    pub fn arrow(&self, sort: &UserSort) -> &'static str {
+        match (*sort == self.sort, self.dir) {
+            (false, _) => "",
+            (true, SortDirection::Asc) => " ▲",
+            (true, SortDirection::Desc) => " ▼",
+        }
+    }
+
+    pub fn first_href(&self) -> String {
+        self.href(self.sort, self.dir, None)
+    }
+
+    pub fn next_href(&self) -> Option<String> {
+        self.page
+            .next_cursor
+            .as_deref()
+            .map(|c| self.href(self.sort, self.dir, Some(c)))
+    }
+
+    /// The sortable columns and their headings.
+    pub fn columns(&self) -> [(UserSort, &'static str); 5] {
+        [
+            (UserSort::UserName, "User name"),
+            (UserSort::DisplayName, "Display name"),
+            (UserSort::Email, "Email"),
+            (UserSort::CreatedAt, "Created"),
+            (UserSort::LastLoginAt, "Last login"),
+        ]
+    }
+}
+
+pub(super) async fn list(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Query(params): Query<ListParams>,
+) -> Result<Response, TenancyError> {
+    let sort = params.sort.unwrap_or(UserSort::UserName);
+    let dir = params.dir.unwrap_or(SortDirection::Asc);
+    let search = params.search.trim().to_string();
+    let mut q = UserQuery::new().sort(sort, dir).limit(PAGE_SIZE);
+    if !search.is_empty() {
+        q = q.search(&search);
+    }
+    if let Some(after) = &params.after {
+        q = q.after(after);
+    }
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let users = user::load_page(&mut tx, &q).await?;
+    let body = ui.templates.user_list(&UserListView {
+        base: base.0.clone(),
+        page: users,
+        sort,
+        dir,
+        search,
+        after: params.after,
+    })?;
+    ui.page(htmx, &base, "Users", body)
+}
+
+/// What the create and edit forms post. Checkboxes are only sent when ticked.
+#[derive(Debug, Deserialize)]
+pub(super) struct UserForm {
+    user_name: String,
+    display_name: String,
+    #[serde(default)]
+    email: String,
+    #[serde(default)]
+    mobile_phone: String,
+    is_admin: Option<String>,
+    /// Only on the create form.
+    password: Option<String>,
+    /// Only on the edit form, the version the user was loaded at.
+    version: Option<i64>,
+}
+
+impl UserForm {
+    fn fields(&self) -> UserFields {
+        UserFields::new(
+            &self.user_name,
+            &self.display_name,
+            &self.email,
+            &self.mobile_phone,
+        )
+    }
+}
+
+/// The create and edit forms.
+#[derive(Template)]
+#[template(path = "admin/user_form.html")]
+pub struct UserFormView {
+    pub base: String,
+    /// `None` on the create form.
+    pub user_id: Option<Uuid>,
+    /// Sent back in a hidden field by the edit form.
+    pub version: i64,
+    /// As loaded or as typed when redisplaying with errors.
+    pub fields: UserFields,
+    pub is_admin: bool,
+    /// By field name, errors not about one field are under `"form"`.
+    pub errors: ValidationErrors,
+}
+
+impl UserFormView {
+    /// Where the form posts to.
+    pub fn action(&self) -> String {
+        match self.user_id {
+            Some(id) => format!("{}/users/{}", self.base, id),
+            None => format!("{}/users", self.base),
+        }
+    }
+
+    pub fn title(&self) -> &'static str {
+        match self.user_id {
+            Some(_) => "Edit user",
+            None => "New user",
+        }
+    }
+}
+
+/// The form again with its errors. htmx only swaps successful responses, so
+/// it gets a 200, browsers get a 422.
+fn form_again(
+    ui: &AdminUi,
+    htmx: Htmx,
+    base: &Base,
+    view: UserFormView,
+) -> Result<Response, TenancyError> {
+    let body = ui.templates.user_form(&view)?;
+    let response = ui.page(htmx, base, view.title(), body)?;
+    if htmx.request {
+        Ok(response)
+    } else {
+        Ok((StatusCode::UNPROCESSABLE_ENTITY, response).into_response())
+    }
+}
+
+/// The errors to show on the form, anything that isn't about the input is
+/// passed on.
+pub(super) fn field_errors(e: TenancyError) -> Result<ValidationErrors, TenancyError> {
+    let mut errors = ValidationErrors::new();
+    match e {
+        TenancyError::InvalidFields(e) => return Ok(e),
+        TenancyError::DuplicateUserName => errors.add("user_name", "is already in use"),
+        TenancyError::DuplicateDisplayName => errors.add("display_name", "is already in use"),
+        TenancyError::DuplicateEmail => errors.add("email", "is already in use"),
+        TenancyError::Conflict => errors.add("form", e.to_string()),
+        e => return Err(e),
+    }
+    Ok(errors)
+}
+
+pub(super) async fn new(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+) -> Result<Response, TenancyError> {
+    let body = ui.templates.user_form(&UserFormView {
+        base: base.0.clone(),
+        user_id: None,
+        version: 0,
+        fields: UserFields::default(),
+        is_admin: false,
+        errors: ValidationErrors::new(),
+    })?;
+    ui.page(htmx, &base, "New user", body)
+}
+
+pub(super) async fn create(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Form(form): Form<UserForm>,
+) -> Result<Response, TenancyError> {
+    let config = ui.tenancy.config();
+    let password = form.password.as_deref().unwrap_or_default();
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let result = user::insert(
+        &mut tx,
+        &form.user_name,
+        password,
+        &form.display_name,
+        form.is_admin.is_some(),
+        &form.email,
+        &form.mobile_phone,
+        &config.user,
+        &config.password,
+    )
+    .await;
+    match result {
+        Ok(_) => {
+            tx.commit().await?;
+            Ok(see_other(htmx, &format!("{}/users", base.0)))
+        }
+        Err(e) => form_again(
+            &ui,
+            htmx,
+            &base,
+            UserFormView {
+                base: base.0.clone(),
+                user_id: None,
+                version: 0,
+                fields: form.fields(),
+                is_admin: form.is_admin.is_some(),
+                errors: field_errors(e)?,
+            },
+        ),
+    }
+}
+
+pub(super) async fn edit(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Path(user_id): Path<Uuid>,
+) -> Result<Response, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude).await?;
+    let body = ui.templates.user_form(&UserFormView {
+        base: base.0.clone(),
+        user_id: Some(user_id),
+        version: u.version,
+        fields: UserFields::new(&u.user_name, &u.display_name, &u.email, &u.mobile_phone),
+        is_admin: u.is_admin,
+        errors: ValidationErrors::new(),
+    })?;
+    ui.page(htmx, &base, "Edit user", body)
+}
+
+pub(super) async fn update(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Path(user_id): Path<Uuid>,
+    Form(form): Form<UserForm>,
+) -> Result<Response, TenancyError> {
+    let version = form.version.ok_or_else(|| {
+        TenancyError::Validation("the form is missing the user's version".to_string())
+    })?;
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let result = user::update(
+        &mut tx,
+        &user_id,
+        version,
+        &form.user_name,
+        &form.display_name,
+        form.is_admin.is_some(),
+        &form.email,
+        &form.mobile_phone,
+        &ui.tenancy.config().user,
+    )
+    .await;
+    match result {
+        Ok(_) => {
+            tx.commit().await?;
+            Ok(see_other(htmx, &format!("{}/users", base.0)))
+        }
+        Err(e) => form_again(
+            &ui,
+            htmx,
+            &base,
+            UserFormView {
+                base: base.0.clone(),
+                user_id: Some(user_id),
+                version,
+                fields: form.fields(),
+                is_admin: form.is_admin.is_some(),
+                errors: field_errors(e)?,
+            },
+        ),
+    }
+}
+
+/// Asks before deleting a user.
+#[derive(Template)]
+#[template(path = "admin/user_delete.html")]
+pub struct UserDeleteView {
+    pub base: String,
+    pub user: User,
+}
+
+pub(super) async fn confirm_delete(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Path(user_id): Path<Uuid>,
+) -> Result<Response, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude).await?;
+    let body = ui.templates.user_delete(&UserDeleteView {
+        base: base.0.clone(),
+        user: u,
+    })?;
+    ui.page(htmx, &base, "Delete user", body)
+}
+
+pub(super) async fn delete(
+    State(ui): State<AdminUi>,
+    base: Base,
+    htmx: Htmx,
+    Path(user_id): Path<Uuid>,
+) -> Result<Response, TenancyError> {
+    let mut tx = ui.tenancy.pool().begin().await?;
+    if user::delete(&mut tx, &user_id).await? == 0 {
+        return Err(TenancyError::NotFound);
+    }
+    tx.commit().await?;
+    Ok(see_other(htmx, &format!("{}/users", base.0)))
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use axum::{
+        body::{to_bytes, Body},
+        http::{header, Request},
+        Extension, Router,
+    };
+    use axum_tenancy_core::admin_core::password_core::PasswordPolicy;
+    use test_context::test_context;
+    use tower::ServiceExt;
+
+    use super::*;
+    use crate::{
+        admin::test_support::*,
+        admin_ui::{LayoutView, TemplateProvider},
+        initialize, Tenancy, TenancyConfig,
+    };
+
+    async fn tenancy() -> Tenancy {
+        let config = TenancyConfig::default()
+            .run_migrations(false)
+            .user(TEST_USER_RULES)
+            .password(PasswordPolicy {
+                min_length: 8,
+                hash: TEST_PASSWORD_PARAMS,
+            });
+        initialize(get_committed_test_db_pool().await, config)
+            .await
+            .unwrap()
+    }
+
+    fn app(tenancy: &Tenancy, user: Option<User>) -> Router {
+        let router = Router::new().nest("/admin", tenancy.admin_router());
+        match user {
+            Some(u) => router.layer(Extension(u)),
+            None => router,
+        }
+    }
+
+    fn admin() -> User {
+        User {
+            user_name: "admin".to_string(),
+            is_admin: true,
+            ..Default::default()
+        }
+    }
+
+    async fn send(router: Router, request: Request<Body>) -> (StatusCode, String, Option<String>) {
+        let response = router.oneshot(request).await.unwrap();
+        let status = response.status();
+        let location = response
+            .headers()
+            .get(header::LOCATION)
+            .or_else(|| response.headers().get("hx-redirect"))
+            .map(|v| v.to_str().unwrap().to_string());
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        (status, String::from_utf8(body.to_vec()).unwrap(), location)
+    }
+
+    fn get(uri: &str) -> Request<Body> {
+        Request::builder().uri(uri).body(Body::empty()).unwrap()
+    }
+
+    fn post(uri: &str, form: &impl serde::Serialize) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .body(Body::from(serde_urlencoded::to_string(form).unwrap()))
+            .unwrap()
+    }
+
+    fn htmx(mut request: Request<Body>) -> Request<Body> {
+        request
+            .headers_mut()
+            .insert("hx-request", "true".parse().unwrap());
+        request
+    }
+
+    async fn add_user(tenancy: &Tenancy, user_name: &str) -> Uuid {
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let id = user::insert(
+            &mut tx,
+            user_name,
+            "password",
+            user_name,
+            false,
+            "",
+            "",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+        id
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn pages_need_an_admin(_tenancy_context: &mut TenancyTestContext) {
+        let tenancy = tenancy().await;
+        let (status, _, _) = send(app(&tenancy, None), get("/admin/users")).await;
+        assert_eq!(status, StatusCode::UNAUTHORIZED);
+        let not_admin = User {
+            is_admin: false,
+            ..admin()
+        };
+        let (status, _, _) = send(app(&tenancy, Some(not_admin)), get("/admin/users")).await;
+        assert_eq!(status, StatusCode::FORBIDDEN);
+        let (status, _, location) = send(app(&tenancy, Some(admin())), get("/admin")).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        assert_eq!(location.as_deref(), Some("/admin/users"));
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn list_is_a_page_or_a_fragment(_tenancy_context: &mut TenancyTestContext) {
+        let tenancy = tenancy().await;
+        let name = format!("list-{}", Uuid::new_v4());
+        add_user(&tenancy, &name).await;
+        let uri = format!("/admin/users?search={}&sort=created_at&dir=desc", name);
+
+        let (status, body, _) = send(app(&tenancy, Some(admin())), get(&uri)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("<html"));
+        assert!(body.contains(&name));
+        assert!(body.contains("1 users"));
+
+        let (status, body, _) = send(app(&tenancy, Some(admin())), htmx(get(&uri))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(!body.contains("<html"));
+        assert!(body.starts_with("<div id=\"user-list\">"));
+        assert!(body.contains(&name));
+        // the current column sorts the other way next time
+        assert!(body.contains("sort=created_at&amp;dir=asc"));
+
+        let mut boosted = htmx(get(&uri));
+        boosted
+            .headers_mut()
+            .insert("hx-boosted", "true".parse().unwrap());
+        let (_, body, _) = send(app(&tenancy, Some(admin())), boosted).await;
+        assert!(body.contains("<html"));
+
+        let (status, _, _) = send(
+            app(&tenancy, Some(admin())),
+            get("/admin/users?sort=hash_password"),
+        )
+        .await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn create_shows_errors_on_the_form(_tenancy_context: &mut TenancyTestContext) {
+        let tenancy = tenancy().await;
+        let name = format!("create-{}", Uuid::new_v4());
+        let form = |email, password| {
+            vec![
+                ("user_name", name.as_str()),
+                ("display_name", name.as_str()),
+                ("email", email),
+                ("mobile_phone", ""),
+                ("password", password),
+            ]
+        };
+
+        let (status, body, _) = send(
+            app(&tenancy, Some(admin())),
+            post("/admin/users", &form("not an email", "short")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.contains("<html"));
+        assert!(body.contains("<p class=\"error\">Email"));
+        assert!(body.contains("<p class=\"error\">Password must be at least 8 characters"));
+        assert!(body.contains(&name));
+
+        let (status, body, _) = send(
+            app(&tenancy, Some(admin())),
+            htmx(post("/admin/users", &form("not an email", "password"))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.starts_with("<form"));
+        assert!(body.contains("<p class=\"error\">Email"));
+
+        let (status, _, location) = send(
+            app(&tenancy, Some(admin())),
+            post("/admin/users", &form("a@example.com", "password")),
+        )
+        .await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        assert_eq!(location.as_deref(), Some("/admin/users"));
+
+        let (status, body, location) = send(
+            app(&tenancy, Some(admin())),
+            htmx(post("/admin/users", &form("a@example.com", "password"))),
+        )
+        .await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("User name is already in use"));
+        assert_eq!(location, None);
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn edit_and_delete(_tenancy_context: &mut TenancyTestContext) {
+        let tenancy = tenancy().await;
+        let name = format!("edit-{}", Uuid::new_v4());
+        let id = add_user(&tenancy, &name).await;
+        let uri = format!("/admin/users/{}", id);
+
+        let (status, body, _) = send(app(&tenancy, Some(admin())), get(&uri)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains("name=\"version\" value=\"1\""));
+
+        let form = |version: &str| {
+            vec![
+                ("user_name", name.clone()),
+                ("display_name", format!("{} edited", name)),
+                ("email", String::new()),
+                ("mobile_phone", "01234567891".to_string()),
+                ("is_admin", "on".to_string()),
+                ("version", version.to_string()),
+            ]
+        };
+
+        let (status, _, location) =
+            send(app(&tenancy, Some(admin())), post(&uri, &form("1"))).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        assert_eq!(location.as_deref(), Some("/admin/users"));
+
+        // saved from the version before the edit above
+        let (status, body, _) = send(app(&tenancy, Some(admin())), post(&uri, &form("1"))).await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert!(body.contains("changed by someone else"));
+
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let u = user::load_by_id(&mut tx, id, Deleted::Exclude)
+            .await
+            .unwrap();
+        assert_eq!(u.display_name, format!("{} edited", name));
+        assert_eq!(u.mobile_phone, "+441234567891");
+        assert!(u.is_admin);
+        drop(tx);
+
+        let delete = format!("{}/delete", uri);
+        let (status, body, _) = send(app(&tenancy, Some(admin())), get(&delete)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.contains(&name));
+        let (status, _, location) = send(app(&tenancy, Some(admin())), post(&delete, &())).await;
+        assert_eq!(status, StatusCode::SEE_OTHER);
+        assert_eq!(location.as_deref(), Some("/admin/users"));
+        let (status, _, _) = send(app(&tenancy, Some(admin())), get(&uri)).await;
+        assert_eq!(status, StatusCode::NOT_FOUND);
+    }
+
+    struct Plain;
+
+    impl TemplateProvider for Plain {
+        fn layout(&self, view: &LayoutView) -> Result<String, TenancyError> {
+            Ok(format!("<app>{}</app>", view.body))
+        }
+
+        fn user_delete(&self, view: &UserDeleteView) -> Result<String, TenancyError> {
+            Ok(format!("delete {}?", view.user.user_name))
+        }
+    }
+
+    fn admin_app(router: Router) -> Router {
+        Router::new()
+            .nest("/admin", router)
+            .layer(Extension(admin()))
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn templates_can_be_replaced(_tenancy_context: &mut TenancyTestContext) {
+        let tenancy = tenancy().await;
+        let name = format!("plain-{}", Uuid::new_v4());
+        let id = add_user(&tenancy, &name).await;
+        let delete = format!("/admin/users/{}/delete", id);
+
+        let app = admin_app(tenancy.admin_ui().templates(Plain).router());
+        let (status, body, _) = send(app.clone(), get(&delete)).await;
+        assert_eq!(status, StatusCode::OK);
+        assert_eq!(body, format!("<app>delete {}?</app>", name));
+        // the rest are still the built-in templates
+        let (_, body, _) = send(app, get("/admin/users")).await;
+        assert!(body.starts_with("<app><div id=\"user-list\">"));
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn fragments_only_never_has_the_layout(_tenancy_context: &mut TenancyTestContext) {
+        let tenancy = tenancy().await;
+        let name = format!("fragment-{}", Uuid::new_v4());
+        add_user(&tenancy, &name).await;
+
+        let app = admin_app(tenancy.admin_ui().fragments_only(true).router());
+        let (status, body, _) =
+            send(app.clone(), get(&format!("/admin/users?search={}", name))).await;
+        assert_eq!(status, StatusCode::OK);
+        assert!(body.starts_with("<div id=\"user-list\">"));
+        assert!(body.contains(&name));
+        let (_, body, _) = send(app, get("/admin/users/new")).await;
+        assert!(body.starts_with("<form"));
+    }
+}
@@ -0,0 +1,389 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! [axum-login](https://crates.io/crates/axum-login) backends over the user
+//! table.
+//!
+//! Put an `AuthManagerLayer` built from [`TenancyBackend`] in front of the
+//! app, then add [`user_to_extensions`] with `axum::middleware::from_fn` so
+//! the logged in [`User`] is where [`TenancyLayer`](crate::TenancyLayer) and
+//! the [extractors](crate::extract) look for it.
+
+use std::collections::HashSet;
+
+use async_trait::async_trait;
+use axum::{extract::Request, middleware::Next, response::Response};
+use axum_login::{AuthnBackend, AuthzBackend, UserId};
+use axum_tenancy_core::{
+    admin_core::{
+        password_core::{PasswordHashParams, PasswordVerification},
+        role_core::RolePermissions,
+        user_core::{Deleted, User},
+    },
+    error::TenancyError,
+};
+use serde::Deserialize;
+use uuid::Uuid;
+
+use crate::admin::{user, user_tenant, DbPool};
+
+pub type AuthSession = axum_login::AuthSession<TenancyBackend>;
+
+/// What a login form posts.
+#[derive(Clone, Deserialize)]
+pub struct Credentials {
+    pub user_name: String,
+    pub password: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Permission {
+    /// The user has `is_admin` set.
+    Admin,
+    /// The user has a UserTenant for the tenant.
+    Member(Uuid),
+    /// A grant of the user's role in the tenant, like `invoices:*`. Asking
+    /// [`has_perm`](AuthzBackend::has_perm) for `invoices:write` matches the
+    /// grants the way [`require_permission`](crate::permission::require_permission)
+    /// does.
+    Tenant { tenant_id: Uuid, permission: String },
+}
+
+impl Permission {
+    pub fn tenant(tenant_id: Uuid, permission: &str) -> Permission {
+        Permission::Tenant {
+            tenant_id,
+            permission: permission.to_string(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TenancyBackend {
+    pool: DbPool,
+    params: PasswordHashParams,
+    roles: RolePermissions,
+}
+
+impl TenancyBackend {
+    pub fn new(pool: DbPool) -> TenancyBackend {
+        TenancyBackend {
+            pool,
+            params: PasswordHashParams::default(),
+            roles: RolePermissions::default(),
+        }
+    }
+
+    /// The argon2 parameters passwords were hashed with.
+    pub fn password_params(mut self, params: PasswordHashParams) -> TenancyBackend {
+        self.params = params;
+        self
+    }
+
+    /// The permissions of each role, the built-in roles by default. Give the
+    /// [`TenancyLayer`](crate::TenancyLayer) the same so `has_perm` and
+    /// `require_permission` agree.
+    pub fn roles(mut self, roles: RolePermissions) -> TenancyBackend {
+        self.roles = roles;
+        self
+    }
+}
+
+#[async_trait]
+impl AuthnBackend for TenancyBackend {
+    type User = User;
+    type Credentials = Credentials;
+    type Error = TenancyError;
+
+    async fn authenticate(&self, creds: Credentials) -> Result<Option<User>, TenancyError> {
+        let mut tx = self.pool.begin().await?;
+        match user::verify_password(&mut tx, &creds.user_name, &creds.password, &self.params)
+            .await?
+        {
+            PasswordVerification::Verified(user_id) => {
+                user::record_login(&mut tx, &user_id).await?;
+                let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude).await?;
+                tx.commit().await?;
+                Ok(Some(u))
+            }
+            PasswordVerification::Rejected => Ok(None),
+        }
+    }
+
+    async fn get_user(&self, user_id: &UserId<Self>) -> Result<Option<User>, TenancyError> {
+        let mut tx = self.pool.begin().await?;
+        match user::load_by_id(&mut tx, *user_id, Deleted::Exclude).await {
+            Ok(u) => Ok(Some(u)),
+            Err(TenancyError::NotFound) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+}
+
+#[async_trait]
+impl AuthzBackend for TenancyBackend {
+    type Permission = Permission;
+
+    async fn get_user_permissions(&self, u: &User) -> Result<HashSet<Permission>, TenancyError> {
+        let mut perms = HashSet::new();
+        if u.is_admin {
+            perms.insert(Permission::Admin);
+        }
+        Ok(perms)
+    }
+
+    /// Each tenant the user belongs to acts as a group, with the grants of
+    /// the user's role in it.
+    async fn get_group_permissions(&self, u: &User) -> Result<HashSet<Permission>, TenancyError> {
+        let mut tx = self.pool.begin().await?;
+        let memberships = user_tenant::load_by_user(&mut tx, &u.user_id).await?;
+        let mut perms = HashSet::new();
+        for ut in memberships {
+            perms.insert(Permission::Member(ut.tenant_id));
+            perms.extend(
+                self.roles
+                    .grants(&ut.role)
+                    .iter()
+                    .map(|grant| Permission::tenant(ut.tenant_id, grant)),
+            );
+        }
+        Ok(perms)
+    }
+
+    /// Tenant permissions are checked against the role's grant patterns,
+    /// everything else must be in the set.
+    async fn has_perm(&self, u: &User, perm: Permission) -> Result<bool, TenancyError> {
+        match &perm {
+            Permission::Tenant {
+                tenant_id,
+                permission,
+            } => {
+                let mut tx = self.pool.begin().await?;
+                match user_tenant::load_by_user_and_tenant(&mut tx, &u.user_id, tenant_id).await {
+                    Ok(ut) => Ok(self.roles.allows(&ut.role, permission)),
+                    Err(TenancyError::NotFound) => Ok(false),
+                    Err(e) => Err(e),
+                }
+            }
+            _ => Ok(self.get_all_permissions(u).await?.contains(&perm)),
+        }
+    }
+}
+
+/// Middleware copying the logged in user from the [`AuthSession`] into the
+/// request extensions.
+pub async fn user_to_extensions(
+    auth_session: AuthSession,
+    mut req: Request,
+    next: Next,
+) -> Response {
+    if let Some(u) = auth_session.user {
+        req.extensions_mut().insert(u);
+    }
+    next.run(req).await
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use axum_tenancy_core::admin_core::role_core::Role;
+    use test_context::test_context;
+
+    use super::*;
+    use crate::admin::{tenant, test_support::*};
+
+    /// Commits a user, the backend uses its own connections. Returns the
+    /// user name, id and the backend.
+    async fn setup_user(is_admin: bool) -> (String, Uuid, TenancyBackend) {
+        let pool = get_committed_test_db_pool().await;
+        let user_name = format!("auth{}", Uuid::new_v4().simple());
+        let mut tx = pool.begin().await.unwrap();
+        let user_id = user::insert(
+            &mut tx,
+            &user_name,
+            "password",
+            &user_name,
+            is_admin,
+            "dwarnock@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        tx.commit().await.unwrap();
+        let backend = TenancyBackend::new(pool.clone()).password_params(TEST_PASSWORD_PARAMS);
+        (user_name, user_id, backend)
+    }
+
+    fn creds(user_name: &str, password: &str) -> Credentials {
+        Credentials {
+            user_name: user_name.to_string(),
+            password: password.to_string(),
+        }
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn authenticate_checks_password(_tenancy_context: &mut TenancyTestContext) {
+        let (user_name, user_id, backend) = setup_user(false).await;
+
+        let u = backend
+            .authenticate(creds(&user_name, "password"))
+            .await
+            .unwrap();
+        assert_eq!(u.as_ref().map(|u| u.user_id), Some(user_id));
+        assert!(u.unwrap().last_login_at.is_some());
+        let u = backend
+            .authenticate(creds(&user_name, "wrong"))
+            .await
+            .unwrap();
+        assert!(u.is_none());
+        let u = backend
+            .authenticate(creds("nobody", "password"))
+            .await
+            .unwrap();
+        assert!(u.is_none());
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn get_user_and_session_hash(_tenancy_context: &mut TenancyTestContext) {
+        let (user_name, user_id, backend) = setup_user(false).await;
+
+        let u = backend.get_user(&user_id).await.unwrap().unwrap();
+        assert_eq!(u.user_name, user_name);
+        assert!(u.hash_password.starts_with("$argon2id$"));
+        assert!(!format!("{:?}", u).contains("argon2"));
+        assert!(backend.get_user(&Uuid::new_v4()).await.unwrap().is_none());
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn deactivated_user_is_logged_out(_tenancy_context: &mut TenancyTestContext) {
+        let (user_name, user_id, backend) = setup_user(false).await;
+        let pool = get_committed_test_db_pool().await;
+        let mut tx = pool.begin().await.unwrap();
+        assert_eq!(user::deactivate(&mut tx, &user_id).await.unwrap(), 1);
+        tx.commit().await.unwrap();
+
+        assert!(backend.get_user(&user_id).await.unwrap().is_none());
+        let u = backend
+            .authenticate(creds(&user_name, "password"))
+            .await
+            .unwrap();
+        assert!(u.is_none());
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn permissions_from_admin_and_roles(_tenancy_context: &mut TenancyTestContext) {
+        let (_, user_id, backend) = setup_user(true).await;
+        let pool = get_committed_test_db_pool().await;
+        let tenant_name = format!("auth{}", Uuid::new_v4().simple());
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = tenant::insert(&mut tx, &tenant_name, &tenant_name)
+            .await
+            .unwrap();
+        user_tenant::insert(&mut tx, &user_id, &tenant_id, &Role::Member)
+            .await
+            .unwrap();
+        let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let perms = backend.get_all_permissions(&u).await.unwrap();
+        assert!(perms.contains(&Permission::Admin));
+        assert!(perms.contains(&Permission::Member(tenant_id)));
+        assert!(perms.contains(&Permission::tenant(tenant_id, "*:write")));
+        assert!(backend.has_perm(&u, Permission::Admin).await.unwrap());
+        assert!(backend
+            .has_perm(&u, Permission::Member(tenant_id))
+            .await
+            .unwrap());
+        assert!(!backend
+            .has_perm(&u, Permission::Member(Uuid::new_v4()))
+            .await
+            .unwrap());
+
+        // members read and write but don't manage members
+        for (permission, expected) in [
+            ("invoices:write", true),
+            ("invoices:read", true),
+            ("members:invite", false),
+        ] {
+            assert_eq!(
+                backend
+                    .has_perm(&u, Permission::tenant(tenant_id, permission))
+                    .await
+                    .unwrap(),
+                expected,
+                "{}",
+                permission
+            );
+        }
+        assert!(!backend
+            .has_perm(&u, Permission::tenant(Uuid::new_v4(), "invoices:read"))
+            .await
+            .unwrap());
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn permissions_from_configured_roles(_tenancy_context: &mut TenancyTestContext) {
+        let (_, user_id, backend) = setup_user(false).await;
+        let roles = RolePermissions::new().role("accountant", &["invoices:*"]);
+        let backend = backend.roles(roles.clone());
+        let pool = get_committed_test_db_pool().await;
+        let tenant_name = format!("auth{}", Uuid::new_v4().simple());
+        let accountant = Role::from("accountant");
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = tenant::insert(&mut tx, &tenant_name, &tenant_name)
+            .await
+            .unwrap();
+        user_tenant::insert(&mut tx, &user_id, &tenant_id, &accountant)
+            .await
+            .unwrap();
+        let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        for permission in ["invoices:write", "invoices:read", "orders:read"] {
+            assert_eq!(
+                backend
+                    .has_perm(&u, Permission::tenant(tenant_id, permission))
+                    .await
+                    .unwrap(),
+                roles.allows(&accountant, permission),
+                "{}",
+                permission
+            );
+        }
+        assert!(!backend
+            .has_perm(&u, Permission::tenant(tenant_id, "orders:read"))
+            .await
+            .unwrap());
+    }
+}
@@ -0,0 +1,659 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Settings for [`connect`](crate::connect) and [`initialize`](crate::initialize).
+//!
+//! Build a [`TenancyConfig`] in code, or load one with
+//! [`TenancyConfig::from_env`] or [`TenancyConfig::from_toml_file`]. Nothing
+//! here panics, problems come back as a [`ConfigError`].
+//!
+//! A TOML file looks like this, every key is optional:
+//!
+//! ```toml
+//! database_url = "sqlite:axum-tenancy.sqlite?mode=rwc"
+//! max_connections = 5
+//! run_migrations = true
+//! # "schema" for a Postgres schema per tenant, "file" for a SQLite file
+//! isolation = "shared"
+//!
+//! [password]
+//! min_length = 8
+//!
+//! [password.hash]
+//! memory_kib = 19456
+//! iterations = 2
+//! parallelism = 1
+//!
+//! [user]
+//! user_name_min_length = 3
+//! user_name_max_length = 32
+//! default_country_code = 44
+//! unique_email = false
+//!
+//! # added to the built-in owner, admin, member and viewer roles
+//! [roles]
+//! accountant = ["invoices:*", "*:read"]
+//!
+//! [session]
+//! cookie_name = "id"
+//! expiry_secs = 86400
+//! secure = true
+//!
+//! [invitation]
+//! expiry_secs = 604800
+//!
+//! # only used with isolation = "file"
+//! [tenant_files]
+//! dir = "tenants"
+//! max_open = 32
+//! idle_secs = 300
+//! ```
+
+use std::{
+    env, fs,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+
+use axum_tenancy_core::admin_core::{
+    password_core::PasswordPolicy, role_core::RolePermissions, validation_core::UserRules,
+};
+use serde::{Deserialize, Serialize};
+
+#[derive(Debug, thiserror::Error)]
+pub enum ConfigError {
+    #[error("no database url configured")]
+    MissingDatabaseUrl,
+    #[error("invalid value for {key}: {message}")]
+    Invalid { key: &'static str, message: String },
+    #[error("could not read {path}: {source}")]
+    Io {
+        path: String,
+        source: std::io::Error,
+    },
+    #[error(transparent)]
+    Toml(#[from] toml::de::Error),
+}
+
+/// Session cookie settings used by [`Tenancy::auth_layer`](crate::Tenancy::auth_layer).
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct SessionConfig {
+    pub cookie_name: String,
+    /// Seconds of inactivity before the session expires, `None` ends it with
+    /// the browser session.
+    pub expiry_secs: Option<i64>,
+    /// Only send the cookie over https.
+    pub secure: bool,
+}
+
+impl Default for SessionConfig {
+    fn default() -> SessionConfig {
+        SessionConfig {
+            cookie_name: "id".to_string(),
+            expiry_secs: None,
+            secure: true,
+        }
+    }
+}
+
+/// How each tenant's application data is kept apart.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TenantIsolation {
+    /// All tenants share the application's tables, which have a tenant id
+    /// column. On Postgres they can be protected with row level security,
+    /// see `axum_tenancy::rls`.
+    #[default]
+    Shared,
+    /// Postgres only, each tenant has its own schema, see
+    /// `axum_tenancy::schema`.
+    Schema,
+    /// SQLite only, each tenant has its own database file, see
+    /// `axum_tenancy::files`.
+    File,
+}
+
+impl FromStr for TenantIsolation {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<TenantIsolation, String> {
+        match s {
+            "shared" => Ok(TenantIsolation::Shared),
+            "schema" => Ok(TenantIsolation::Schema),
+            "file" => Ok(TenantIsolation::File),
+            s => Err(format!("{} is not shared, schema or file", s)),
+        }
+    }
+}
+
+/// Tenant invitation settings.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct InvitationConfig {
+    /// Seconds an invitation can be accepted for, a week by default.
+    pub expiry_secs: i64,
+}
+
+impl Default for InvitationConfig {
+    fn default() -> InvitationConfig {
+        InvitationConfig {
+            expiry_secs: 7 * 24 * 60 * 60,
+        }
+    }
+}
+
+impl InvitationConfig {
+    pub fn valid_for(&self) -> chrono::Duration {
+        chrono::Duration::seconds(self.expiry_secs)
+    }
+}
+
+/// Where the tenant databases go with [`TenantIsolation::File`].
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenantFilesConfig {
+    pub dir: PathBuf,
+    /// Tenant pools kept open at once.
+    pub max_open: usize,
+    /// Seconds unused before a tenant's pool is closed.
+    pub idle_secs: u64,
+}
+
+impl Default for TenantFilesConfig {
+    fn default() -> TenantFilesConfig {
+        TenantFilesConfig {
+            dir: PathBuf::from("tenants"),
+            max_open: 32,
+            idle_secs: 5 * 60,
+        }
+    }
+}
+
+impl TenantFilesConfig {
+    pub fn idle_timeout(&self) -> Duration {
+        Duration::from_secs(self.idle_secs)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct TenancyConfig {
+    /// Only needed by [`connect`](crate::connect), [`initialize`](crate::initialize)
+    /// is given a pool.
+    pub database_url: Option<String>,
+    pub max_connections: u32,
+    /// When off the application runs the migrations and then
+    /// [`recanonicalize`](crate::admin::user::recanonicalize) itself.
+    pub run_migrations: bool,
+    pub isolation: TenantIsolation,
+    pub password: PasswordPolicy,
+    pub user: UserRules,
+    /// What each per tenant role may do.
+    pub roles: RolePermissions,
+    pub session: SessionConfig,
+    pub invitation: InvitationConfig,
+    pub tenant_files: TenantFilesConfig,
+}
+
+impl Default for TenancyConfig {
+    fn default() -> TenancyConfig {
+        TenancyConfig {
+            database_url: None,
+            max_connections: 5,
+            run_migrations: true,
+            isolation: TenantIsolation::default(),
+            password: PasswordPolicy::default(),
+            user: UserRules::default(),
+            roles: RolePermissions::default(),
+            session: SessionConfig::default(),
+            invitation: InvitationConfig::default(),
+            tenant_files: TenantFilesConfig::default(),
+        }
+    }
+}
+
+impl TenancyConfig {
+    pub fn new() -> TenancyConfig {
+        TenancyConfig::default()
+    }
+
+    pub fn database_url(mut self, url: &str) -> TenancyConfig {
+        self.database_url = Some(url.to_string());
+        self
+    }
+
+    pub fn max_connections(mut self, max_connections: u32) -> TenancyConfig {
+        self.max_connections = max_connections;
+        self
+    }
+
+    pub fn run_migrations(mut self, run_migrations: bool) -> TenancyConfig {
+        self.run_migrations = run_migrations;
+        self
+    }
+
+    pub fn isolation(mut self, isolation: TenantIsolation) -> TenancyConfig {
+        self.isolation = isolation;
+        self
+    }
+
+    pub fn password(mut self, password: PasswordPolicy) -> TenancyConfig {
+        self.password = password;
+        self
+    }
+
+    pub fn user(mut self, user: UserRules) -> TenancyConfig {
+        self.user = user;
+        self
+    }
+
+    pub fn roles(mut self, roles: RolePermissions) -> TenancyConfig {
+        self.roles = roles;
+        self
+    }
+
+    pub fn session(mut self, session: SessionConfig) -> TenancyConfig {
+        self.session = session;
+        self
+    }
+
+    pub fn invitation(mut self, invitation: InvitationConfig) -> TenancyConfig {
+        self.invitation = invitation;
+        self
+    }
+
+    pub fn tenant_files(mut self, tenant_files: TenantFilesConfig) -> TenancyConfig {
+        self.tenant_files = tenant_files;
+        self
+    }
+
+    /// Read the settings from environment variables, anything unset keeps its
+    /// default. Load a `.env` file first (e.g. with dotenvy) if you use one.
+    ///
+    /// `DATABASE_URL`, `AXUM_TENANCY_MAX_CONNECTIONS`,
+    /// `AXUM_TENANCY_RUN_MIGRATIONS`, `AXUM_TENANCY_ISOLATION`,
+    /// `AXUM_TENANCY_PASSWORD_MIN_LENGTH`,
+    /// `AXUM_TENANCY_ARGON2_MEMORY_KIB`, `AXUM_TENANCY_ARGON2_ITERATIONS`,
+    /// `AXUM_TENANCY_ARGON2_PARALLELISM`, `AXUM_TENANCY_USER_NAME_MIN_LENGTH`,
+    /// `AXUM_TENANCY_USER_NAME_MAX_LENGTH`, `AXUM_TENANCY_DEFAULT_COUNTRY_CODE`,
+    /// `AXUM_TENANCY_UNIQUE_EMAIL`,
+    /// `AXUM_TENANCY_SESSION_COOKIE`,
+    /// `AXUM_TENANCY_SESSION_EXPIRY_SECS`, `AXUM_TENANCY_SESSION_SECURE`,
+    /// `AXUM_TENANCY_INVITATION_EXPIRY_SECS`, `AXUM_TENANCY_TENANT_FILES_DIR`,
+    /// `AXUM_TENANCY_TENANT_FILES_MAX_OPEN`, `AXUM_TENANCY_TENANT_FILES_IDLE_SECS`
+    pub fn from_env() -> Result<TenancyConfig, ConfigError> {
+        TenancyConfig::from_lookup(|key| env::var(key).ok())
+    }
+
+    fn from_lookup(lookup: impl Fn(&str) -> Option<String>) -> Result<TenancyConfig, ConfigError> {
+        fn parse<T: FromStr>(key: &'static str, value: String) -> Result<T, ConfigError>
+        where
+            T::Err: std::fmt::Display,
+        {
+            value
+                .trim()
+                .parse()
+                .map_err(|e: T::Err| ConfigError::Invalid {
+                    key,
+                    message: e.to_string(),
+                })
+        }
+
+        let mut c = TenancyConfig {
+            database_url: lookup("DATABASE_URL"),
+            ..Default::default()
+        };
+        if let Some(v) = lookup("AXUM_TENANCY_MAX_CONNECTIONS") {
+            c.max_connections = parse("AXUM_TENANCY_MAX_CONNECTIONS", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_RUN_MIGRATIONS") {
+            c.run_migrations = parse("AXUM_TENANCY_RUN_MIGRATIONS", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_ISOLATION") {
+            c.isolation = parse("AXUM_TENANCY_ISOLATION", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_PASSWORD_MIN_LENGTH") {
+            c.password.min_length = parse("AXUM_TENANCY_PASSWORD_MIN_LENGTH", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_ARGON2_MEMORY_KIB") {
+            c.password.hash.memory_kib = parse("AXUM_TENANCY_ARGON2_MEMORY_KIB", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_ARGON2_ITERATIONS") {
+            c.password.hash.iterations = parse("AXUM_TENANCY_ARGON2_ITERATIONS", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_ARGON2_PARALLELISM") {
+            c.password.hash.parallelism = parse("AXUM_TENANCY_ARGON2_PARALLELISM", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_USER_NAME_MIN_LENGTH") {
+            c.user.user_name_min_length = parse("AXUM_TENANCY_USER_NAME_MIN_LENGTH", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_USER_NAME_MAX_LENGTH") {
+            c.user.user_name_max_length = parse("AXUM_TENANCY_USER_NAME_MAX_LENGTH", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_DEFAULT_COUNTRY_CODE") {
+            c.user.default_country_code = Some(parse("AXUM_TENANCY_DEFAULT_COUNTRY_CODE", v)?);
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_UNIQUE_EMAIL") {
+            c.user.unique_email = parse("AXUM_TENANCY_UNIQUE_EMAIL", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_SESSION_COOKIE") {
+            c.session.cookie_name = v;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_SESSION_EXPIRY_SECS") {
+            c.session.expiry_secs = Some(parse("AXUM_TENANCY_SESSION_EXPIRY_SECS", v)?);
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_SESSION_SECURE") {
+            c.session.secure = parse("AXUM_TENANCY_SESSION_SECURE", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_INVITATION_EXPIRY_SECS") {
+            c.invitation.expiry_secs = parse("AXUM_TENANCY_INVITATION_EXPIRY_SECS", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_TENANT_FILES_DIR") {
+            c.tenant_files.dir = PathBuf::from(v);
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_TENANT_FILES_MAX_OPEN") {
+            c.tenant_files.max_open = parse("AXUM_TENANCY_TENANT_FILES_MAX_OPEN", v)?;
+        }
+        if let Some(v) = lookup("AXUM_TENANCY_TENANT_FILES_IDLE_SECS") {
+            c.tenant_files.idle_secs = parse("AXUM_TENANCY_TENANT_FILES_IDLE_SECS", v)?;
+        }
+        c.validate()?;
+        Ok(c)
+    }
+
+    pub fn from_toml_str(toml: &str) -> Result<TenancyConfig, ConfigError> {
+        let c: TenancyConfig = toml::from_str(toml)?;
+        c.validate()?;
+        Ok(c)
+    }
+
+    pub fn from_toml_file(path: impl AsRef<Path>) -> Result<TenancyConfig, ConfigError> {
+        let path = path.as_ref();
+        let toml = fs::read_to_string(path).map_err(|source| ConfigError::Io {
+            path: path.display().to_string(),
+            source,
+        })?;
+        TenancyConfig::from_toml_str(&toml)
+    }
+
+    /// Checks the values make sense together, the loaders call this but
+    /// a config built in code is only checked when it is used.
+    pub fn validate(&self) -> Result<(), ConfigError> {
+        if self.max_connections == 0 {
+            return Err(ConfigError::Invalid {
+                key: "max_connections",
+                message: "must be at least 1".to_string(),
+            });
+        }
+        if self.password.min_length == 0 {
+            return Err(ConfigError::Invalid {
+                key: "password.min_length",
+                message: "must be at least 1".to_string(),
+            });
+        }
+        if let Err(e) = self.password.hash.check() {
+            return Err(ConfigError::Invalid {
+                key: "password.hash",
+                message: e.to_string(),
+            });
+        }
+        if self.user.user_name_min_length == 0
+            || self.user.user_name_max_length < self.user.user_name_min_length
+        {
+            return Err(ConfigError::Invalid {
+                key: "user.user_name_max_length",
+                message: "must be at least user_name_min_length, which must be at least 1"
+                    .to_string(),
+            });
+        }
+        if matches!(self.user.default_country_code, Some(cc) if !(1..=999).contains(&cc)) {
+            return Err(ConfigError::Invalid {
+                key: "user.default_country_code",
+                message: "must be 1 to 999".to_string(),
+            });
+        }
+        if let Err((role, message)) = self.roles.check() {
+            return Err(ConfigError::Invalid {
+                key: "roles",
+                message: format!("{}: {}", role, message),
+            });
+        }
+        if self.session.cookie_name.is_empty() {
+            return Err(ConfigError::Invalid {
+                key: "session.cookie_name",
+                message: "must not be empty".to_string(),
+            });
+        }
+        if matches!(self.session.expiry_secs, Some(secs) if secs <= 0) {
+            return Err(ConfigError::Invalid {
+                key: "session.expiry_secs",
+                message: "must be positive".to_string(),
+            });
+        }
+        if self.invitation.expiry_secs <= 0 {
+            return Err(ConfigError::Invalid {
+                key: "invitation.expiry_secs",
+                message: "must be positive".to_string(),
+            });
+        }
+        if self.tenant_files.max_open == 0 {
+            return Err(ConfigError::Invalid {
+                key: "tenant_files.max_open",
+                message: "must be at least 1".to_string(),
+            });
+        }
+        if self.tenant_files.idle_secs == 0 {
+            return Err(ConfigError::Invalid {
+                key: "tenant_files.idle_secs",
+                message: "must be positive".to_string(),
+            });
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashMap;
+
+    use axum_tenancy_core::admin_core::role_core::Role;
+
+    use super::*;
+
+    fn from_vars(vars: &[(&str, &str)]) -> Result<TenancyConfig, ConfigError> {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        TenancyConfig::from_lookup(|key| vars.get(key).cloned())
+    }
+
+    #[test]
+    fn env_overrides_defaults() {
+        let c = from_vars(&[
+            ("DATABASE_URL", "sqlite::memory:"),
+            ("AXUM_TENANCY_MAX_CONNECTIONS", "9"),
+            ("AXUM_TENANCY_RUN_MIGRATIONS", "false"),
+            ("AXUM_TENANCY_SESSION_EXPIRY_SECS", "60"),
+            ("AXUM_TENANCY_DEFAULT_COUNTRY_CODE", "44"),
+            ("AXUM_TENANCY_UNIQUE_EMAIL", "true"),
+            ("AXUM_TENANCY_INVITATION_EXPIRY_SECS", "3600"),
+            ("AXUM_TENANCY_ISOLATION", "file"),
+            ("AXUM_TENANCY_TENANT_FILES_DIR", "/var/lib/hannah"),
+            ("AXUM_TENANCY_TENANT_FILES_IDLE_SECS", "60"),
+        ])
+        .unwrap();
+        assert_eq!(c.database_url.as_deref(), Some("sqlite::memory:"));
+        assert_eq!(c.max_connections, 9);
+        assert!(!c.run_migrations);
+        assert_eq!(c.session.expiry_secs, Some(60));
+        assert_eq!(c.password, PasswordPolicy::default());
+        assert_eq!(c.user.default_country_code, Some(44));
+        assert_eq!(c.user.user_name_max_length, 32);
+        assert!(c.user.unique_email);
+        assert_eq!(c.invitation.valid_for(), chrono::Duration::hours(1));
+        assert_eq!(c.isolation, TenantIsolation::File);
+        assert_eq!(c.tenant_files.dir, PathBuf::from("/var/lib/hannah"));
+        assert_eq!(c.tenant_files.idle_timeout(), Duration::from_secs(60));
+        assert_eq!(c.tenant_files.max_open, 32);
+
+        assert_eq!(from_vars(&[]).unwrap(), TenancyConfig::default());
+    }
+
+    #[test]
+    fn env_bad_values_are_errors() {
+        let e = from_vars(&[("AXUM_TENANCY_MAX_CONNECTIONS", "lots")]).unwrap_err();
+        assert!(matches!(
+            e,
+            ConfigError::Invalid {
+                key: "AXUM_TENANCY_MAX_CONNECTIONS",
+                ..
+            }
+        ));
+        let e = from_vars(&[("AXUM_TENANCY_MAX_CONNECTIONS", "0")]).unwrap_err();
+        assert!(matches!(
+            e,
+            ConfigError::Invalid {
+                key: "max_connections",
+                ..
+            }
+        ));
+        let e = from_vars(&[("AXUM_TENANCY_USER_NAME_MIN_LENGTH", "40")]).unwrap_err();
+        assert!(matches!(
+            e,
+            ConfigError::Invalid {
+                key: "user.user_name_max_length",
+                ..
+            }
+        ));
+        let e = from_vars(&[("AXUM_TENANCY_ARGON2_ITERATIONS", "0")]).unwrap_err();
+        assert!(matches!(
+            e,
+            ConfigError::Invalid {
+                key: "password.hash",
+                ..
+            }
+        ));
+        let e = from_vars(&[("AXUM_TENANCY_INVITATION_EXPIRY_SECS", "0")]).unwrap_err();
+        assert!(matches!(
+            e,
+            ConfigError::Invalid {
+                key: "invitation.expiry_secs",
+                ..
+            }
+        ));
+        let e = from_vars(&[("AXUM_TENANCY_TENANT_FILES_MAX_OPEN", "0")]).unwrap_err();
+        assert!(matches!(
+            e,
+            ConfigError::Invalid {
+                key: "tenant_files.max_open",
+                ..
+            }
+        ));
+        let e = from_vars(&[("AXUM_TENANCY_ISOLATION", "database")]).unwrap_err();
+        assert!(matches!(
+            e,
+            ConfigError::Invalid {
+                key: "AXUM_TENANCY_ISOLATION",
+                ..
+            }
+        ));
+    }
+
+    #[test]
+    fn toml_partial_and_nested() {
+        let c = TenancyConfig::from_toml_str(
+            r#"
+            database_url = "sqlite:test.sqlite"
+            isolation = "schema"
+            [password]
+            min_length = 12
+            [password.hash]
+            iterations = 3
+            [session]
+            cookie_name = "tenancy"
+            "#,
+        )
+        .unwrap();
+        assert_eq!(c.database_url.as_deref(), Some("sqlite:test.sqlite"));
+        assert_eq!(c.max_connections, 5);
+        assert_eq!(c.isolation, TenantIsolation::Schema);
+        assert_eq!(c.password.min_length, 12);
+        assert_eq!(c.password.hash.iterations, 3);
+        assert_eq!(c.password.hash.memory_kib, 19 * 1024);
+        assert_eq!(c.session.cookie_name, "tenancy");
+        assert!(c.session.secure);
+    }
+
+    #[test]
+    fn toml_roles() {
+        let c = TenancyConfig::from_toml_str(
+            r#"
+            [roles]
+            accountant = ["invoices:*"]
+            viewer = ["reports:read"]
+            "#,
+        )
+        .unwrap();
+        assert!(c.roles.allows(&Role::from("accountant"), "invoices:write"));
+        assert!(!c.roles.allows(&Role::Viewer, "invoices:read"));
+        assert!(c.roles.allows(&Role::Owner, "invoices:read"));
+        assert!(matches!(
+            TenancyConfig::from_toml_str("[roles]\nx = [\"invoices\"]"),
+            Err(ConfigError::Invalid { key: "roles", .. })
+        ));
+    }
+
+    #[test]
+    fn toml_errors() {
+        assert!(matches!(
+            TenancyConfig::from_toml_str("max_conections = 3"),
+            Err(ConfigError::Toml(_))
+        ));
+        assert!(matches!(
+            TenancyConfig::from_toml_str("[session]\ncookie_name = \"\""),
+            Err(ConfigError::Invalid {
+                key: "session.cookie_name",
+                ..
+            })
+        ));
+        assert!(matches!(
+            TenancyConfig::from_toml_file("/no/such/axum-tenancy.toml"),
+            Err(ConfigError::Io { .. })
+        ));
+    }
+
+    #[test]
+    fn built_in_code() {
+        let c = TenancyConfig::new()
+            .database_url("sqlite::memory:")
+            .max_connections(1)
+            .run_migrations(false);
+        assert!(c.validate().is_ok());
+        assert_eq!(c.max_connections, 1);
+        assert!(c.max_connections(0).validate().is_err());
+    }
+}
@@ -0,0 +1,274 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! axum extractors for the values the tenancy middleware provides.
+//!
+//! The logged in [`User`] is expected in the request extensions, put there by
+//! whatever authentication layer sits in front of the routes.
+
+use std::sync::Arc;
+
+use axum::{
+    async_trait,
+    extract::FromRequestParts,
+    http::{request::Parts, StatusCode},
+};
+use axum_tenancy_core::admin_core::{
+    role_core::{Role, RolePermissions},
+    tenant_core::Tenant,
+    user_core::User,
+    user_tenant_core::UserTenant,
+};
+#[cfg(feature = "sqlite")]
+use axum_tenancy_sqlite::files::TenantPools;
+use uuid::Uuid;
+
+use crate::{
+    admin::{DbPool, DbTransaction},
+    config::TenantIsolation,
+};
+
+/// The logged in user. Rejects with 401 when nobody is logged in.
+#[derive(Debug, Clone)]
+pub struct CurrentUser(pub User);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for CurrentUser {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<User>()
+            .cloned()
+            .map(CurrentUser)
+            .ok_or(StatusCode::UNAUTHORIZED)
+    }
+}
+
+/// The logged in user, who must have `is_admin` set. Rejects with 401 when
+/// nobody is logged in and 403 for users who aren't admins.
+#[derive(Debug, Clone)]
+pub struct AdminUser(pub User);
+
+/// Guard name for handlers that only need the check, not the user.
+pub type RequireAdmin = AdminUser;
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for AdminUser {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let CurrentUser(user) = CurrentUser::from_request_parts(parts, state).await?;
+        if user.is_admin {
+            Ok(AdminUser(user))
+        } else {
+            Err(StatusCode::FORBIDDEN)
+        }
+    }
+}
+
+/// The tenant resolved by [`TenancyLayer`](crate::TenancyLayer) for this
+/// request. Rejects with 403 when there isn't one, which only happens if the
+/// route isn't behind the layer.
+#[derive(Debug, Clone)]
+pub struct CurrentTenant(pub Tenant);
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for CurrentTenant {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<Tenant>()
+            .cloned()
+            .map(CurrentTenant)
+            .ok_or(StatusCode::FORBIDDEN)
+    }
+}
+
+/// The logged in user's role in the current tenant and what it allows.
+/// Rejects like [`CurrentTenant`], and with 401 when nobody is logged in.
+#[derive(Debug, Clone)]
+pub struct TenantPermissions {
+    pub membership: UserTenant,
+    roles: Arc<RolePermissions>,
+}
+
+impl TenantPermissions {
+    pub fn role(&self) -> &Role {
+        &self.membership.role
+    }
+
+    pub fn allows(&self, permission: &str) -> bool {
+        self.roles.allows(&self.membership.role, permission)
+    }
+
+    /// The role permissions the layer was configured with.
+    pub fn roles(&self) -> &RolePermissions {
+        &self.roles
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for TenantPermissions {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        CurrentUser::from_request_parts(parts, state).await?;
+        let membership = parts.extensions.get::<UserTenant>().cloned();
+        let roles = parts.extensions.get::<Arc<RolePermissions>>().cloned();
+        match (membership, roles) {
+            (Some(membership), Some(roles)) => Ok(TenantPermissions { membership, roles }),
+            _ => Err(StatusCode::FORBIDDEN),
+        }
+    }
+}
+
+/// Starts transactions for the current tenant, see
+/// [`DbPool::begin_for_tenant`]. On Postgres this is what makes row level
+/// security policies apply, with [`TenantIsolation::File`] the transactions
+/// are in the tenant's own SQLite file. Rejects like [`CurrentTenant`].
+#[derive(Debug, Clone)]
+pub struct TenantDb {
+    pool: DbPool,
+    tenant_id: Uuid,
+    isolation: TenantIsolation,
+    #[cfg(feature = "sqlite")]
+    files: Option<Arc<TenantPools>>,
+}
+
+impl TenantDb {
+    pub(crate) fn new(pool: DbPool, tenant_id: Uuid, isolation: TenantIsolation) -> TenantDb {
+        TenantDb {
+            pool,
+            tenant_id,
+            isolation,
+            #[cfg(feature = "sqlite")]
+            files: None,
+        }
+    }
+
+    #[cfg(feature = "sqlite")]
+    pub(crate) fn files(mut self, files: Option<Arc<TenantPools>>) -> TenantDb {
+        self.files = files;
+        self
+    }
+
+    pub fn tenant_id(&self) -> Uuid {
+        self.tenant_id
+    }
+
+    pub async fn begin(&self) -> Result<DbTransaction<'static>, sqlx::Error> {
+        #[cfg(feature = "sqlite")]
+        if let (TenantIsolation::File, Some(files)) = (self.isolation, &self.files) {
+            // closed to make room for another tenant since it was handed out
+            let tx = match files.pool(&self.tenant_id).begin().await {
+                Err(sqlx::Error::PoolClosed) => files.pool(&self.tenant_id).begin().await?,
+                tx => tx?,
+            };
+            return Ok(DbTransaction::Sqlite(tx));
+        }
+        self.pool
+            .begin_for_tenant(&self.tenant_id, self.isolation)
+            .await
+    }
+}
+
+#[async_trait]
+impl<S: Send + Sync> FromRequestParts<S> for TenantDb {
+    type Rejection = StatusCode;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        parts
+            .extensions
+            .get::<TenantDb>()
+            .cloned()
+            .ok_or(StatusCode::FORBIDDEN)
+    }
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use axum::{body::Body, http::Request, routing::get, Extension, Router};
+    use tower::ServiceExt;
+
+    use super::*;
+
+    async fn user_name(CurrentUser(user): CurrentUser) -> String {
+        user.user_name
+    }
+
+    async fn admin_name(AdminUser(user): AdminUser) -> String {
+        user.user_name
+    }
+
+    async fn tenant_name(CurrentTenant(tenant): CurrentTenant) -> String {
+        tenant.tenant_name
+    }
+
+    async fn status(router: Router, uri: &str) -> StatusCode {
+        let request = Request::builder().uri(uri).body(Body::empty()).unwrap();
+        router.oneshot(request).await.unwrap().status()
+    }
+
+    fn app() -> Router {
+        Router::new()
+            .route("/user", get(user_name))
+            .route("/admin", get(admin_name))
+            .route("/tenant", get(tenant_name))
+    }
+
+    fn user(is_admin: bool) -> User {
+        User {
+            user_name: "Dave".to_string(),
+            is_admin,
+            ..Default::default()
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_values_are_rejected() {
+        assert_eq!(status(app(), "/user").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(app(), "/admin").await, StatusCode::UNAUTHORIZED);
+        assert_eq!(status(app(), "/tenant").await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn values_from_extensions() {
+        let router = app()
+            .layer(Extension(user(false)))
+            .layer(Extension(Tenant::default()));
+        assert_eq!(status(router.clone(), "/user").await, StatusCode::OK);
+        assert_eq!(status(router.clone(), "/tenant").await, StatusCode::OK);
+        assert_eq!(status(router, "/admin").await, StatusCode::FORBIDDEN);
+    }
+
+    #[tokio::test]
+    async fn admin_passes_guard() {
+        let router = app().layer(Extension(user(true)));
+        assert_eq!(status(router, "/admin").await, StatusCode::OK);
+    }
+}
@@ -22,75 +22,742 @@
 # SOFTWARE.
 */
 
-use std::env;
+use std::sync::Arc;
 
-use anyhow::Ok;
-use axum_tenancy_core::ActiveDb;
-use dotenvy::dotenv;
-use sqlx::{any::install_default_drivers, pool::PoolOptions, AnyPool};
+use axum_login::{
+    tower_sessions::{cookie::time::Duration, Expiry, SessionManagerLayer, SessionStore},
+    AuthManagerLayer, AuthManagerLayerBuilder,
+};
+use axum_tenancy_core::{migration::MigrationReport, tenant_resolver::TenantResolver, ActiveDb};
+use sqlx::{
+    migrate::{MigrateError, Migrator},
+    pool::PoolOptions,
+};
+use uuid::Uuid;
 
 pub mod admin;
+pub mod admin_api;
+pub mod admin_ui;
+pub mod auth;
+pub mod config;
+pub mod extract;
+pub mod middleware;
+pub mod permission;
 
-cfg_if::cfg_if! {
-    if #[cfg(feature = "sqlite")] {
-        pub const ACTIVE_DB: ActiveDb = ActiveDb::Sqlite;
-    } else if #[cfg(feature = "postgres")] {
-        pub const ACTIVE_DB: ActiveDb = ActiveDb::Postgres;
-    } else {
-        pub const ACTIVE_DB: ActiveDb = ActiveDb::Undefined;
+use admin::{tenant, DbPool, DbTransaction};
+use auth::TenancyBackend;
+pub use axum_tenancy_core::{error::TenancyError, migration, tenant_resolver};
+pub use config::{ConfigError, TenancyConfig, TenantIsolation};
+pub use middleware::{TenancyLayer, TenancyService};
+
+#[cfg(feature = "postgres")]
+pub use axum_tenancy_postgres::{rls, schema};
+#[cfg(feature = "sqlite")]
+pub use axum_tenancy_sqlite::files;
+#[cfg(feature = "sqlite")]
+use files::TenantPools;
+
+#[derive(Debug, thiserror::Error)]
+pub enum InitError {
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Database(#[from] sqlx::Error),
+    #[error(transparent)]
+    Migrate(#[from] MigrateError),
+    #[error(transparent)]
+    Tenancy(#[from] TenancyError),
+}
+
+/// Returned by [`initialize`], cheap to clone into routers and middleware.
+#[derive(Clone)]
+pub struct Tenancy {
+    pool: DbPool,
+    config: Arc<TenancyConfig>,
+    tenant_migrator: Option<&'static Migrator>,
+    #[cfg(feature = "sqlite")]
+    tenant_files: Option<Arc<TenantPools>>,
+}
+
+impl Tenancy {
+    pub fn pool(&self) -> &DbPool {
+        &self.pool
+    }
+
+    pub fn config(&self) -> &TenancyConfig {
+        &self.config
+    }
+
+    pub fn active_db(&self) -> ActiveDb {
+        self.pool.active_db()
+    }
+
+    /// The tenant database files, only with [`TenantIsolation::File`].
+    #[cfg(feature = "sqlite")]
+    pub fn tenant_files(&self) -> Option<&Arc<TenantPools>> {
+        self.tenant_files.as_ref()
+    }
+
+    /// The application's migrations for each tenant's own schema or file,
+    /// usually `&sqlx::migrate!("migrations/tenant")`. Not used with
+    /// [`TenantIsolation::Shared`].
+    pub fn tenant_migrations(mut self, migrator: &'static Migrator) -> Tenancy {
+        self.tenant_migrator = Some(migrator);
+        self
+    }
+
+    /// Inserts a tenant and creates and migrates its own schema or file,
+    /// committing `tx`. A schema is created in the same transaction, a file
+    /// once it has committed. If the file can't be made the tenant is
+    /// deleted again and that error returned, failures cleaning up are only
+    /// logged.
+    pub async fn insert_tenant(
+        &self,
+        mut tx: DbTransaction<'_>,
+        tenant_name: &str,
+        display_name: &str,
+    ) -> Result<Uuid, TenancyError> {
+        let tenant_id = tenant::insert(&mut tx, tenant_name, display_name).await?;
+        if self.config.isolation == TenantIsolation::Schema {
+            tenant::create_schema(&mut tx, &tenant_id, self.tenant_migrator).await?;
+        }
+        tx.commit().await?;
+        #[cfg(feature = "sqlite")]
+        if let Some(files) = &self.tenant_files {
+            if let Err(e) = files.create(&tenant_id, self.tenant_migrator).await {
+                if let Err(remove) = files.remove(&tenant_id).await {
+                    tracing::error!(error = %remove, %tenant_id, "axum-tenancy could not remove a tenant file");
+                }
+                if let Err(delete) = self.delete_tenant_row(&tenant_id).await {
+                    tracing::error!(error = %delete, %tenant_id, "axum-tenancy could not delete a tenant without a file");
+                }
+                return Err(e.into());
+            }
+        }
+        Ok(tenant_id)
+    }
+
+    #[cfg(feature = "sqlite")]
+    async fn delete_tenant_row(&self, tenant_id: &Uuid) -> Result<(), TenancyError> {
+        let mut tx = self.pool.begin().await?;
+        tenant::delete(&mut tx, tenant_id).await?;
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Deletes a tenant with its own schema or file, committing `tx` and
+    /// returning the number of tenants deleted. A schema is dropped in the
+    /// same transaction, a file once it has committed, so a failed delete
+    /// leaves the file alone.
+    pub async fn delete_tenant(
+        &self,
+        mut tx: DbTransaction<'_>,
+        tenant_id: &Uuid,
+    ) -> Result<u64, TenancyError> {
+        let deleted = tenant::delete(&mut tx, tenant_id).await?;
+        if deleted > 0 && self.config.isolation == TenantIsolation::Schema {
+            tenant::drop_schema(&mut tx, tenant_id).await?;
+        }
+        tx.commit().await?;
+        #[cfg(feature = "sqlite")]
+        if let Some(files) = &self.tenant_files {
+            if deleted > 0 {
+                files.remove(tenant_id).await?;
+            }
+        }
+        Ok(deleted)
+    }
+
+    /// Runs the tenant migrations in every tenant's schema or file,
+    /// creating any that are missing. Nothing to do with shared tables or
+    /// without [`tenant_migrations`](Self::tenant_migrations).
+    pub async fn migrate_tenants(&self) -> Result<MigrationReport, TenancyError> {
+        let Some(migrator) = self.tenant_migrator else {
+            return Ok(MigrationReport::default());
+        };
+        #[cfg(feature = "sqlite")]
+        if let (DbPool::Sqlite(pool), Some(files)) = (&self.pool, &self.tenant_files) {
+            return Ok(files.migrate_all(pool, migrator).await?);
+        }
+        #[cfg(feature = "postgres")]
+        if let (DbPool::Postgres(pool), TenantIsolation::Schema) =
+            (&self.pool, self.config.isolation)
+        {
+            return Ok(schema::migrate_all(pool, migrator).await?);
+        }
+        Ok(MigrationReport::default())
+    }
+
+    /// The admin pages, see [`admin_ui`].
+    pub fn admin_router(&self) -> axum::Router {
+        admin_ui::admin_router(self.clone())
+    }
+
+    /// The JSON admin API, see [`admin_api`].
+    pub fn api_router(&self) -> axum::Router {
+        admin_api::api_router(self.clone())
+    }
+
+    /// For building the admin pages with other templates or as fragments.
+    pub fn admin_ui(&self) -> admin_ui::AdminUi {
+        admin_ui::AdminUi::new(self.clone())
+    }
+
+    /// A [`TenancyLayer`] sharing this pool, the configured roles and
+    /// isolation.
+    pub fn layer<R: TenantResolver + 'static>(&self, resolver: R) -> TenancyLayer {
+        let layer = TenancyLayer::new(self.pool.clone(), resolver)
+            .roles(self.config.roles.clone())
+            .isolation(self.config.isolation);
+        #[cfg(feature = "sqlite")]
+        if let Some(files) = &self.tenant_files {
+            return layer.tenant_files(files.clone());
+        }
+        layer
+    }
+
+    /// An axum-login backend sharing this pool, the configured password
+    /// hashing and roles.
+    pub fn auth_backend(&self) -> TenancyBackend {
+        TenancyBackend::new(self.pool.clone())
+            .password_params(self.config.password.hash)
+            .roles(self.config.roles.clone())
+    }
+
+    /// The axum-login layer, with sessions in `store` set up from the
+    /// session config.
+    pub fn auth_layer<Store: SessionStore + Clone>(
+        &self,
+        store: Store,
+    ) -> AuthManagerLayer<TenancyBackend, Store> {
+        let session = &self.config.session;
+        let mut sessions = SessionManagerLayer::new(store)
+            .with_name(session.cookie_name.clone())
+            .with_secure(session.secure);
+        if let Some(secs) = session.expiry_secs {
+            sessions = sessions.with_expiry(Expiry::OnInactivity(Duration::seconds(secs)));
+        }
+        AuthManagerLayerBuilder::new(self.auth_backend(), sessions).build()
     }
 }
 
-pub async fn initialize() -> anyhow::Result<()> {
-    println!("Initializing axum-tenancy for DB: {:?}", ACTIVE_DB);
-    dotenv().expect(".env file not found");
-    let database_url: String = env::var("DATABASE_URL").expect("env missing DATABASE_URL");
-    install_default_drivers();
+/// Sets up axum-tenancy on a pool the application made, running the
+/// migrations for the pool's database unless `config` turns them off.
+/// When a migration was applied the stored canonical user names and emails
+/// are brought up to date too, see [`admin::user::recanonicalize`].
+pub async fn initialize(
+    pool: impl Into<DbPool>,
+    config: TenancyConfig,
+) -> Result<Tenancy, InitError> {
+    config.validate()?;
+    let pool = pool.into();
+    if config.isolation == TenantIsolation::Schema && pool.active_db() != ActiveDb::Postgres {
+        return Err(ConfigError::Invalid {
+            key: "isolation",
+            message: "schema isolation needs Postgres".to_string(),
+        }
+        .into());
+    }
+    if config.isolation == TenantIsolation::File && pool.active_db() != ActiveDb::Sqlite {
+        return Err(ConfigError::Invalid {
+            key: "isolation",
+            message: "file isolation needs SQLite".to_string(),
+        }
+        .into());
+    }
+    // recanonicalizing reads every user, only worth it after an upgrade
+    if config.run_migrations && pool.migrate().await? {
+        let mut tx = pool.begin().await?;
+        admin::user::recanonicalize(&mut tx, &config.user).await?;
+        tx.commit().await?;
+    }
+    #[cfg(feature = "sqlite")]
+    let tenant_files = (config.isolation == TenantIsolation::File).then(|| {
+        let files = Arc::new(
+            TenantPools::new(&config.tenant_files.dir)
+                .max_open(config.tenant_files.max_open)
+                .idle_timeout(config.tenant_files.idle_timeout()),
+        );
+        // stops once the last Tenancy and TenancyLayer are dropped
+        files.spawn_close_idle();
+        files
+    });
+    Ok(Tenancy {
+        pool,
+        config: Arc::new(config),
+        tenant_migrator: None,
+        #[cfg(feature = "sqlite")]
+        tenant_files,
+    })
+}
 
-    let _pool: AnyPool = PoolOptions::new()
-        .max_connections(5)
-        .connect(&database_url)
-        .await
-        .expect("Could not create db pool");
-    Ok(())
-    //:#![warn()]::migrate!().run(&pool).await.expect("Migration fail");
+/// Like [`initialize`] with a pool made from the configured database url,
+/// the url's scheme picks the backend.
+pub async fn connect(config: TenancyConfig) -> Result<Tenancy, InitError> {
+    config.validate()?;
+    let url = config
+        .database_url
+        .as_deref()
+        .ok_or(ConfigError::MissingDatabaseUrl)?;
+    let pool: DbPool = match ActiveDb::from_url(url) {
+        #[cfg(feature = "sqlite")]
+        ActiveDb::Sqlite => PoolOptions::<sqlx::Sqlite>::new()
+            .max_connections(config.max_connections)
+            .connect(url)
+            .await?
+            .into(),
+        #[cfg(feature = "postgres")]
+        ActiveDb::Postgres => PoolOptions::<sqlx::Postgres>::new()
+            .max_connections(config.max_connections)
+            .connect(url)
+            .await?
+            .into(),
+        db => {
+            return Err(ConfigError::Invalid {
+                key: "database_url",
+                message: format!("{:?} is not supported by this build", db),
+            }
+            .into())
+        }
+    };
+    initialize(pool, config).await
 }
 
-//pub async fn initialize(_pool: &PgPool) -> anyhow::Result<()> {
-//assert!(SQLX_DB.ne("No Database Feature set in your Cargo.toml, should be
-// sqlite or postgres"));    println!("Initializing axum-tenancy for DB: {:?}",
-// ACTIVE_DB); admin_postgres::user_postgres::insert();
-/*
- *cfg_if::cfg_if! {
- *    if #[cfg(feature = "sqlite")] {
- *        sqlx::migrate!("migrations/sqlite").run(pool).await?;
- *    } else if #[cfg(feature = "postgres")] {
- *        sqlx::migrate!("migrations/postgres").run(pool).await?;
- *    } else {
- *        assert_eq!("No Db feature", "In Cargo.toml for axum-tenancy");
- *        // never yet here but ensure that pool is used
- *        sqlx::migrate!("migrations/sqlite").run(pool).await?;
- *    }
- *}
- */
+#[cfg(test)]
+mod tests_tokio {
+    use test_context::test_context;
 
-/*
-* println!("pre migrate");
-sqlx::migrate!("migrations/postgres").run(pool).await?;
-println!("migrated");
-
-let tx: &mut sqlx::Transaction<'_, sqlx::Postgres> = &mut pool.begin().await?;
-println!("tx");
-
-let ru = insert(tx, "Dave", "Dave Warnock", true, "dwarnock@test.com", "01234567891").await;
-println!("ins");
-
-let uid = ru.unwrap_or_default();
-println!("uuid: {}", uid);
-let ur = load_by_id(tx, uid).await;
-let u = ur.unwrap_or_default();
-println!("u.user_name: {}", u.user_name);
-eprintln!("sort:{}",UserSort::UserName.as_str());
-*/
-//    Ok(())
-//}
+    use super::*;
+    use crate::admin::test_support::*;
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn initialize_is_repeatable(_tenancy_context: &mut TenancyTestContext) {
+        let pool = get_test_db_pool().await;
+        initialize(pool.clone(), TenancyConfig::default())
+            .await
+            .unwrap();
+        initialize(pool.clone(), TenancyConfig::default())
+            .await
+            .unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn initialize_creates_tables() {
+        use axum_tenancy_core::admin_core::user_core::Deleted;
+
+        use crate::admin::user;
+
+        let config = TenancyConfig::new()
+            .database_url("sqlite::memory:")
+            .max_connections(1);
+        let tenancy = connect(config).await.unwrap();
+        assert_eq!(tenancy.active_db(), ActiveDb::Sqlite);
+        // already migrated, so nothing to recanonicalize next time
+        assert!(!tenancy.pool().migrate().await.unwrap());
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let user_id = user::insert(
+            &mut tx,
+            "Dave",
+            "password",
+            "Dave Warnock",
+            true,
+            "dwarnock@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        assert_eq!(
+            user::load_by_id(&mut tx, user_id, Deleted::Exclude)
+                .await
+                .unwrap()
+                .user_name,
+            "Dave"
+        );
+    }
+
+    #[cfg(all(feature = "sqlite", feature = "postgres"))]
+    #[tokio::test]
+    async fn connect_picks_backend_from_url() {
+        dotenvy::dotenv().expect(".env file not found");
+        let url = std::env::var("POSTGRES_TEST_DATABASE_URL")
+            .expect("env missing POSTGRES_TEST_DATABASE_URL");
+        let config = TenancyConfig::new()
+            .database_url(&url)
+            .run_migrations(false);
+        let tenancy = connect(config).await.unwrap();
+        assert_eq!(tenancy.active_db(), ActiveDb::Postgres);
+
+        let config = TenancyConfig::new().database_url("sqlite::memory:");
+        let tenancy = connect(config).await.unwrap();
+        assert_eq!(tenancy.active_db(), ActiveDb::Sqlite);
+    }
+
+    #[tokio::test]
+    async fn connect_rejects_unknown_scheme() {
+        let r = connect(TenancyConfig::new().database_url("mysql://localhost/tenancy")).await;
+        assert!(matches!(
+            r,
+            Err(InitError::Config(ConfigError::Invalid {
+                key: "database_url",
+                ..
+            }))
+        ));
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn schema_isolation_needs_postgres() {
+        let config = TenancyConfig::new()
+            .database_url("sqlite::memory:")
+            .isolation(TenantIsolation::Schema);
+        assert!(matches!(
+            connect(config).await,
+            Err(InitError::Config(ConfigError::Invalid {
+                key: "isolation",
+                ..
+            }))
+        ));
+    }
+
+    static TENANT_MIGRATIONS: Migrator = sqlx::migrate!("test_migrations/tenant");
+
+    #[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn each_tenant_has_its_own_schema(_tenancy_context: &mut TenancyTestContext) {
+        let pool = get_committed_test_db_pool().await;
+        let tenancy = initialize(
+            pool.clone(),
+            TenancyConfig::default().isolation(TenantIsolation::Schema),
+        )
+        .await
+        .unwrap()
+        .tenant_migrations(&TENANT_MIGRATIONS);
+        let suffix = Uuid::new_v4().simple().to_string();
+
+        let a = tenancy
+            .insert_tenant(
+                pool.begin().await.unwrap(),
+                &format!("A{suffix}"),
+                &format!("A {suffix}"),
+            )
+            .await
+            .unwrap();
+        let b = tenancy
+            .insert_tenant(
+                pool.begin().await.unwrap(),
+                &format!("B{suffix}"),
+                &format!("B {suffix}"),
+            )
+            .await
+            .unwrap();
+
+        let mut tx = pool
+            .begin_for_tenant(&a, TenantIsolation::Schema)
+            .await
+            .unwrap();
+        let DbTransaction::Postgres(pg) = &mut tx;
+        sqlx::query("INSERT INTO note (note_id, body) VALUES (1, 'for a')")
+            .execute(&mut **pg)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        for (tenant_id, expected) in [(a, 1), (b, 0)] {
+            let mut tx = pool
+                .begin_for_tenant(&tenant_id, TenantIsolation::Schema)
+                .await
+                .unwrap();
+            let DbTransaction::Postgres(pg) = &mut tx;
+            let count: i64 = sqlx::query_scalar("SELECT count(*) FROM note")
+                .fetch_one(&mut **pg)
+                .await
+                .unwrap();
+            assert_eq!(count, expected);
+        }
+
+        for tenant_id in [a, b] {
+            let tx = pool.begin().await.unwrap();
+            assert_eq!(tenancy.delete_tenant(tx, &tenant_id).await.unwrap(), 1);
+        }
+        let mut tx = pool.begin().await.unwrap();
+        let DbTransaction::Postgres(pg) = &mut tx;
+        let left: i64 = sqlx::query_scalar(
+            "SELECT count(*) FROM information_schema.schemata WHERE schema_name = ANY($1)",
+        )
+        .bind([schema::schema_name(&a), schema::schema_name(&b)])
+        .fetch_one(&mut **pg)
+        .await
+        .unwrap();
+        assert_eq!(left, 0);
+        tx.commit().await.unwrap();
+    }
+
+    #[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn concurrent_schema_migrations_take_turns(_tenancy_context: &mut TenancyTestContext) {
+        let pool = get_committed_test_db_pool().await;
+        let suffix = Uuid::new_v4().simple().to_string();
+        let mut tx = pool.begin().await.unwrap();
+        let tenant_id = tenant::insert(&mut tx, &format!("C{suffix}"), &format!("C {suffix}"))
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        // without the lock the second CREATE TABLE fails once the first commits
+        let create = || async {
+            let mut tx = pool.begin().await.unwrap();
+            tenant::create_schema(&mut tx, &tenant_id, Some(&TENANT_MIGRATIONS)).await?;
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            tx.commit().await?;
+            Ok::<_, TenancyError>(())
+        };
+        let (first, second) = tokio::join!(create(), create());
+        first.unwrap();
+        second.unwrap();
+
+        let mut tx = pool.begin().await.unwrap();
+        tenant::drop_schema(&mut tx, &tenant_id).await.unwrap();
+        tenant::delete(&mut tx, &tenant_id).await.unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    #[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn migrate_tenants_reports_failures(_tenancy_context: &mut TenancyTestContext) {
+        let pool = get_committed_test_db_pool().await;
+        let tenancy = initialize(
+            pool.clone(),
+            TenancyConfig::default().isolation(TenantIsolation::Schema),
+        )
+        .await
+        .unwrap()
+        .tenant_migrations(&TENANT_MIGRATIONS);
+        let suffix = Uuid::new_v4().simple().to_string();
+
+        // tenants added before schema isolation was turned on, one with a
+        // clashing table the migrations can't create
+        let mut tx = pool.begin().await.unwrap();
+        let good = tenant::insert(&mut tx, &format!("Good{suffix}"), &format!("Good {suffix}"))
+            .await
+            .unwrap();
+        let bad = tenant::insert(&mut tx, &format!("Bad{suffix}"), &format!("Bad {suffix}"))
+            .await
+            .unwrap();
+        let DbTransaction::Postgres(pg) = &mut tx;
+        let bad_schema = schema::schema_name(&bad);
+        sqlx::query(&format!("CREATE SCHEMA \"{bad_schema}\""))
+            .execute(&mut **pg)
+            .await
+            .unwrap();
+        sqlx::query(&format!("CREATE TABLE \"{bad_schema}\".note (note_id int)"))
+            .execute(&mut **pg)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        let report = tenancy.migrate_tenants().await.unwrap();
+        assert!(!report.is_ok());
+        assert!(report.migrated.iter().any(|t| t.tenant_id == good));
+        assert!(report.failed.iter().any(|(t, _)| t.tenant_id == bad));
+        assert!(!report.failed.iter().any(|(t, _)| t.tenant_id == good));
+
+        let mut tx = pool.begin().await.unwrap();
+        for t in report
+            .migrated
+            .iter()
+            .chain(report.failed.iter().map(|(t, _)| t))
+        {
+            tenant::drop_schema(&mut tx, &t.tenant_id).await.unwrap();
+        }
+        tenant::delete(&mut tx, &good).await.unwrap();
+        tenant::delete(&mut tx, &bad).await.unwrap();
+        tx.commit().await.unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    async fn file_tenancy() -> (Tenancy, std::path::PathBuf) {
+        let dir = std::env::temp_dir().join(format!(
+            "axum_tenancy_tenant_files_{}",
+            Uuid::new_v4().simple()
+        ));
+        let config = TenancyConfig::new()
+            .database_url("sqlite::memory:")
+            .max_connections(1)
+            .isolation(TenantIsolation::File)
+            .tenant_files(config::TenantFilesConfig {
+                dir: dir.clone(),
+                ..Default::default()
+            });
+        let tenancy = connect(config)
+            .await
+            .unwrap()
+            .tenant_migrations(&TENANT_MIGRATIONS);
+        (tenancy, dir)
+    }
+
+    // with both features DbTransaction has a Postgres variant too
+    #[cfg(feature = "sqlite")]
+    #[allow(irrefutable_let_patterns)]
+    #[tokio::test]
+    async fn each_tenant_has_its_own_file() {
+        use crate::extract::TenantDb;
+
+        let (tenancy, dir) = file_tenancy().await;
+        let files = tenancy.tenant_files().unwrap().clone();
+        let begin = || async { tenancy.pool().begin().await.unwrap() };
+        let a = tenancy
+            .insert_tenant(begin().await, "A", "A Church")
+            .await
+            .unwrap();
+        let b = tenancy
+            .insert_tenant(begin().await, "B", "B Church")
+            .await
+            .unwrap();
+        assert!(files.file_path(&a).exists());
+        assert!(files.file_path(&b).exists());
+
+        let db = |tenant_id| {
+            TenantDb::new(tenancy.pool().clone(), tenant_id, TenantIsolation::File)
+                .files(Some(files.clone()))
+        };
+        let mut tx = db(a).begin().await.unwrap();
+        let DbTransaction::Sqlite(conn) = &mut tx else {
+            panic!("tenant files are SQLite")
+        };
+        sqlx::query("INSERT INTO note (note_id, body) VALUES (1, 'for a')")
+            .execute(&mut **conn)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+
+        for (tenant_id, expected) in [(a, 1), (b, 0)] {
+            let mut tx = db(tenant_id).begin().await.unwrap();
+            let DbTransaction::Sqlite(conn) = &mut tx else {
+                panic!("tenant files are SQLite")
+            };
+            let count: i64 = sqlx::query_scalar("SELECT count(*) FROM note")
+                .fetch_one(&mut **conn)
+                .await
+                .unwrap();
+            assert_eq!(count, expected);
+        }
+
+        assert_eq!(tenancy.delete_tenant(begin().await, &a).await.unwrap(), 1);
+        assert_eq!(tenancy.delete_tenant(begin().await, &b).await.unwrap(), 1);
+        assert!(!files.file_path(&a).exists());
+        assert!(!files.file_path(&b).exists());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    // with both features DbTransaction has a Postgres variant too
+    #[cfg(feature = "sqlite")]
+    #[allow(irrefutable_let_patterns)]
+    #[tokio::test]
+    async fn rolled_back_tenant_changes_leave_files_alone() {
+        let (tenancy, dir) = file_tenancy().await;
+        let files = tenancy.tenant_files().unwrap().clone();
+        let begin = || async { tenancy.pool().begin().await.unwrap() };
+        let a = tenancy
+            .insert_tenant(begin().await, "A", "A Church")
+            .await
+            .unwrap();
+
+        // a clashing name is rolled back before any file is made
+        let r = tenancy.insert_tenant(begin().await, "A", "Other").await;
+        assert!(
+            matches!(r, Err(TenancyError::DuplicateTenantName)),
+            "{:?}",
+            r
+        );
+        assert_eq!(std::fs::read_dir(&dir).unwrap().count(), 1);
+
+        // the delete goes through but leaves a membership behind, which the
+        // deferred foreign key check turns into a failed commit
+        let mut tx = begin().await;
+        let DbTransaction::Sqlite(conn) = &mut tx else {
+            panic!("tenant files are SQLite")
+        };
+        for sql in [
+            "PRAGMA defer_foreign_keys = ON",
+            "CREATE TEMP TRIGGER orphan AFTER DELETE ON tenant BEGIN \
+                INSERT INTO user_tenant (user_tenant_id, user_id, tenant_id) \
+                VALUES ('orphan', 'orphan', old.tenant_id); END",
+        ] {
+            sqlx::query(sql).execute(&mut **conn).await.unwrap();
+        }
+        assert!(tenancy.delete_tenant(tx, &a).await.is_err());
+        assert!(files.file_path(&a).exists());
+        let mut tx = begin().await;
+        assert_eq!(tenant::load_by_id(&mut tx, a).await.unwrap().tenant_id, a);
+        drop(tx);
+
+        assert_eq!(tenancy.delete_tenant(begin().await, &a).await.unwrap(), 1);
+        assert!(!files.file_path(&a).exists());
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn failed_file_deletes_the_tenant_and_returns_its_error() {
+        let (tenancy, dir) = file_tenancy().await;
+
+        // a plain file where the directory should be, so making the tenant's
+        // file fails and so does removing it again
+        std::fs::write(&dir, "").unwrap();
+        let tx = tenancy.pool().begin().await.unwrap();
+        let r = tenancy.insert_tenant(tx, "A", "A Church").await;
+        assert!(matches!(r, Err(TenancyError::Migration(_))), "{:?}", r);
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let r = tenant::load_by_name(&mut tx, "A").await;
+        assert!(matches!(r, Err(TenancyError::NotFound)), "{:?}", r);
+        std::fs::remove_file(&dir).unwrap();
+    }
+
+    #[cfg(feature = "sqlite")]
+    #[tokio::test]
+    async fn migrate_tenants_reports_failures_per_file() {
+        let (tenancy, dir) = file_tenancy().await;
+        let files = tenancy.tenant_files().unwrap().clone();
+
+        // tenants added before file isolation was turned on, one with a
+        // clashing table the migrations can't create
+        let mut tx = tenancy.pool().begin().await.unwrap();
+        let good = tenant::insert(&mut tx, "Good", "Good Church")
+            .await
+            .unwrap();
+        let bad = tenant::insert(&mut tx, "Bad", "Bad Church").await.unwrap();
+        tx.commit().await.unwrap();
+        files.create(&bad, None).await.unwrap();
+        sqlx::query("CREATE TABLE note (note_id TEXT)")
+            .execute(&files.pool(&bad))
+            .await
+            .unwrap();
+
+        let report = tenancy.migrate_tenants().await.unwrap();
+        assert!(!report.is_ok());
+        assert_eq!(report.migrated.len(), 1);
+        assert_eq!(report.migrated[0].tenant_id, good);
+        assert_eq!(report.failed.len(), 1);
+        assert_eq!(report.failed[0].0.tenant_id, bad);
+
+        files.remove(&good).await.unwrap();
+        files.remove(&bad).await.unwrap();
+        std::fs::remove_dir(&dir).unwrap();
+    }
+
+    #[tokio::test]
+    async fn connect_needs_database_url() {
+        let r = connect(TenancyConfig::default()).await;
+        assert!(matches!(
+            r,
+            Err(InitError::Config(ConfigError::MissingDatabaseUrl))
+        ));
+    }
+}
@@ -0,0 +1,536 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Tower middleware that keeps users inside the tenants they belong to.
+//!
+//! Apply [`TenancyLayer`] to the routers that need protecting, for example
+//! with `Router::route_layer`. An authentication layer earlier in the stack
+//! must have put the logged in [`User`] into the request extensions. The
+//! layer uses a [`TenantResolver`] to find the requested tenant, checks the
+//! user has a UserTenant for it and then adds the [`Tenant`] to the request
+//! extensions, where handlers get it with the
+//! [`CurrentTenant`](crate::extract::CurrentTenant) extractor. The
+//! [`UserTenant`] and the layer's [`RolePermissions`] are added too, for
+//! [`TenantPermissions`](crate::extract::TenantPermissions) and
+//! [`require_permission`](crate::permission::require_permission), and a
+//! [`TenantDb`] whose transactions set the tenant for Postgres row level
+//! security, or open the tenant's own schema or database file.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    http::{request::Parts, Request, StatusCode},
+    response::{IntoResponse, Redirect, Response},
+};
+use axum_tenancy_core::{
+    admin_core::{
+        role_core::RolePermissions, tenant_core::Tenant, user_core::User,
+        user_tenant_core::UserTenant,
+    },
+    error::TenancyError,
+    tenant_resolver::TenantResolver,
+};
+#[cfg(feature = "sqlite")]
+use axum_tenancy_sqlite::files::TenantPools;
+use tower::{Layer, Service};
+
+use crate::{
+    admin::{tenant, user_tenant, DbPool},
+    config::TenantIsolation,
+    extract::TenantDb,
+};
+
+#[derive(Clone)]
+pub struct TenancyLayer {
+    pool: DbPool,
+    resolver: Arc<dyn TenantResolver>,
+    redirect_to: Option<String>,
+    roles: Arc<RolePermissions>,
+    isolation: TenantIsolation,
+    #[cfg(feature = "sqlite")]
+    files: Option<Arc<TenantPools>>,
+}
+
+impl TenancyLayer {
+    pub fn new<R: TenantResolver + 'static>(pool: DbPool, resolver: R) -> TenancyLayer {
+        TenancyLayer {
+            pool,
+            resolver: Arc::new(resolver),
+            redirect_to: None,
+            roles: Arc::new(RolePermissions::default()),
+            isolation: TenantIsolation::default(),
+            #[cfg(feature = "sqlite")]
+            files: None,
+        }
+    }
+
+    /// How [`TenantDb`] transactions find the tenant's data, shared tables
+    /// by default.
+    pub fn isolation(mut self, isolation: TenantIsolation) -> TenancyLayer {
+        self.isolation = isolation;
+        self
+    }
+
+    /// Gives each tenant its own SQLite file from `files`, setting
+    /// [`TenantIsolation::File`].
+    #[cfg(feature = "sqlite")]
+    pub fn tenant_files(mut self, files: Arc<TenantPools>) -> TenancyLayer {
+        self.isolation = TenantIsolation::File;
+        self.files = Some(files);
+        self
+    }
+
+    /// The permissions of each role, the built-in roles by default.
+    pub fn roles(mut self, roles: RolePermissions) -> TenancyLayer {
+        self.roles = Arc::new(roles);
+        self
+    }
+
+    /// Redirect rejected requests to `url` instead of answering 401/403.
+    pub fn redirect_to(mut self, url: &str) -> TenancyLayer {
+        self.redirect_to = Some(url.to_string());
+        self
+    }
+
+    async fn check_access(&self, parts: &Parts) -> Result<(Tenant, UserTenant), Denied> {
+        let user = parts
+            .extensions
+            .get::<User>()
+            .ok_or(Denied::Unauthenticated)?;
+        let tenant_name = self.resolver.resolve(parts).ok_or(Denied::Forbidden)?;
+
+        let mut tx = self.pool.begin().await?;
+        let tenant = tenant::load_by_name(&mut tx, &tenant_name).await?;
+        let membership =
+            user_tenant::load_by_user_and_tenant(&mut tx, &user.user_id, &tenant.tenant_id).await?;
+        Ok((tenant, membership))
+    }
+
+    fn reject(&self, denied: Denied) -> Response {
+        match (&self.redirect_to, denied) {
+            (_, Denied::Error(e)) => e.into_response(),
+            (Some(url), _) => Redirect::to(url).into_response(),
+            (None, Denied::Unauthenticated) => StatusCode::UNAUTHORIZED.into_response(),
+            (None, Denied::Forbidden) => StatusCode::FORBIDDEN.into_response(),
+        }
+    }
+}
+
+impl<S> Layer<S> for TenancyLayer {
+    type Service = TenancyService<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        TenancyService {
+            inner,
+            layer: self.clone(),
+        }
+    }
+}
+
+enum Denied {
+    Unauthenticated,
+    Forbidden,
+    Error(TenancyError),
+}
+
+impl From<TenancyError> for Denied {
+    fn from(e: TenancyError) -> Denied {
+        match e {
+            TenancyError::NotFound => Denied::Forbidden,
+            e => Denied::Error(e),
+        }
+    }
+}
+
+impl From<sqlx::Error> for Denied {
+    fn from(e: sqlx::Error) -> Denied {
+        TenancyError::from(e).into()
+    }
+}
+
+#[derive(Clone)]
+pub struct TenancyService<S> {
+    inner: S,
+    layer: TenancyLayer,
+}
+
+impl<S> Service<Request<Body>> for TenancyService<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // the clone may not be ready, keep the service poll_ready was called on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let layer = self.layer.clone();
+        Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            match layer.check_access(&parts).await {
+                Ok((tenant, membership)) => {
+                    let db = TenantDb::new(layer.pool.clone(), tenant.tenant_id, layer.isolation);
+                    #[cfg(feature = "sqlite")]
+                    let db = db.files(layer.files.clone());
+                    parts.extensions.insert(db);
+                    parts.extensions.insert(tenant);
+                    parts.extensions.insert(membership);
+                    parts.extensions.insert(layer.roles.clone());
+                    inner.call(Request::from_parts(parts, body)).await
+                }
+                Err(denied) => Ok(layer.reject(denied)),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use axum::{http::header, routing::get, Extension, Router};
+    use axum_tenancy_core::admin_core::{role_core::Role, user_core::Deleted};
+    use axum_tenancy_core::tenant_resolver::{ChainResolver, HeaderResolver, PathPrefixResolver};
+    use test_context::test_context;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        admin::{test_support::*, user},
+        extract::{CurrentTenant, TenantDb},
+    };
+
+    fn tenant_from_header(parts: &Parts) -> Option<String> {
+        parts
+            .headers
+            .get("x-tenant")
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    }
+
+    async fn tenant_name(CurrentTenant(tenant): CurrentTenant) -> String {
+        tenant.tenant_name
+    }
+
+    fn app(layer: TenancyLayer, user: Option<User>) -> Router {
+        let router = Router::new()
+            .route("/", get(tenant_name))
+            .route("/t/:tenant", get(tenant_name))
+            .route_layer(layer);
+        match user {
+            Some(u) => router.layer(Extension(u)),
+            None => router,
+        }
+    }
+
+    fn request(tenant_name: &str) -> Request<Body> {
+        Request::builder()
+            .uri("/")
+            .header("x-tenant", tenant_name)
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    /// Commits a user who belongs to one tenant, the middleware uses its own
+    /// connection so can't see uncommitted rows. Returns (user, member tenant,
+    /// other tenant).
+    async fn setup_member(pool: &DbPool) -> (User, String, String) {
+        let suffix = Uuid::new_v4().simple().to_string();
+        let mut tx = pool.begin().await.unwrap();
+        let user_id = user::insert(
+            &mut tx,
+            &format!("mw{}", suffix),
+            "password",
+            &format!("Middleware {}", suffix),
+            false,
+            "dwarnock@test.com",
+            "01234567891",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        let member = format!("member{}", suffix);
+        let other = format!("other{}", suffix);
+        let member_id = tenant::insert(&mut tx, &member, &member).await.unwrap();
+        tenant::insert(&mut tx, &other, &other).await.unwrap();
+        user_tenant::insert(&mut tx, &user_id, &member_id, &Role::Viewer)
+            .await
+            .unwrap();
+        let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        (u, member, other)
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn member_reaches_handler_with_tenant(_tenancy_context: &mut TenancyTestContext) {
+        let pool = get_committed_test_db_pool().await;
+        let (u, member, _) = setup_member(&pool).await;
+        let layer = TenancyLayer::new(pool.clone(), tenant_from_header);
+
+        let response = app(layer, Some(u)).oneshot(request(&member)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], member.as_bytes());
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn non_member_is_forbidden(_tenancy_context: &mut TenancyTestContext) {
+        let pool = get_committed_test_db_pool().await;
+        let (u, _, other) = setup_member(&pool).await;
+        let layer = TenancyLayer::new(pool.clone(), HeaderResolver::default());
+
+        let response = app(layer.clone(), Some(u.clone()))
+            .oneshot(request(&other))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = app(layer, Some(u))
+            .oneshot(request("no such tenant"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn no_user_is_unauthorized(_tenancy_context: &mut TenancyTestContext) {
+        let pool = get_committed_test_db_pool().await;
+        let (_, member, _) = setup_member(&pool).await;
+        let layer = TenancyLayer::new(pool.clone(), HeaderResolver::default());
+
+        let response = app(layer, None).oneshot(request(&member)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn rejection_redirects_when_configured(_tenancy_context: &mut TenancyTestContext) {
+        let pool = get_committed_test_db_pool().await;
+        let (u, _, other) = setup_member(&pool).await;
+        let layer =
+            TenancyLayer::new(pool.clone(), HeaderResolver::default()).redirect_to("/tenants");
+
+        let response = app(layer, Some(u)).oneshot(request(&other)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.headers()[header::LOCATION], "/tenants");
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn chained_resolvers_feed_current_tenant(_tenancy_context: &mut TenancyTestContext) {
+        let pool = get_committed_test_db_pool().await;
+        let (u, member, other) = setup_member(&pool).await;
+        let resolver = ChainResolver::new()
+            .or(PathPrefixResolver::new("t"))
+            .or(HeaderResolver::default());
+        let layer = TenancyLayer::new(pool.clone(), resolver);
+
+        // the path wins over the header
+        let req = Request::builder()
+            .uri(format!("/t/{}", member))
+            .header("x-tenant", &other)
+            .body(Body::empty())
+            .unwrap();
+        let response = app(layer.clone(), Some(u.clone()))
+            .oneshot(req)
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(&body[..], member.as_bytes());
+
+        let response = app(layer, Some(u)).oneshot(request(&other)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn tenant_db_is_for_the_current_tenant(_tenancy_context: &mut TenancyTestContext) {
+        let pool = get_committed_test_db_pool().await;
+        let (u, member, _) = setup_member(&pool).await;
+        let layer = TenancyLayer::new(pool.clone(), HeaderResolver::default());
+        async fn tenant_id(db: TenantDb) -> String {
+            db.begin().await.unwrap().commit().await.unwrap();
+            db.tenant_id().to_string()
+        }
+        let router = Router::new()
+            .route("/", get(tenant_id))
+            .route_layer(layer)
+            .layer(Extension(u));
+
+        let response = router.oneshot(request(&member)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let mut tx = pool.begin().await.unwrap();
+        let t = tenant::load_by_name(&mut tx, &member).await.unwrap();
+        assert_eq!(&body[..], t.tenant_id.to_string().as_bytes());
+
+        let response = Router::new()
+            .route("/", get(tenant_id))
+            .oneshot(request(&member))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+
+    // the test database user is a superuser, which row level security never
+    // applies to, so the handler switches to an ordinary role
+    #[cfg(all(feature = "postgres", not(feature = "sqlite")))]
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn row_level_security_hides_other_tenants(_tenancy_context: &mut TenancyTestContext) {
+        use crate::{admin::DbTransaction, rls::TenantPolicy};
+
+        let pool = get_committed_test_db_pool().await;
+        let (u, member, other) = setup_member(&pool).await;
+        let suffix = Uuid::new_v4().simple().to_string();
+        let table = format!("rls_note_{}", suffix);
+        let role = format!("rls_role_{}", suffix);
+
+        let mut tx = pool.begin().await.unwrap();
+        let member_id = tenant::load_by_name(&mut tx, &member)
+            .await
+            .unwrap()
+            .tenant_id;
+        let other_id = tenant::load_by_name(&mut tx, &other)
+            .await
+            .unwrap()
+            .tenant_id;
+        user_tenant::insert(&mut tx, &u.user_id, &other_id, &Role::Viewer)
+            .await
+            .unwrap();
+        let DbTransaction::Postgres(pg) = &mut tx;
+        for sql in [
+            format!(
+                "CREATE TABLE {} (tenant_id uuid NOT NULL, note text NOT NULL)",
+                table
+            ),
+            format!("CREATE ROLE {} NOLOGIN", role),
+            format!("GRANT SELECT, INSERT ON {} TO {}", table, role),
+            // the committed tests have their own schema
+            format!(
+                "DO $$ BEGIN EXECUTE format('GRANT USAGE ON SCHEMA %I TO {}', current_schema()); END $$",
+                role
+            ),
+            format!(
+                "INSERT INTO {} VALUES ('{}', 'mine'), ('{}', 'theirs')",
+                table, member_id, other_id
+            ),
+        ] {
+            sqlx::query(&sql).execute(&mut **pg).await.unwrap();
+        }
+        TenantPolicy::new(&table).apply(pg).await.unwrap();
+        tx.commit().await.unwrap();
+
+        let notes = {
+            let (table, role) = (table.clone(), role.clone());
+            move |db: TenantDb| async move {
+                let mut tx = db.begin().await.unwrap();
+                let DbTransaction::Postgres(pg) = &mut tx;
+                sqlx::query(&format!("SET LOCAL ROLE {}", role))
+                    .execute(&mut **pg)
+                    .await
+                    .unwrap();
+                // no tenant_id, the policy's default fills it in
+                sqlx::query(&format!("INSERT INTO {} (note) VALUES ('new')", table))
+                    .execute(&mut **pg)
+                    .await
+                    .unwrap();
+                // and no WHERE, the policy does the filtering
+                let notes: Vec<String> =
+                    sqlx::query_scalar(&format!("SELECT note FROM {} ORDER BY note", table))
+                        .fetch_all(&mut **pg)
+                        .await
+                        .unwrap();
+                tx.commit().await.unwrap();
+                notes.join(",")
+            }
+        };
+        let router = Router::new()
+            .route("/", get(notes))
+            .route_layer(TenancyLayer::new(pool.clone(), HeaderResolver::default()))
+            .layer(Extension(u));
+        for (tenant, expected) in [(&member, "mine,new"), (&other, "new,theirs")] {
+            let response = router.clone().oneshot(request(tenant)).await.unwrap();
+            let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+                .await
+                .unwrap();
+            assert_eq!(&body[..], expected.as_bytes());
+        }
+
+        // writing another tenant's rows is refused too
+        let mut tx = pool
+            .begin_for_tenant(&member_id, TenantIsolation::Shared)
+            .await
+            .unwrap();
+        let DbTransaction::Postgres(pg) = &mut tx;
+        sqlx::query(&format!("SET LOCAL ROLE {}", role))
+            .execute(&mut **pg)
+            .await
+            .unwrap();
+        let r = sqlx::query(&format!(
+            "INSERT INTO {} VALUES ('{}', 'sneaky')",
+            table, other_id
+        ))
+        .execute(&mut **pg)
+        .await;
+        assert!(r.is_err());
+        tx.rollback().await.unwrap();
+
+        let mut tx = pool.begin().await.unwrap();
+        let DbTransaction::Postgres(pg) = &mut tx;
+        for sql in [
+            format!("DROP TABLE {}", table),
+            format!("DROP OWNED BY {}", role),
+            format!("DROP ROLE {}", role),
+        ] {
+            sqlx::query(&sql).execute(&mut **pg).await.unwrap();
+        }
+        tx.commit().await.unwrap();
+    }
+}
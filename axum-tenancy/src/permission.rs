@@ -0,0 +1,280 @@
+/*
+# MIT License
+#
+# Copyright (c) 2024 Dave Warnock
+#
+# Permission is hereby granted, free of charge, to any person obtaining a copy
+# of this software and associated documentation files (the "Software"), to deal
+# in the Software without restriction, including without limitation the rights
+# to use, copy, modify, merge, publish, distribute, sublicense, and/or sell
+# copies of the Software, and to permit persons to whom the Software is
+# furnished to do so, subject to the following conditions:
+#
+# The above copyright notice and this permission notice shall be included in all
+# copies or substantial portions of the Software.
+#
+# THE SOFTWARE IS PROVIDED "AS IS", WITHOUT WARRANTY OF ANY KIND, EXPRESS OR
+# IMPLIED, INCLUDING BUT NOT LIMITED TO THE WARRANTIES OF MERCHANTABILITY,
+# FITNESS FOR A PARTICULAR PURPOSE AND NONINFRINGEMENT. IN NO EVENT SHALL THE
+# AUTHORS OR COPYRIGHT HOLDERS BE LIABLE FOR ANY CLAIM, DAMAGES OR OTHER
+# LIABILITY, WHETHER IN AN ACTION OF CONTRACT, TORT OR OTHERWISE, ARISING FROM,
+# OUT OF OR IN CONNECTION WITH THE SOFTWARE OR THE USE OR OTHER DEALINGS IN THE
+# SOFTWARE.
+*/
+
+//! Route guards for per tenant permissions.
+//!
+//! The routes must be behind [`TenancyLayer`](crate::TenancyLayer), which
+//! finds the user's role in the current tenant:
+//!
+//! ```no_run
+//! # use axum::{routing::get, Router};
+//! # use axum_tenancy::{
+//! #     permission::require_permission, tenant_resolver::PathPrefixResolver, Tenancy,
+//! # };
+//! # async fn list() {}
+//! # async fn create() {}
+//! # fn routes(tenancy: &Tenancy) -> Router {
+//! // `/t/acme/invoices` is acme's invoices
+//! let invoices = Router::new()
+//!     .route("/t/:tenant/invoices", get(list).post(create))
+//!     .route_layer(require_permission("invoices:write"))
+//!     .route_layer(tenancy.layer(PathPrefixResolver::new("t")));
+//! # invoices
+//! # }
+//! ```
+//!
+//! Inside a handler use [`TenantPermissions`] instead.
+
+use std::{
+    future::Future,
+    pin::Pin,
+    sync::Arc,
+    task::{Context, Poll},
+};
+
+use axum::{
+    body::Body,
+    extract::FromRequestParts,
+    http::{Request, StatusCode},
+    response::{IntoResponse, Response},
+};
+use tower::{Layer, Service};
+
+use crate::extract::TenantPermissions;
+
+/// Lets the request through only if the user's role in the current tenant
+/// allows `permission`. Rejects with 401 when nobody is logged in and 403
+/// otherwise.
+pub fn require_permission(permission: &str) -> RequirePermissionLayer {
+    RequirePermissionLayer {
+        permission: Arc::from(permission),
+    }
+}
+
+#[derive(Clone)]
+pub struct RequirePermissionLayer {
+    permission: Arc<str>,
+}
+
+impl<S> Layer<S> for RequirePermissionLayer {
+    type Service = RequirePermission<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        RequirePermission {
+            inner,
+            permission: self.permission.clone(),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct RequirePermission<S> {
+    inner: S,
+    permission: Arc<str>,
+}
+
+impl<S> Service<Request<Body>> for RequirePermission<S>
+where
+    S: Service<Request<Body>, Response = Response> + Clone + Send + 'static,
+    S::Future: Send + 'static,
+{
+    type Response = Response;
+    type Error = S::Error;
+    type Future = Pin<Box<dyn Future<Output = Result<Response, S::Error>> + Send>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: Request<Body>) -> Self::Future {
+        // the clone may not be ready, keep the service poll_ready was called on
+        let clone = self.inner.clone();
+        let mut inner = std::mem::replace(&mut self.inner, clone);
+        let permission = self.permission.clone();
+        Box::pin(async move {
+            let (mut parts, body) = req.into_parts();
+            match TenantPermissions::from_request_parts(&mut parts, &()).await {
+                Ok(p) if p.allows(&permission) => {
+                    inner.call(Request::from_parts(parts, body)).await
+                }
+                Ok(_) => Ok(StatusCode::FORBIDDEN.into_response()),
+                Err(status) => Ok(status.into_response()),
+            }
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests_tokio {
+    use axum::{routing::get, Extension, Router};
+    use axum_tenancy_core::admin_core::{
+        role_core::{Role, RolePermissions},
+        user_core::{Deleted, User},
+    };
+    use test_context::test_context;
+    use tower::ServiceExt;
+    use uuid::Uuid;
+
+    use super::*;
+    use crate::{
+        admin::{tenant, test_support::*, user, user_tenant},
+        TenancyLayer,
+    };
+
+    /// Commits a user with `role` in a new tenant, returns the user and the
+    /// tenant name.
+    async fn setup(role: Role) -> (User, String) {
+        let pool = get_committed_test_db_pool().await;
+        let suffix = Uuid::new_v4().simple().to_string();
+        let mut tx = pool.begin().await.unwrap();
+        let user_id = user::insert(
+            &mut tx,
+            &format!("perm{}", suffix),
+            "password",
+            &format!("Permission {}", suffix),
+            false,
+            "",
+            "",
+            &TEST_USER_RULES,
+            &TEST_PASSWORD_POLICY,
+        )
+        .await
+        .unwrap();
+        let tenant_name = format!("perm{}", suffix);
+        let tenant_id = tenant::insert(&mut tx, &tenant_name, &tenant_name)
+            .await
+            .unwrap();
+        user_tenant::insert(&mut tx, &user_id, &tenant_id, &role)
+            .await
+            .unwrap();
+        let u = user::load_by_id(&mut tx, user_id, Deleted::Exclude)
+            .await
+            .unwrap();
+        tx.commit().await.unwrap();
+        (u, tenant_name)
+    }
+
+    async fn role(p: TenantPermissions) -> String {
+        p.role().to_string()
+    }
+
+    async fn app(user: Option<User>) -> Router {
+        let roles = RolePermissions::new().role("accountant", &["invoices:*"]);
+        let layer = TenancyLayer::new(
+            get_committed_test_db_pool().await,
+            |parts: &axum::http::request::Parts| {
+                parts
+                    .headers
+                    .get("x-tenant")
+                    .and_then(|v| v.to_str().ok())
+                    .map(|v| v.to_string())
+            },
+        )
+        .roles(roles);
+        let router = Router::new()
+            .route(
+                "/read",
+                get(role).route_layer(require_permission("invoices:read")),
+            )
+            .route(
+                "/write",
+                get(role).route_layer(require_permission("invoices:write")),
+            )
+            .route(
+                "/delete",
+                get(role).route_layer(require_permission("tenant:delete")),
+            )
+            .route_layer(layer);
+        match user {
+            Some(u) => router.layer(Extension(u)),
+            None => router,
+        }
+    }
+
+    async fn status(user: Option<User>, tenant_name: &str, uri: &str) -> StatusCode {
+        let request = Request::builder()
+            .uri(uri)
+            .header("x-tenant", tenant_name)
+            .body(Body::empty())
+            .unwrap();
+        app(user).await.oneshot(request).await.unwrap().status()
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn roles_grant_permissions(_tenancy_context: &mut TenancyTestContext) {
+        let (viewer, tenant_name) = setup(Role::Viewer).await;
+        assert_eq!(
+            status(Some(viewer.clone()), &tenant_name, "/read").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(Some(viewer), &tenant_name, "/write").await,
+            StatusCode::FORBIDDEN
+        );
+
+        let (accountant, tenant_name) = setup(Role::from("accountant")).await;
+        assert_eq!(
+            status(Some(accountant.clone()), &tenant_name, "/write").await,
+            StatusCode::OK
+        );
+        assert_eq!(
+            status(Some(accountant), &tenant_name, "/delete").await,
+            StatusCode::FORBIDDEN
+        );
+
+        let (owner, tenant_name) = setup(Role::Owner).await;
+        assert_eq!(
+            status(Some(owner), &tenant_name, "/delete").await,
+            StatusCode::OK
+        );
+    }
+
+    #[test_context(TenancyTestContext)]
+    #[tokio::test(flavor = "multi_thread")]
+    async fn role_is_per_tenant(_tenancy_context: &mut TenancyTestContext) {
+        let (u, _) = setup(Role::Owner).await;
+        let (_, other_tenant) = setup(Role::Viewer).await;
+        // a member of one tenant has no role in another
+        assert_eq!(
+            status(Some(u), &other_tenant, "/read").await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status(None, &other_tenant, "/read").await,
+            StatusCode::UNAUTHORIZED
+        );
+    }
+
+    #[tokio::test]
+    async fn needs_the_tenancy_layer() {
+        let router = Router::new()
+            .route("/", get(role))
+            .route_layer(require_permission("invoices:read"))
+            .layer(Extension(User::default()));
+        let request = Request::builder().uri("/").body(Body::empty()).unwrap();
+        let response = router.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+    }
+}
@@ -24,24 +24,35 @@
 
 use std::env;
 
-use axum_tenancy;
+use axum::{routing::get, Router};
+use axum_tenancy::{extract::CurrentTenant, tenant_resolver::PathPrefixResolver, TenancyConfig};
 use dotenvy::dotenv;
-use sqlx::{any::install_default_drivers, postgres::PgPoolOptions, PgPool};
+
+async fn tenant_home(CurrentTenant(tenant): CurrentTenant) -> String {
+    format!("Welcome to {}", tenant.display_name)
+}
 
 #[tokio::main]
 async fn main() {
-    println!("Hello, world!");
     dotenv().expect(".env file not found");
-    let _server_uri: String = env::var("SERVER_URI").expect(".env missing SERVER");
+    let server_uri: String = env::var("SERVER_URI").expect(".env missing SERVER");
     let database_url: String = env::var("DATABASE_PARTIAL_URL").expect(".env missing DATABASE_URL");
     let database_pw: String = env::var("DATABASE_PW").expect(".env missing DATABASE_PW");
-    //install_default_drivers();
-    let pool_options = PgPoolOptions::new();
-    let uri = format!("{}={}", database_url, database_pw);
-    println!("uri:{}", &uri);
-    //let pool: Pool<sqlx::PgPool> = pool_options.connect(&uri).await.unwrap();
-    let pool = pool_options.connect(&uri).await.unwrap();
-    println!("pool created");
-    let _ = axum_tenancy::initialize(&pool).await;
-    println!("init donei");
+    let config = TenancyConfig::from_env()
+        .expect("Invalid axum-tenancy settings")
+        .database_url(&format!("{}={}", database_url, database_pw));
+    let tenancy = axum_tenancy::connect(config)
+        .await
+        .expect("Could not start axum-tenancy");
+
+    // needs a login layer in front putting the User into the extensions,
+    // without one every request gets a 401
+    let app = Router::new()
+        .route("/t/:tenant", get(tenant_home))
+        .route_layer(tenancy.layer(PathPrefixResolver::new("t")));
+
+    let listener = tokio::net::TcpListener::bind(&server_uri)
+        .await
+        .expect("Could not bind SERVER_URI");
+    axum::serve(listener, app).await.unwrap();
 }
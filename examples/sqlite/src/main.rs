@@ -24,21 +24,31 @@
 
 use std::env;
 
-use axum_tenancy;
+use axum::{routing::get, Router};
+use axum_tenancy::{extract::CurrentTenant, tenant_resolver::PathPrefixResolver, TenancyConfig};
 use dotenvy::dotenv;
-use sqlx::{
-    any::{install_default_drivers, AnyPoolOptions},
-    Pool,
-};
+
+async fn tenant_home(CurrentTenant(tenant): CurrentTenant) -> String {
+    format!("Welcome to {}", tenant.display_name)
+}
 
 #[tokio::main]
 async fn main() {
-    println!("Hello, world!");
     dotenv().expect(".env file not found");
-    let _server_uri: String = env::var("SERVER_URI").expect(".env missing SERVER");
-    let database_url: String = env::var("DATABASE_URL").expect(".env missing DATABASE_URL");
-    install_default_drivers();
-    let pool_options = AnyPoolOptions::new();
-    let pool: Pool<sqlx::Any> = pool_options.connect(&database_url).await.unwrap();
-    let _ = axum_tenancy::initialize(&pool).await;
+    let server_uri: String = env::var("SERVER_URI").expect(".env missing SERVER");
+    let config = TenancyConfig::from_env().expect("Invalid axum-tenancy settings");
+    let tenancy = axum_tenancy::connect(config)
+        .await
+        .expect("Could not start axum-tenancy");
+
+    // needs a login layer in front putting the User into the extensions,
+    // without one every request gets a 401
+    let app = Router::new()
+        .route("/t/:tenant", get(tenant_home))
+        .route_layer(tenancy.layer(PathPrefixResolver::new("t")));
+
+    let listener = tokio::net::TcpListener::bind(&server_uri)
+        .await
+        .expect("Could not bind SERVER_URI");
+    axum::serve(listener, app).await.unwrap();
 }